@@ -0,0 +1,557 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Take};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+
+use super::dir_fd::{DirFd, Stat};
+use super::failures::quote;
+use super::logger::{self, StatusLogger};
+use super::paths;
+use super::remove_action::RemoveAction;
+
+mod tar;
+mod zip;
+
+/// An archive member, as stored by an ArchiveWriter.
+struct Entry {
+    /// path inside the archive
+    name: Vec<u8>,
+    kind: Kind,
+    /// permission bits
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: i64,
+}
+
+#[derive(Clone, PartialEq)]
+enum Kind {
+    File,
+    Directory,
+    Symlink(Vec<u8>),
+}
+
+/// An archive format.
+trait ArchiveWriter {
+    /// Add entry, a file reads exactly entry.size
+    /// bytes from content.
+    fn add(&mut self, entry: &Entry, content: &mut dyn Read) -> Result<()>;
+
+    /// Complete the archive and flush it.
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Read exactly the size written in the entry header:
+/// a file that shrank while being archived fails, one
+/// that grew is cut and then kept by Pending::remove.
+struct Exact<'a> {
+    content: Take<&'a mut File>,
+    path: &'a Path,
+}
+
+impl Read for Exact<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let count = self.content.read(buf)?;
+        if count == 0 && !buf.is_empty() && self.content.limit() > 0 {
+            let msg = format!("{} shrank while being archived", quote(self.path));
+            return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+        }
+        Ok(count)
+    }
+}
+
+/// An archived entry, deleted once the archive is
+/// complete only if it is still what was archived.
+struct Pending {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+    /// size and modification time, not checked for
+    /// a directory: rmdir fails if it is not empty
+    content: Option<(u64, (i64, i64))>,
+}
+
+impl Pending {
+    fn new(path: &Path, meta: &fs::Metadata) -> Self {
+        let content = if meta.is_dir() {
+            None
+        } else {
+            Some((meta.len(), (meta.mtime(), meta.mtime_nsec())))
+        };
+        Self {
+            path: path.to_path_buf(),
+            dev: meta.dev(),
+            ino: meta.ino(),
+            content,
+        }
+    }
+
+    fn matches(&self, stat: &Stat) -> bool {
+        let content = if stat.is_dir() {
+            None
+        } else {
+            Some((stat.len(), stat.modified()))
+        };
+        (stat.dev(), stat.ino()) == (self.dev, self.ino) && content == self.content
+    }
+
+    fn remove(&self) -> Result<()> {
+        let (dir, name) = DirFd::open_parent(&self.path)?;
+        if !self.matches(&dir.metadata(&name)?) {
+            let msg = format!(
+                "{} changed after being archived, it is not removed",
+                quote(&self.path)
+            );
+            return Err(Error::other(msg));
+        }
+        if self.content.is_none() {
+            dir.remove_dir(&name)
+        } else {
+            dir.remove_file(&name)
+        }
+    }
+}
+
+/// Add entries to an archive, keeping their path relative
+/// to the root being walked, or just their name outside of
+/// a walk. Nothing is deleted until finish has written the
+/// archive and synced it to disk, and then only entries
+/// still as they were archived. The format is chosen by
+/// extension: `.zip`, `.tar.zst` or `.tar`.
+pub struct ArchiveTo {
+    path: PathBuf,
+    partial: PathBuf,
+    /// device and inode of the partial archive,
+    /// that is never added to itself
+    identity: (u64, u64),
+    writer: Option<Box<dyn ArchiveWriter>>,
+    root: Option<PathBuf>,
+    members: Vec<(PathBuf, PathBuf)>,
+    /// entries to delete, children before their directory
+    pending: Vec<Pending>,
+}
+
+impl ArchiveTo {
+    /// Start writing a new archive at path, which must not exist.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = paths::absolute(path.as_ref())?;
+        if fs::symlink_metadata(&path).is_ok() {
+            let msg = format!("archive {} already exists", quote(&path));
+            return Err(Error::new(ErrorKind::AlreadyExists, msg));
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = name.to_lowercase();
+        if ![".zip", ".tar.zst", ".tzst", ".tar"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+        {
+            let msg = format!(
+                "{}: archive must end in .tar.zst, .zip or .tar",
+                quote(&path)
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&partial)?;
+        let meta = file.metadata()?;
+        let identity = (meta.dev(), meta.ino());
+
+        let writer: Box<dyn ArchiveWriter> = if name.ends_with(".zip") {
+            Box::new(zip::ZipWriter::new(file))
+        } else if name.ends_with(".tar") {
+            Box::new(tar::TarWriter::new(file))
+        } else {
+            match tar::TarWriter::compressed(file) {
+                Ok(writer) => Box::new(writer),
+                Err(err) => {
+                    let _ = fs::remove_file(&partial);
+                    return Err(err);
+                }
+            }
+        };
+
+        Ok(Self {
+            path,
+            partial,
+            identity,
+            writer: Some(writer),
+            root: None,
+            members: Vec::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Where path goes inside the archive.
+    fn member_name(&self, path: &Path) -> PathBuf {
+        let relative = self
+            .root
+            .as_ref()
+            .and_then(|root| path.strip_prefix(root).ok())
+            .filter(|relative| !relative.as_os_str().is_empty());
+        match relative {
+            Some(relative) => relative
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect(),
+            None => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+        }
+    }
+
+    fn is_partial(&self, meta: &fs::Metadata) -> bool {
+        (meta.dev(), meta.ino()) == self.identity
+    }
+
+    /// The archive cannot be written inside a directory
+    /// that is going to be removed.
+    fn check_outside(&self, path: &Path) -> Result<()> {
+        if self.path.starts_with(paths::absolute(path)?) {
+            let msg = format!("cannot remove {}: it contains the archive", quote(path));
+            Err(Error::new(ErrorKind::InvalidInput, msg))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Add an entry, returning the status of what was
+    /// actually archived.
+    fn add(
+        &mut self,
+        dir: Option<&DirFd>,
+        name: &OsStr,
+        path: &Path,
+        member: &Path,
+    ) -> Result<fs::Metadata> {
+        let meta = fs::symlink_metadata(path)?;
+        let file_type = meta.file_type();
+        let mut file = None;
+        let (kind, meta) = if file_type.is_file() {
+            // read through the descriptor, stat the file actually opened
+            let opened = match dir {
+                Some(dir) => dir.open_file(name)?,
+                None => OpenOptions::new()
+                    .read(true)
+                    .custom_flags(libc::O_NOFOLLOW)
+                    .open(path)?,
+            };
+            let meta = opened.metadata()?;
+            file = Some(opened);
+            (Kind::File, meta)
+        } else if file_type.is_dir() {
+            (Kind::Directory, meta)
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(path)?;
+            (Kind::Symlink(target.as_os_str().as_bytes().to_vec()), meta)
+        } else {
+            let msg = format!(
+                "cannot archive {}: not a file, directory or link",
+                quote(path)
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        };
+
+        let entry = Entry {
+            name: member.as_os_str().as_bytes().to_vec(),
+            size: if kind == Kind::File { meta.len() } else { 0 },
+            kind,
+            mode: meta.mode() & 0o7777,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            mtime: meta.mtime(),
+        };
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| Error::other("archive already finished"))?;
+        match file.as_mut() {
+            Some(file) => {
+                let mut content = Exact {
+                    content: file.take(entry.size),
+                    path,
+                };
+                writer.add(&entry, &mut content)?
+            }
+            None => writer.add(&entry, &mut std::io::empty())?,
+        }
+        self.members
+            .push((path.to_path_buf(), member.to_path_buf()));
+        Ok(meta)
+    }
+
+    /// Add path and, for a directory, all its content.
+    fn add_tree(&mut self, path: &Path, member: &Path) -> Result<()> {
+        let meta = fs::symlink_metadata(path)?;
+        if self.is_partial(&meta) {
+            return Ok(());
+        }
+        let meta = self.add(None, path.as_os_str(), path, member)?;
+        if meta.is_dir() {
+            let mut names: Vec<OsString> = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<Result<_>>()?;
+            names.sort();
+            for name in names {
+                self.add_tree(&path.join(&name), &member.join(&name))?;
+            }
+        }
+        self.pending.push(Pending::new(path, &meta));
+        Ok(())
+    }
+
+    /// Complete the archive, sync the data and
+    /// move the archive to its final name.
+    fn write_archive(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        File::open(&self.partial)?.sync_all()?;
+        // link does not replace an archive created meanwhile
+        fs::hard_link(&self.partial, &self.path)?;
+        fs::remove_file(&self.partial)?;
+        if let Some(parent) = self.path.parent() {
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl RemoveAction for ArchiveTo {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        if dir.metadata(name)?.is_dir() {
+            return Err(Error::from_raw_os_error(libc::EISDIR));
+        }
+        if self.is_partial(&fs::symlink_metadata(path)?) {
+            return Ok(());
+        }
+        let member = self.member_name(path);
+        let meta = self.add(Some(dir), name, path, &member)?;
+        self.pending.push(Pending::new(path, &meta));
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, _dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.check_outside(path)?;
+        let member = self.member_name(path);
+        let meta = self.add(None, name, path, &member)?;
+        self.pending.push(Pending::new(path, &meta));
+        Ok(())
+    }
+
+    fn remove_dir_all(&mut self, _dir: &DirFd, _name: &OsStr, path: &Path) -> Result<()> {
+        self.check_outside(path)?;
+        let member = self.member_name(path);
+        self.add_tree(path, &member)
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<()> {
+        self.root = Some(root.to_path_buf());
+        Ok(())
+    }
+
+    /// Write the archive, then delete the archived entries
+    /// that did not change meanwhile. An empty archive is
+    /// not kept.
+    fn finish(&mut self, log: &mut Option<StatusLogger>) -> Result<()> {
+        if self.members.is_empty() {
+            self.writer = None;
+            return fs::remove_file(&self.partial);
+        }
+        self.write_archive()?;
+        for (path, member) in &self.members {
+            logger::add_archive_log(log, path, member, &self.path);
+        }
+        self.members.clear();
+        // a changed entry is kept, the others are still removed
+        let mut result = Ok(());
+        for pending in self.pending.drain(..) {
+            if let Err(err) = pending.remove() {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// An archive never finished is discarded,
+/// leaving every entry in place.
+impl Drop for ArchiveTo {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.partial);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{create_dir_all, read_to_string};
+    use tempfile::TempDir;
+
+    /// Type and path of each member of a tar archive.
+    fn list_tar(path: &Path) -> Vec<(::tar::EntryType, PathBuf)> {
+        let mut archive = ::tar::Archive::new(File::open(path).unwrap());
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (
+                    entry.header().entry_type(),
+                    entry.path().unwrap().into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_to() {
+        let root = TempDir::new().unwrap();
+        let scan = root.path().join("scan");
+        create_dir_all(scan.join("a")).unwrap();
+        let file = scan.join("a").join("file");
+        fs::write(&file, "content").unwrap();
+        let archive = root.path().join("out.tar");
+
+        let mut action = ArchiveTo::new(&archive).unwrap();
+        // a concurrent run cannot write the same archive
+        assert!(ArchiveTo::new(&archive).is_err());
+        action.enter_root(&scan).unwrap();
+        let dir = DirFd::open(scan.join("a")).unwrap();
+        action.remove_file(&dir, OsStr::new("file"), &file).unwrap();
+        let dir = DirFd::open(&scan).unwrap();
+        let path = scan.join("a");
+        action.remove_dir(&dir, OsStr::new("a"), &path).unwrap();
+
+        // nothing is removed before the archive is complete
+        assert_eq!(read_to_string(&file).unwrap(), "content");
+        assert!(!archive.exists());
+        action.finish(&mut None).unwrap();
+        assert!(!path.exists());
+        assert!(archive.exists());
+        assert!(!root.path().join("out.tar.partial").exists());
+        assert!(ArchiveTo::new(&archive).is_err());
+
+        assert_eq!(
+            list_tar(&archive),
+            vec![
+                (::tar::EntryType::Regular, PathBuf::from("a/file")),
+                (::tar::EntryType::Directory, PathBuf::from("a")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unfinished_archive() {
+        let root = TempDir::new().unwrap();
+        let file = root.path().join("file");
+        fs::write(&file, "content").unwrap();
+        let archive = root.path().join("out.zip");
+
+        let mut action = ArchiveTo::new(&archive).unwrap();
+        let (dir, name) = DirFd::open_parent(&file).unwrap();
+        action.remove_file(&dir, &name, &file).unwrap();
+        drop(action);
+        assert!(file.exists());
+        assert!(!archive.exists());
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 1);
+
+        // empty archives are not kept
+        let mut action = ArchiveTo::new(&archive).unwrap();
+        action.finish(&mut None).unwrap();
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 1);
+
+        assert!(ArchiveTo::new(root.path().join("out.rar")).is_err());
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_archive_tree() {
+        let root = TempDir::new().unwrap();
+        let tree = root.path().join("tree");
+        create_dir_all(tree.join("sub")).unwrap();
+        fs::write(tree.join("sub").join("file"), "content").unwrap();
+        std::os::unix::fs::symlink("sub/file", tree.join("link")).unwrap();
+        let archive = tree.join("out.tar");
+
+        // the tree cannot be removed while it holds the archive
+        let mut action = ArchiveTo::new(&archive).unwrap();
+        let (dir, name) = DirFd::open_parent(&tree).unwrap();
+        assert!(action.remove_dir_all(&dir, &name, &tree).is_err());
+        drop(action);
+
+        let archive = root.path().join("out.tar");
+        let mut action = ArchiveTo::new(&archive).unwrap();
+        action.remove_dir_all(&dir, &name, &tree).unwrap();
+        action.finish(&mut None).unwrap();
+        assert!(!tree.exists());
+
+        let listing = list_tar(&archive);
+        assert_eq!(listing.len(), 4);
+        assert!(listing.contains(&(::tar::EntryType::Symlink, PathBuf::from("tree/link"))));
+        assert!(listing.contains(&(::tar::EntryType::Regular, PathBuf::from("tree/sub/file"))));
+    }
+
+    #[test]
+    fn test_changed_after_archiving() {
+        let root = TempDir::new().unwrap();
+        let tree = root.path().join("tree");
+        create_dir_all(&tree).unwrap();
+        let changed = tree.join("changed");
+        let same = tree.join("same");
+        fs::write(&changed, "content").unwrap();
+        fs::write(&same, "content").unwrap();
+        let archive = root.path().join("out.tar.zst");
+
+        let mut action = ArchiveTo::new(&archive).unwrap();
+        let dir = DirFd::open(&tree).unwrap();
+        action
+            .remove_file(&dir, OsStr::new("changed"), &changed)
+            .unwrap();
+        action.remove_file(&dir, OsStr::new("same"), &same).unwrap();
+        let (parent, name) = DirFd::open_parent(&tree).unwrap();
+        action.remove_dir(&parent, &name, &tree).unwrap();
+        // the archive cannot hold its own directory
+        let (parent, name) = DirFd::open_parent(root.path()).unwrap();
+        assert!(action.remove_dir(&parent, &name, root.path()).is_err());
+
+        fs::write(&changed, "new content").unwrap();
+        let err = action.finish(&mut None).unwrap_err();
+        assert!(err.to_string().contains("changed after being archived"));
+        assert_eq!(read_to_string(&changed).unwrap(), "new content");
+        assert!(!same.exists());
+        assert!(tree.exists());
+        assert!(archive.exists());
+    }
+
+    #[test]
+    fn test_shrank_while_archiving() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("file");
+        fs::write(&path, "abc").unwrap();
+        let read = |size| {
+            let mut file = File::open(&path).unwrap();
+            let mut content = Exact {
+                content: Read::take(&mut file, size),
+                path: &path,
+            };
+            let mut buffer = Vec::new();
+            content.read_to_end(&mut buffer).map(|_| buffer)
+        };
+        assert_eq!(read(2).unwrap(), b"ab");
+        assert_eq!(read(3).unwrap(), b"abc");
+        let err = read(5).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
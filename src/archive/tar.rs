@@ -0,0 +1,183 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{empty, BufWriter, Read, Result, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use tar::{Builder, EntryType, Header};
+use zstd::stream::write::Encoder;
+
+use super::{ArchiveWriter, Entry, Kind};
+
+/// Where the tar stream goes.
+enum Output {
+    Plain(BufWriter<File>),
+    Zstd(Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    /// Write what is still buffered, ending
+    /// the compressed frame if any.
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Plain(mut output) => output.flush(),
+            Self::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self {
+            Self::Plain(output) => output.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Self::Plain(output) => output.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Write entries as a tar archive, using GNU headers
+/// for long names and values that do not fit in the
+/// ustar fields, optionally compressed with zstd.
+pub struct TarWriter {
+    builder: Builder<Output>,
+}
+
+impl TarWriter {
+    pub fn new(output: File) -> Self {
+        Self::with_output(Output::Plain(BufWriter::new(output)))
+    }
+
+    pub fn compressed(output: File) -> Result<Self> {
+        // level 0 is zstd's default level
+        let encoder = Encoder::new(BufWriter::new(output), 0)?;
+        Ok(Self::with_output(Output::Zstd(encoder)))
+    }
+
+    fn with_output(output: Output) -> Self {
+        Self {
+            builder: Builder::new(output),
+        }
+    }
+}
+
+impl ArchiveWriter for TarWriter {
+    fn add(&mut self, entry: &Entry, content: &mut dyn Read) -> Result<()> {
+        let path = Path::new(OsStr::from_bytes(&entry.name));
+        let mut header = Header::new_gnu();
+        header.set_mode(entry.mode);
+        header.set_uid(entry.uid.into());
+        header.set_gid(entry.gid.into());
+        header.set_mtime(entry.mtime.max(0) as u64);
+        match &entry.kind {
+            Kind::File => {
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(entry.size);
+                self.builder.append_data(&mut header, path, content)
+            }
+            Kind::Directory => {
+                header.set_entry_type(EntryType::Directory);
+                header.set_size(0);
+                self.builder.append_data(&mut header, path, empty())
+            }
+            Kind::Symlink(target) => {
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                let target = Path::new(OsStr::from_bytes(target));
+                self.builder.append_link(&mut header, path, target)
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.builder.into_inner()?.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    fn entry(name: &[u8], kind: Kind, size: u64) -> Entry {
+        Entry {
+            name: name.to_vec(),
+            kind,
+            mode: 0o640,
+            uid: 3_000_000,
+            gid: 100,
+            size,
+            mtime: 1_600_000_000,
+        }
+    }
+
+    fn write_archive(path: &Path, compressed: bool) {
+        let file = File::create(path).unwrap();
+        let mut writer = if compressed {
+            TarWriter::compressed(file).unwrap()
+        } else {
+            TarWriter::new(file)
+        };
+        let long = [b'n'; 150];
+        writer
+            .add(&entry(b"dir", Kind::Directory, 0), &mut empty())
+            .unwrap();
+        writer
+            .add(&entry(&long, Kind::File, 7), &mut Cursor::new("content"))
+            .unwrap();
+        let link = Kind::Symlink(long.to_vec());
+        writer
+            .add(&entry(b"dir/link", link, 0), &mut empty())
+            .unwrap();
+        Box::new(writer).finish().unwrap();
+    }
+
+    fn check_archive(input: impl Read) {
+        let mut archive = tar::Archive::new(input);
+        let mut entries = archive.entries().unwrap();
+
+        let dir = entries.next().unwrap().unwrap();
+        assert_eq!(dir.header().entry_type(), EntryType::Directory);
+        assert_eq!(dir.path().unwrap(), Path::new("dir"));
+
+        let mut file = entries.next().unwrap().unwrap();
+        let name = String::from_utf8(vec![b'n'; 150]).unwrap();
+        assert_eq!(file.path().unwrap(), Path::new(&name));
+        assert_eq!(file.header().mode().unwrap(), 0o640);
+        assert_eq!(file.header().uid().unwrap(), 3_000_000);
+        assert_eq!(file.header().mtime().unwrap(), 1_600_000_000);
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "content");
+
+        let link = entries.next().unwrap().unwrap();
+        assert_eq!(link.header().entry_type(), EntryType::Symlink);
+        assert_eq!(link.link_name().unwrap().unwrap(), Path::new(&name));
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_tar() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("out.tar");
+        write_archive(&path, false);
+        check_archive(File::open(&path).unwrap());
+    }
+
+    #[test]
+    fn test_tar_zst() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("out.tar.zst");
+        write_archive(&path, true);
+        let input = zstd::stream::read::Decoder::new(File::open(&path).unwrap()).unwrap();
+        check_archive(input);
+    }
+}
@@ -0,0 +1,138 @@
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{copy, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use chrono::{Local, TimeZone};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime};
+
+use super::{ArchiveWriter, Entry, Kind};
+use crate::failures::quote;
+
+/// Write entries as a zip archive: files are deflated,
+/// the permissions and the modification time, in local
+/// time, are kept. Zip64 is used for large files.
+pub struct ZipWriter {
+    zip: zip::ZipWriter<BufWriter<File>>,
+}
+
+impl ZipWriter {
+    pub fn new(output: File) -> Self {
+        Self {
+            zip: zip::ZipWriter::new(BufWriter::new(output)),
+        }
+    }
+}
+
+impl ArchiveWriter for ZipWriter {
+    fn add(&mut self, entry: &Entry, content: &mut dyn Read) -> Result<()> {
+        let name = utf8(&entry.name, &entry.name)?;
+        let options = SimpleFileOptions::default()
+            .unix_permissions(entry.mode)
+            .last_modified_time(dos_time(entry.mtime));
+        match &entry.kind {
+            Kind::File => {
+                let options = options
+                    .compression_method(CompressionMethod::Deflated)
+                    .large_file(entry.size >= u64::from(u32::MAX));
+                self.zip.start_file(name, options)?;
+                copy(content, &mut self.zip)?;
+            }
+            Kind::Directory => self.zip.add_directory(name, options)?,
+            Kind::Symlink(target) => {
+                let target = utf8(target, &entry.name)?;
+                self.zip.add_symlink(name, target, options)?
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.zip.finish()?.flush()
+    }
+}
+
+/// Zip names are UTF-8: a name made of other bytes
+/// would not be restored as it was.
+fn utf8<'a>(bytes: &'a [u8], name: &[u8]) -> Result<&'a str> {
+    std::str::from_utf8(bytes).map_err(|_| {
+        let msg = format!(
+            "cannot archive {} in a zip: name is not valid UTF-8",
+            quote(Path::new(OsStr::from_bytes(name)))
+        );
+        Error::new(ErrorKind::InvalidInput, msg)
+    })
+}
+
+/// DOS time counts local time from 1980, earlier
+/// times are clamped to its start.
+fn dos_time(mtime: i64) -> DateTime {
+    Local
+        .timestamp_opt(mtime, 0)
+        .single()
+        .and_then(|time| DateTime::try_from(time.naive_local()).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::io::{empty, Cursor};
+    use tempfile::TempDir;
+
+    fn entry(name: &[u8], kind: Kind, size: u64) -> Entry {
+        Entry {
+            name: name.to_vec(),
+            kind,
+            mode: 0o640,
+            uid: 0,
+            gid: 0,
+            size,
+            mtime: 1_600_000_000,
+        }
+    }
+
+    #[test]
+    fn test_zip() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("out.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        writer
+            .add(&entry(b"dir", Kind::Directory, 0), &mut empty())
+            .unwrap();
+        let content = "content ".repeat(100);
+        let file = entry(b"dir/file", Kind::File, content.len() as u64);
+        writer.add(&file, &mut Cursor::new(&content)).unwrap();
+        let link = Kind::Symlink(b"file".to_vec());
+        writer
+            .add(&entry(b"dir/link", link, 0), &mut empty())
+            .unwrap();
+        let invalid = entry(b"caf\xe9", Kind::File, 0);
+        assert!(writer.add(&invalid, &mut empty()).is_err());
+        Box::new(writer).finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.len(), 3);
+        assert!(archive.by_name("dir/").unwrap().is_dir());
+        let mut file = archive.by_name("dir/file").unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Deflated);
+        assert!(file.compressed_size() < content.len() as u64);
+        assert_eq!(file.unix_mode().unwrap() & 0o777, 0o640);
+        let mut read = String::new();
+        file.read_to_string(&mut read).unwrap();
+        assert_eq!(read, content);
+        drop(file);
+        assert!(archive.by_name("dir/link").unwrap().is_symlink());
+    }
+
+    #[test]
+    fn test_dos_time() {
+        let time = dos_time(1_600_000_000);
+        assert_eq!(time.year(), 2020);
+        assert_eq!(dos_time(0), DateTime::default());
+    }
+}
@@ -0,0 +1,616 @@
+extern crate libc;
+
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs::{File, Metadata};
+use std::io::{Error, ErrorKind, Result};
+use std::os::raw::c_int;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::Path;
+
+use super::failures::quote;
+
+/// A directory opened as a file descriptor.
+/// Every operation is made relative to this
+/// descriptor using the *at system calls and
+/// never follows symbolic links: once a directory
+/// is open it cannot be swapped for a symlink
+/// between a check and the following removal.
+pub struct DirFd {
+    fd: RawFd,
+}
+
+/// File status as returned by fstatat, symbolic
+/// links are never followed.
+pub struct Stat {
+    stat: libc::stat,
+}
+
+impl Stat {
+    pub fn is_dir(&self) -> bool {
+        self.stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.stat.st_mode & libc::S_IFMT == libc::S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.stat.st_mode & libc::S_IFMT == libc::S_IFLNK
+    }
+
+    /// The kind of file, named like rm does.
+    pub fn kind_name(&self) -> &'static str {
+        match self.stat.st_mode & libc::S_IFMT {
+            libc::S_IFREG if self.is_empty() => "regular empty file",
+            libc::S_IFREG => "regular file",
+            libc::S_IFDIR => "directory",
+            libc::S_IFLNK => "symbolic link",
+            libc::S_IFIFO => "fifo",
+            libc::S_IFSOCK => "socket",
+            libc::S_IFCHR => "character special file",
+            libc::S_IFBLK => "block special file",
+            _ => "weird file",
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.stat.st_size as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The device holding the file.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn dev(&self) -> u64 {
+        self.stat.st_dev as u64
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn ino(&self) -> u64 {
+        self.stat.st_ino as u64
+    }
+
+    /// Whether the sticky bit is set: in a directory only
+    /// the owner of an entry may remove or rename it.
+    pub fn is_sticky(&self) -> bool {
+        self.stat.st_mode & libc::S_ISVTX != 0
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.stat.st_uid
+    }
+
+    /// Permission bits, without the file type.
+    pub fn permissions(&self) -> libc::mode_t {
+        self.stat.st_mode & 0o7777
+    }
+
+    /// Number of hard links to the file.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn links(&self) -> u64 {
+        self.stat.st_nlink as u64
+    }
+
+    /// Modification time, seconds and nanoseconds.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn modified(&self) -> (i64, i64) {
+        (self.stat.st_mtime as i64, self.stat.st_mtime_nsec as i64)
+    }
+}
+
+impl DirFd {
+    /// Open the directory at path. This is the
+    /// only place where a symbolic link is followed:
+    /// the user explicitly named this directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = to_cstring(path.as_ref().as_os_str())?;
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        let fd = cvt(unsafe { libc::open(path.as_ptr(), flags) })?;
+        Ok(Self { fd })
+    }
+
+    /// Open the directory containing path and
+    /// return it together with the name of the
+    /// last path component. Refuse to work on
+    /// `.` and `..` like rm does.
+    pub fn open_parent<P: AsRef<Path>>(path: P) -> Result<(Self, OsString)> {
+        let path = path.as_ref().as_os_str().as_bytes();
+        let mut end = path.len();
+        while end > 1 && path[end - 1] == b'/' {
+            end -= 1;
+        }
+        let path = &path[..end];
+        let (parent, name) = match path.iter().rposition(|c| *c == b'/') {
+            Some(0) => (&b"/"[..], &path[1..]),
+            Some(index) => (&path[..index], &path[index + 1..]),
+            None => (&b"."[..], path),
+        };
+
+        if name.is_empty() || name == b"." || name == b".." {
+            let msg = format!(
+                "refusing to remove {}",
+                quote(Path::new(OsStr::from_bytes(path)))
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+
+        let dir = Self::open(OsStr::from_bytes(parent))?;
+        Ok((dir, OsStr::from_bytes(name).to_os_string()))
+    }
+
+    /// Open the sub directory name, fails if name
+    /// is a symbolic link.
+    pub fn open_dir(&self, name: &OsStr) -> Result<Self> {
+        let name = to_cstring(name)?;
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = cvt(unsafe { libc::openat(self.fd, name.as_ptr(), flags) })?;
+        Ok(Self { fd })
+    }
+
+    /// Open the file name for reading, fails
+    /// if name is a symbolic link.
+    pub fn open_file(&self, name: &OsStr) -> Result<File> {
+        self.open_at(name, libc::O_RDONLY)
+    }
+
+    /// Open the file name for writing, without
+    /// truncating it. Fails if name is a symbolic link.
+    pub fn open_file_write(&self, name: &OsStr) -> Result<File> {
+        self.open_at(name, libc::O_WRONLY)
+    }
+
+    /// Create the file name for writing with the given
+    /// permissions, fails if name already exists, even
+    /// as a symbolic link.
+    pub fn create_file(&self, name: &OsStr, mode: u32) -> Result<File> {
+        let name = to_cstring(name)?;
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC;
+        let fd = cvt(unsafe { libc::openat(self.fd, name.as_ptr(), flags, mode as libc::c_uint) })?;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Create the directory name with the given permissions,
+    /// fails if name already exists, even as a symbolic link.
+    pub fn create_dir(&self, name: &OsStr, mode: u32) -> Result<()> {
+        let name = to_cstring(name)?;
+        cvt(unsafe { libc::mkdirat(self.fd, name.as_ptr(), mode as libc::mode_t) })?;
+        Ok(())
+    }
+
+    /// The target of the symbolic link name.
+    pub fn read_link(&self, name: &OsStr) -> Result<OsString> {
+        let name = to_cstring(name)?;
+        let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+        let len = unsafe {
+            libc::readlinkat(
+                self.fd,
+                name.as_ptr(),
+                buffer.as_mut_ptr() as *mut libc::c_char,
+                buffer.len(),
+            )
+        };
+        if len == -1 {
+            return Err(Error::last_os_error());
+        }
+        buffer.truncate(len as usize);
+        Ok(OsString::from_vec(buffer))
+    }
+
+    /// Create the symbolic link name pointing to target.
+    pub fn symlink(&self, target: &OsStr, name: &OsStr) -> Result<()> {
+        let target = to_cstring(target)?;
+        let name = to_cstring(name)?;
+        cvt(unsafe { libc::symlinkat(target.as_ptr(), self.fd, name.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Give name the permissions and the access and
+    /// modification times of stat. The permissions of
+    /// a symbolic link are left alone.
+    pub fn copy_status(&self, name: &OsStr, stat: &Stat) -> Result<()> {
+        if !self.metadata(name)?.is_symlink() {
+            self.set_permissions(name, stat.permissions())?;
+        }
+        let accessed = (stat.stat.st_atime, stat.stat.st_atime_nsec);
+        let modified = (stat.stat.st_mtime, stat.stat.st_mtime_nsec);
+        self.set_times(name, Some(accessed), modified)
+    }
+
+    /// Change the permissions of name, which
+    /// must not be a symbolic link.
+    pub fn set_permissions(&self, name: &OsStr, mode: libc::mode_t) -> Result<()> {
+        let name = to_cstring(name)?;
+        cvt(unsafe { libc::fchmodat(self.fd, name.as_ptr(), mode, 0) })?;
+        Ok(())
+    }
+
+    /// Change the owner and group of name,
+    /// symbolic links are not followed.
+    pub fn set_owner(&self, name: &OsStr, uid: u32, gid: u32) -> Result<()> {
+        let name = to_cstring(name)?;
+        cvt(unsafe {
+            libc::fchownat(self.fd, name.as_ptr(), uid, gid, libc::AT_SYMLINK_NOFOLLOW)
+        })?;
+        Ok(())
+    }
+
+    /// Change the access time, unless None, and the modification
+    /// time of name, each as seconds and nanoseconds since the
+    /// epoch. Symbolic links are not followed.
+    pub fn set_times(
+        &self,
+        name: &OsStr,
+        accessed: Option<(libc::time_t, libc::c_long)>,
+        modified: (libc::time_t, libc::c_long),
+    ) -> Result<()> {
+        let name = to_cstring(name)?;
+        let (atime, atime_nsec) = accessed.unwrap_or((0, libc::UTIME_OMIT));
+        let times = [
+            libc::timespec {
+                tv_sec: atime,
+                tv_nsec: atime_nsec,
+            },
+            libc::timespec {
+                tv_sec: modified.0,
+                tv_nsec: modified.1,
+            },
+        ];
+        cvt(unsafe {
+            libc::utimensat(
+                self.fd,
+                name.as_ptr(),
+                times.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// The status of name as std reports it, birth
+    /// time included. Symbolic links are not followed.
+    pub fn file_metadata(&self, name: &OsStr) -> Result<Metadata> {
+        self.open_at(name, O_STAT)?.metadata()
+    }
+
+    /// The status of this directory.
+    pub fn status(&self) -> Result<Stat> {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        cvt(unsafe { libc::fstat(self.fd, &mut stat) })?;
+        Ok(Stat { stat })
+    }
+
+    pub fn metadata(&self, name: &OsStr) -> Result<Stat> {
+        let name = to_cstring(name)?;
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        cvt(unsafe {
+            libc::fstatat(self.fd, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW)
+        })?;
+        Ok(Stat { stat })
+    }
+
+    /// Whether the effective user may write name, which
+    /// permissions or a read-only filesystem prevent.
+    pub fn is_writable(&self, name: &OsStr) -> Result<bool> {
+        let name = to_cstring(name)?;
+        let result =
+            cvt(unsafe { libc::faccessat(self.fd, name.as_ptr(), libc::W_OK, libc::AT_EACCESS) });
+        match result {
+            Ok(_) => Ok(true),
+            Err(err) if matches!(err.raw_os_error(), Some(libc::EACCES) | Some(libc::EROFS)) => {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether the regular file or directory name has the
+    /// immutable or append-only attribute, that even root
+    /// must clear before removing it. Filesystems without
+    /// attributes never have them.
+    #[cfg(target_os = "linux")]
+    pub fn is_immutable(&self, name: &OsStr) -> Result<bool> {
+        use std::os::unix::io::AsRawFd;
+
+        const FS_IMMUTABLE_FL: c_int = 0x10;
+        const FS_APPEND_FL: c_int = 0x20;
+
+        let file = match self.open_at(name, libc::O_RDONLY | libc::O_NONBLOCK | libc::O_NOCTTY) {
+            Err(err) if err.raw_os_error() == Some(libc::EACCES) => return Ok(false),
+            file => file?,
+        };
+        let mut flags: c_int = 0;
+        match cvt(unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) }) {
+            Ok(_) => Ok(flags & (FS_IMMUTABLE_FL | FS_APPEND_FL) != 0),
+            Err(err)
+                if matches!(
+                    err.raw_os_error(),
+                    Some(libc::ENOTTY) | Some(libc::EINVAL) | Some(libc::EOPNOTSUPP)
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn is_immutable(&self, _name: &OsStr) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Total size in bytes of the filesystem
+    /// holding this directory.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn filesystem_size(&self) -> Result<u64> {
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        cvt(unsafe { libc::fstatvfs(self.fd, &mut stat) })?;
+        Ok((stat.f_blocks as u64).saturating_mul(stat.f_frsize as u64))
+    }
+
+    /// List the names contained in this directory,
+    /// `.` and `..` excluded.
+    pub fn entries(&self) -> Result<Vec<OsString>> {
+        let fd = cvt(unsafe { libc::fcntl(self.fd, libc::F_DUPFD_CLOEXEC, 0) })?;
+        let dir = unsafe { libc::fdopendir(fd) };
+        if dir.is_null() {
+            let err = Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+
+        unsafe { libc::rewinddir(dir) };
+        let mut output = Vec::new();
+        let result = loop {
+            unsafe { *errno_location() = 0 };
+            let entry = unsafe { libc::readdir(dir) };
+            if entry.is_null() {
+                let err = Error::last_os_error();
+                break match err.raw_os_error() {
+                    Some(0) | None => Ok(()),
+                    Some(_) => Err(err),
+                };
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+            if name != b"." && name != b".." {
+                output.push(OsString::from_vec(name.to_vec()));
+            }
+        };
+        unsafe { libc::closedir(dir) };
+
+        result.map(|_| output)
+    }
+
+    pub fn remove_file(&self, name: &OsStr) -> Result<()> {
+        self.unlink(name, 0)
+    }
+
+    pub fn remove_dir(&self, name: &OsStr) -> Result<()> {
+        self.unlink(name, libc::AT_REMOVEDIR)
+    }
+
+    /// Remove name and, if it is a directory, all its
+    /// content. Symbolic links are removed, never followed.
+    pub fn remove_dir_all(&self, name: &OsStr) -> Result<()> {
+        if self.metadata(name)?.is_dir() {
+            let dir = self.open_dir(name)?;
+            for entry in dir.entries()? {
+                dir.remove_dir_all(&entry)?;
+            }
+            self.remove_dir(name)
+        } else {
+            self.remove_file(name)
+        }
+    }
+
+    /// Move name to target, on the same filesystem. A relative
+    /// target is resolved against the working directory.
+    pub fn rename<P: AsRef<Path>>(&self, name: &OsStr, target: P) -> Result<()> {
+        let name = to_cstring(name)?;
+        let target = to_cstring(target.as_ref().as_os_str())?;
+        cvt(unsafe { libc::renameat(self.fd, name.as_ptr(), libc::AT_FDCWD, target.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Rename name to new_name, both in this directory.
+    pub fn rename_in(&self, name: &OsStr, new_name: &OsStr) -> Result<()> {
+        let name = to_cstring(name)?;
+        let new_name = to_cstring(new_name)?;
+        cvt(unsafe { libc::renameat(self.fd, name.as_ptr(), self.fd, new_name.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Rename name to new_name, both in this directory, failing
+    /// with AlreadyExists instead of replacing new_name. Where
+    /// renameat2 is missing, name is linked to new_name then
+    /// unlinked, which only works for files.
+    pub fn rename_in_no_replace(&self, name: &OsStr, new_name: &OsStr) -> Result<()> {
+        let name = to_cstring(name)?;
+        let new_name = to_cstring(new_name)?;
+        match rename_no_replace(self.fd, &name, &new_name) {
+            Err(err) if err.raw_os_error() == Some(libc::EINVAL) => (),
+            result => return result,
+        }
+        cvt(unsafe { libc::linkat(self.fd, name.as_ptr(), self.fd, new_name.as_ptr(), 0) })?;
+        cvt(unsafe { libc::unlinkat(self.fd, name.as_ptr(), 0) })?;
+        Ok(())
+    }
+
+    /// Rename name to new_name in target, on the same filesystem.
+    pub fn rename_to(&self, name: &OsStr, target: &DirFd, new_name: &OsStr) -> Result<()> {
+        let name = to_cstring(name)?;
+        let new_name = to_cstring(new_name)?;
+        cvt(unsafe { libc::renameat(self.fd, name.as_ptr(), target.fd, new_name.as_ptr()) })?;
+        Ok(())
+    }
+
+    /// Flush changes to this directory to disk.
+    pub fn sync(&self) -> Result<()> {
+        cvt(unsafe { libc::fsync(self.fd) })?;
+        Ok(())
+    }
+
+    fn open_at(&self, name: &OsStr, flags: c_int) -> Result<File> {
+        let name = to_cstring(name)?;
+        let flags = flags | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = cvt(unsafe { libc::openat(self.fd, name.as_ptr(), flags) })?;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn unlink(&self, name: &OsStr, flags: c_int) -> Result<()> {
+        let name = to_cstring(name)?;
+        cvt(unsafe { libc::unlinkat(self.fd, name.as_ptr(), flags) })?;
+        Ok(())
+    }
+}
+
+impl Drop for DirFd {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Flags opening a file only to read its status.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const O_STAT: c_int = libc::O_PATH;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const O_STAT: c_int = libc::O_RDONLY | libc::O_NONBLOCK;
+
+fn to_cstring(name: &OsStr) -> Result<CString> {
+    CString::new(name.as_bytes()).map_err(|err| Error::new(ErrorKind::InvalidInput, err))
+}
+
+fn cvt(ret: c_int) -> Result<c_int> {
+    if ret == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// renameat2 with RENAME_NOREPLACE, EINVAL when the
+/// filesystem or the system cannot do it.
+#[cfg(target_os = "linux")]
+fn rename_no_replace(fd: RawFd, name: &CStr, new_name: &CStr) -> Result<()> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            fd,
+            name.as_ptr(),
+            fd,
+            new_name.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    match ret {
+        -1 if Error::last_os_error().raw_os_error() == Some(libc::ENOSYS) => {
+            Err(Error::from_raw_os_error(libc::EINVAL))
+        }
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_no_replace(_fd: RawFd, _name: &CStr, _new_name: &CStr) -> Result<()> {
+    Err(Error::from_raw_os_error(libc::EINVAL))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno_location()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__error()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{self, create_dir_all, File};
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rename_in_no_replace() {
+        let root = TempDir::new().unwrap();
+        fs::write(root.path().join("file"), "file").unwrap();
+        fs::write(root.path().join("taken"), "taken").unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        let err = dir
+            .rename_in_no_replace(OsStr::new("file"), OsStr::new("taken"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(root.path().join("taken")).unwrap(), b"taken");
+        dir.rename_in_no_replace(OsStr::new("file"), OsStr::new("free"))
+            .unwrap();
+        assert_eq!(fs::read(root.path().join("free")).unwrap(), b"file");
+        assert!(!root.path().join("file").exists());
+    }
+
+    #[test]
+    fn test_remove_dir_all_does_not_follow_links() {
+        let root = TempDir::new().unwrap();
+        let outside = root.path().join("outside");
+        create_dir_all(&outside).unwrap();
+        let precious = outside.join("precious");
+        File::create(&precious).unwrap();
+
+        let tree = root.path().join("tree");
+        create_dir_all(tree.join("a").join("b")).unwrap();
+        File::create(tree.join("a").join("b").join("file")).unwrap();
+        symlink(&outside, tree.join("a").join("link")).unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        dir.remove_dir_all(OsStr::new("tree")).unwrap();
+
+        assert!(!tree.exists());
+        assert!(precious.exists());
+    }
+
+    #[test]
+    fn test_open_dir_refuses_links() {
+        let root = TempDir::new().unwrap();
+        create_dir_all(root.path().join("dir")).unwrap();
+        symlink(root.path().join("dir"), root.path().join("link")).unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        assert!(dir.open_dir(OsStr::new("dir")).is_ok());
+        assert!(dir.open_dir(OsStr::new("link")).is_err());
+        assert!(!dir.metadata(OsStr::new("link")).unwrap().is_dir());
+
+        let mut entries = dir.entries().unwrap();
+        entries.sort();
+        assert_eq!(entries, vec![OsString::from("dir"), OsString::from("link")]);
+    }
+
+    #[test]
+    fn test_open_parent() {
+        let root = TempDir::new().unwrap();
+        create_dir_all(root.path().join("dir")).unwrap();
+        let path = root.path().join("dir").join("file");
+        File::create(&path).unwrap();
+
+        let (dir, name) = DirFd::open_parent(&path).unwrap();
+        assert_eq!(name, OsString::from("file"));
+        dir.remove_file(&name).unwrap();
+        assert!(!path.exists());
+
+        assert!(DirFd::open_parent(root.path().join("dir").join("..")).is_err());
+        assert!(DirFd::open_parent("/").is_err());
+    }
+
+    #[test]
+    fn test_filesystem_size() {
+        let root = TempDir::new().unwrap();
+        let dir = DirFd::open(root.path()).unwrap();
+        assert!(dir.filesystem_size().unwrap() > 0);
+    }
+}
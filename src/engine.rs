@@ -1,119 +1,304 @@
+use super::dir_fd::DirFd;
+use super::failures::{keep_going, Failures};
 use super::file_remove_iterator::*;
-use super::io_engine;
+use super::io_engine::{self, Prompt};
 use super::logger;
+use super::plan::Plan;
+use super::remove_action::RemoveAction;
 
-use std::fs::{remove_dir_all, remove_file};
-use std::io::Result;
+use std::ffi::OsStr;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
 
-pub enum Command<'a> {
+pub use super::file_remove_iterator::{Syntax, UnitTable};
+pub use remove_by_date::TimeField;
+pub use remove_composite::Combine;
+
+pub enum Criterion<'a> {
     BySize((&'a str, bool)),
     ByDate((&'a str, bool)),
+    SizeRange(&'a str),
+    AgeRange(&'a str),
+    BySizeReference((&'a Path, bool)),
+    ByDateReference((&'a Path, bool)),
+    Where(&'a str),
     Duplicates,
 }
 
+/// A set of criteria, a file is removed when
+/// they are satisfied according to combine.
+/// Duplicates are always checked last, only
+/// on the files matched by the other criteria.
+pub struct Command<'a> {
+    criteria: Vec<Criterion<'a>>,
+    combine: Combine,
+    time_field: TimeField,
+    syntax: Syntax,
+    size_units: UnitTable,
+    time_units: UnitTable,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(criteria: Vec<Criterion<'a>>, combine: Combine) -> Self {
+        Self {
+            criteria,
+            combine,
+            time_field: TimeField::default(),
+            syntax: Syntax::default(),
+            size_units: UnitTable::size(),
+            time_units: UnitTable::time(),
+        }
+    }
+
+    /// Select the timestamp compared by time based criteria.
+    pub fn with_time_field(mut self, time_field: TimeField) -> Self {
+        self.time_field = time_field;
+        self
+    }
+
+    /// Select how specification strings are parsed.
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Select the units of size and time specifications,
+    /// by default UnitTable::size and UnitTable::time.
+    pub fn with_units(mut self, size_units: UnitTable, time_units: UnitTable) -> Self {
+        self.size_units = size_units;
+        self.time_units = time_units;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.criteria.is_empty()
+    }
+}
+
 pub enum Mode {
     Standard,
+    /// like KeepGoing, ignoring files that do not exist
     Force,
     Interactive,
+    /// plan the whole removal, then prompt once
+    Once,
+    /// record errors and go on, see Failures
+    KeepGoing,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn automatic_remove(
-    paths: &[&str],
+    paths: &[&Path],
     mode: Mode,
     command: Command,
     clean: bool,
     log: &mut Option<logger::StatusLogger>,
-    extensions: Option<Vec<&str>>,
-    directories: Option<Vec<&str>>,
+    extensions: Option<Vec<&OsStr>>,
+    directories: Option<Vec<&OsStr>>,
     ignore_hiddens: bool,
-) -> Result<()> {
-    let controller = make_controller(command)?;
-    let mut controller = if let Mode::Interactive = mode {
-        let controller = io_engine::InteractiveFileRemove::new(controller);
-        Box::new(controller)
-    } else {
-        controller
-    };
+    action: &mut dyn RemoveAction,
+) -> Result<Failures> {
+    let mut controller = make_controller(command)?;
+    let prompt = new_prompt(&mode);
     let filter = make_file_filter(extensions, directories, ignore_hiddens);
-    for path in paths.iter() {
-        run_remove(path, &mode, &mut controller, clean, log, &filter)?;
+    let mut failures = new_failures(&mode);
+    if let Mode::Once = mode {
+        let mut plan = Plan::new(action);
+        for path in paths.iter() {
+            file_remove::file_remover(
+                path,
+                &mut controller,
+                clean,
+                &mut None,
+                &filter,
+                &mut plan,
+                prompt,
+                &mut failures,
+            )?;
+        }
+        confirm_plan(plan, log, &mut failures)?;
+    } else {
+        for path in paths.iter() {
+            file_remove::file_remover(
+                path,
+                &mut controller,
+                clean,
+                log,
+                &filter,
+                action,
+                prompt,
+                &mut failures,
+            )?;
+        }
     }
 
-    Ok(())
+    action.finish(log)?;
+    Ok(failures.unwrap_or_default())
 }
 
 pub fn remove(
-    file_name: &[&str],
+    file_name: &[&Path],
     mode: Mode,
     recursive: bool,
     log: &mut Option<logger::StatusLogger>,
-) -> Result<()> {
-    for file in file_name {
-        let mut done = true;
-        logger::add_file_remove_log(log, file)?;
-        match mode {
-            Mode::Standard => {
-                remove_wrap(file, recursive)?;
-            }
-            Mode::Force => {
-                let _ = remove_wrap(file, recursive);
-            }
-            Mode::Interactive => {
-                if io_engine::remove_question(file)? {
-                    remove_wrap(file, recursive)?;
-                } else {
-                    done = false;
-                }
-            }
-        }
-        if done {
-            logger::output_file_remove_log(log);
+    action: &mut dyn RemoveAction,
+) -> Result<Failures> {
+    // like rm, every operand is tried: errors are collected
+    // even when not going on through an automatic removal
+    let mut failures = new_failures(&mode).or_else(|| Some(Failures::new()));
+    // like rm -I: only a recursive removal or more than three files is worth asking for
+    if let (Mode::Once, true) = (&mode, recursive || file_name.len() > 3) {
+        let mut plan = Plan::new(action);
+        for file in file_name {
+            let prompt = Prompt::write_protected();
+            remove_operand(file, prompt, recursive, &mut None, &mut plan, &mut failures)?;
         }
+        confirm_plan(plan, log, &mut failures)?;
+        action.finish(log)?;
+        return Ok(failures.unwrap_or_default());
+    }
+    let prompt = new_prompt(&mode);
+    for file in file_name {
+        remove_operand(file, prompt, recursive, log, action, &mut failures)?;
+    }
+    action.finish(log)?;
+    Ok(failures.unwrap_or_default())
+}
+
+fn remove_operand(
+    path: &Path,
+    prompt: Prompt,
+    recursive: bool,
+    log: &mut Option<logger::StatusLogger>,
+    action: &mut dyn RemoveAction,
+    failures: &mut Option<Failures>,
+) -> Result<()> {
+    if recursive && !action.keeps_trees() {
+        let removed = DirFd::open_parent(path).and_then(|(dir, name)| {
+            action.enter_tree(&dir, &name, path)?;
+            remove_tree(&dir, &name, path, prompt, log, action, failures)
+        });
+        keep_going(removed, path, failures)?;
+        return Ok(());
+    }
+    let confirmed = DirFd::open_parent(path)
+        .and_then(|(dir, name)| io_engine::ask_remove(prompt, &dir, &name, path, log));
+    if keep_going(confirmed, path, failures)? != Some(true) {
+        return Ok(());
+    }
+    let removed =
+        logger::add_file_remove_log(log, path).and_then(|_| remove_wrap(path, recursive, action));
+    if keep_going(removed, path, failures)?.is_some() {
+        logger::output_file_remove_log(log);
     }
     Ok(())
 }
 
-fn remove_wrap(name: &str, rec: bool) -> Result<()> {
-    if rec {
-        remove_dir_all(name)
+/// Remove name and, when it is a directory, everything in
+/// it one entry at a time like rm -r: each entry is logged,
+/// and asked for according to prompt. Return whether name was
+/// removed: when failures are collected, what cannot be
+/// removed is left in place with its parent directories.
+fn remove_tree(
+    dir: &DirFd,
+    name: &OsStr,
+    path: &Path,
+    prompt: Prompt,
+    log: &mut Option<logger::StatusLogger>,
+    action: &mut dyn RemoveAction,
+    failures: &mut Option<Failures>,
+) -> Result<bool> {
+    let is_dir = dir.metadata(name)?.is_dir();
+    if is_dir {
+        if !action.enter_dir(dir, name, path)? {
+            return Ok(false);
+        }
+        // like rm -ri, an empty directory is only asked to be removed
+        let sub_dir = dir.open_dir(name)?;
+        let entries = sub_dir.entries()?;
+        if !entries.is_empty() && !io_engine::ask_descend(prompt, dir, name, path)? {
+            return Ok(false);
+        }
+        let mut empty = true;
+        for entry in entries {
+            let entry_path = path.join(&entry);
+            let removed = remove_tree(&sub_dir, &entry, &entry_path, prompt, log, action, failures);
+            if keep_going(removed, &entry_path, failures)? != Some(true) {
+                empty = false;
+            }
+        }
+        if !empty {
+            return Ok(false);
+        }
+    }
+    if !io_engine::ask_remove(prompt, dir, name, path, log)? {
+        return Ok(false);
+    }
+    logger::add_file_remove_log(log, path)?;
+    if is_dir {
+        action.remove_dir(dir, name, path)?;
     } else {
-        remove_file(name)
+        action.remove_file(dir, name, path)?;
     }
+    logger::output_file_remove_log(log);
+    Ok(true)
 }
 
-fn run_remove(
-    path: &str,
-    mode: &Mode,
-    controller: &mut Box<dyn file_remove::FileRemove>,
-    clean: bool,
+/// Ask once for the whole plan, that
+/// is carried out when confirmed.
+fn confirm_plan(
+    plan: Plan,
     log: &mut Option<logger::StatusLogger>,
-    file_filter: &file_filter::FileFilter,
+    failures: &mut Option<Failures>,
 ) -> Result<()> {
+    if !plan.is_empty() && io_engine::confirm(&plan.to_string())? {
+        plan.carry_out(log, failures)?;
+    }
+    Ok(())
+}
+
+/// Interactive runs ask for every file, forced ones
+/// for none and the others for write-protected files.
+fn new_prompt(mode: &Mode) -> Prompt {
     match mode {
-        Mode::Standard | Mode::Interactive => {
-            file_remove::file_remover(path, controller, clean, log, file_filter)?;
-        }
-        Mode::Force => {
-            let _ = file_remove::file_remover(path, controller, clean, log, file_filter);
-        }
+        Mode::Interactive => Prompt::Always,
+        Mode::Force => Prompt::Never,
+        Mode::Standard | Mode::Once | Mode::KeepGoing => Prompt::write_protected(),
     }
+}
 
-    Ok(())
+/// Failures are collected when going on after an error,
+/// forced runs ignore files that do not exist like `rm -f`.
+fn new_failures(mode: &Mode) -> Option<Failures> {
+    match mode {
+        Mode::KeepGoing => Some(Failures::new()),
+        Mode::Force => Some(Failures::new().with_missing_ignored()),
+        Mode::Standard | Mode::Interactive | Mode::Once => None,
+    }
+}
+
+fn remove_wrap(path: &Path, rec: bool, action: &mut dyn RemoveAction) -> Result<()> {
+    let (parent, name) = DirFd::open_parent(path)?;
+    if rec {
+        action.remove_dir_all(&parent, &name, path)
+    } else {
+        action.remove_file(&parent, &name, path)
+    }
 }
 
 fn make_file_filter(
-    ext: Option<Vec<&str>>,
-    dir: Option<Vec<&str>>,
+    ext: Option<Vec<&OsStr>>,
+    dir: Option<Vec<&OsStr>>,
     ignore_hidden: bool,
 ) -> file_filter::FileFilter {
-    let ext: Option<&[&str]> = if let Some(ref ext) = ext {
-        Some(&ext)
+    let ext: Option<&[&OsStr]> = if let Some(ref ext) = ext {
+        Some(ext)
     } else {
         None
     };
 
-    let dir: Option<&[&str]> = if let Some(ref dir) = dir {
-        Some(&dir)
+    let dir: Option<&[&OsStr]> = if let Some(ref dir) = dir {
+        Some(dir)
     } else {
         None
     };
@@ -126,26 +311,96 @@ fn make_file_filter(
     }
 }
 
-fn make_controller(command: Command) -> Result<Box<dyn file_remove::FileRemove>> {
-    match command {
-        Command::BySize((size, smaller)) => {
-            let val = remove_by_size::SizeRemove::new(&size, smaller)?;
+fn make_controller(mut command: Command) -> Result<Box<dyn file_remove::FileRemove>> {
+    let criteria = std::mem::take(&mut command.criteria);
+    let mut controllers = Vec::with_capacity(criteria.len());
+    let mut duplicates = false;
+    for criterion in criteria {
+        match criterion {
+            Criterion::Duplicates => duplicates = true,
+            criterion => controllers.push(make_criterion_controller(criterion, &command)?),
+        }
+    }
+
+    let controller: Option<Box<dyn file_remove::FileRemove>> = match controllers.len() {
+        0 => None,
+        1 => controllers.pop(),
+        _ => Some(Box::new(remove_composite::CompositeRemove::new(
+            controllers,
+            command.combine,
+        ))),
+    };
+
+    match (controller, duplicates) {
+        (Some(controller), true) => {
+            let controllers = vec![
+                controller,
+                make_criterion_controller(Criterion::Duplicates, &command)?,
+            ];
+            let composite = remove_composite::CompositeRemove::new(controllers, Combine::All);
+            Ok(Box::new(composite))
+        }
+        (Some(controller), false) => Ok(controller),
+        (None, true) => make_criterion_controller(Criterion::Duplicates, &command),
+        (None, false) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "no automatic removal criteria given",
+        )),
+    }
+}
+
+fn make_criterion_controller(
+    criterion: Criterion,
+    command: &Command,
+) -> Result<Box<dyn file_remove::FileRemove>> {
+    let (time_field, syntax) = (command.time_field, command.syntax);
+    let (size_units, time_units) = (&command.size_units, &command.time_units);
+    match criterion {
+        Criterion::BySize((size, smaller)) => {
+            let val = remove_by_size::SizeRemove::new(size, smaller, syntax, size_units)?;
+            Ok(Box::new(val))
+        }
+        Criterion::ByDate((time, older)) => {
+            let val = remove_by_date::TimeRemove::new(time, older, syntax, time_units)?
+                .with_field(time_field);
+            Ok(Box::new(val))
+        }
+        Criterion::SizeRange(range) => {
+            let val = remove_by_size::SizeRemove::from_range(range, syntax, size_units)?;
             Ok(Box::new(val))
         }
-        Command::ByDate((time, older)) => {
-            let val = remove_by_date::TimeRemove::new(&time, older)?;
+        Criterion::AgeRange(range) => {
+            let val = remove_by_date::TimeRemove::from_range(range, syntax, time_units)?
+                .with_field(time_field);
             Ok(Box::new(val))
         }
-        Command::Duplicates => Ok(Box::new(remove_duplicates::FileIndex::new())),
+        Criterion::BySizeReference((reference, smaller)) => {
+            let val = remove_by_size::SizeRemove::from_reference(reference, smaller)?;
+            Ok(Box::new(val))
+        }
+        Criterion::ByDateReference((reference, older)) => {
+            let val = remove_by_date::TimeRemove::from_reference(reference, older, time_field)?;
+            Ok(Box::new(val))
+        }
+        Criterion::Where(expression) => {
+            let val = remove_by_expression::ExpressionRemove::new(
+                expression, time_field, syntax, size_units, time_units,
+            )?;
+            Ok(Box::new(val))
+        }
+        Criterion::Duplicates => Ok(Box::new(remove_duplicates::FileIndex::new())),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unused_io_amount, clippy::useless_vec)]
 mod test {
 
     use super::*;
+    use crate::remove_action::{DryRun, Unlink};
     use std::collections::HashMap;
-    use std::fs::{create_dir, File};
+    use std::ffi::OsStr;
+    use std::fs::{create_dir, create_dir_all, File};
     use std::io::prelude::Write;
     use std::path::{Path, PathBuf};
     use std::thread;
@@ -158,16 +413,17 @@ mod test {
         let temp_dir = tempdir().unwrap();
         let unique = build_unique_file_tree(&temp_dir);
         let duplicates = build_duplicates_file_tree(&temp_dir);
-        let paths = [temp_dir.path().to_str().unwrap()];
+        let paths = [temp_dir.path()];
         automatic_remove(
             &paths,
             Mode::Standard,
-            Command::Duplicates,
+            Command::new(vec![Criterion::Duplicates], Combine::All),
             false,
             &mut None,
             None,
             None,
             false,
+            &mut Unlink,
         )
         .unwrap();
 
@@ -255,16 +511,17 @@ mod test {
 
         let file_to_keep = temp_dir.path().join("b");
         File::create(&file_to_keep).unwrap();
-        let paths = [temp_dir.path().to_str().unwrap()];
+        let paths = [temp_dir.path()];
         automatic_remove(
             &paths,
             Mode::Standard,
-            Command::ByDate(("2s", true)),
+            Command::new(vec![Criterion::ByDate(("2s", true))], Combine::All),
             false,
             &mut None,
             None,
             None,
             false,
+            &mut Unlink,
         )
         .unwrap();
 
@@ -283,16 +540,17 @@ mod test {
 
         let file_to_remove = temp_dir.path().join("b");
         File::create(&file_to_remove).unwrap();
-        let paths = [temp_dir.path().to_str().unwrap()];
+        let paths = [temp_dir.path()];
         automatic_remove(
             &paths,
             Mode::Standard,
-            Command::ByDate(("2s", false)),
+            Command::new(vec![Criterion::ByDate(("2s", false))], Combine::All),
             false,
             &mut None,
             None,
             None,
             false,
+            &mut Unlink,
         )
         .unwrap();
 
@@ -306,16 +564,17 @@ mod test {
         let base_dir = tempdir().unwrap();
         let non_remove_files = make_sized_files(&base_dir, "a", 10, 1, 4130);
         let remove_files = make_sized_files(&base_dir, "b", 10, 4140, 10000);
-        let paths = [base_dir.path().to_str().unwrap()];
+        let paths = [base_dir.path()];
         automatic_remove(
             &paths,
             Mode::Standard,
-            Command::BySize((size_spec, false)),
+            Command::new(vec![Criterion::BySize((size_spec, false))], Combine::All),
             false,
             &mut None,
             None,
             None,
             false,
+            &mut Unlink,
         )
         .unwrap();
         for f in non_remove_files.iter() {
@@ -332,16 +591,17 @@ mod test {
         let base_dir = tempdir().unwrap();
         let remove_files = make_sized_files(&base_dir, "a", 10, 1, 4140);
         let non_remove_files = make_sized_files(&base_dir, "b", 10, 4150, 10000);
-        let paths = [base_dir.path().to_str().unwrap()];
+        let paths = [base_dir.path()];
         automatic_remove(
             &paths,
             Mode::Standard,
-            Command::BySize((size_spec, true)),
+            Command::new(vec![Criterion::BySize((size_spec, true))], Combine::All),
             false,
             &mut None,
             None,
             None,
             false,
+            &mut Unlink,
         )
         .unwrap();
         for f in non_remove_files.iter() {
@@ -353,12 +613,307 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_remove_does_not_follow_links() {
+        let base_dir = tempdir().unwrap();
+        let outside = base_dir.path().join("outside");
+        create_dir(&outside).unwrap();
+        let outside_files = make_sized_files_in(&outside, "a", 10, 1, 100);
+
+        let tree = base_dir.path().join("tree");
+        create_dir(&tree).unwrap();
+        let remove_files = make_sized_files_in(&tree, "b", 10, 1, 100);
+        std::os::unix::fs::symlink(&outside, tree.join("link")).unwrap();
+
+        automatic_remove(
+            &[tree.as_path()],
+            Mode::Standard,
+            Command::new(vec![Criterion::BySize(("1kb", true))], Combine::All),
+            false,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut Unlink,
+        )
+        .unwrap();
+
+        for f in outside_files.iter() {
+            assert!(f.exists());
+        }
+
+        for f in remove_files.iter() {
+            assert!(!f.exists());
+        }
+    }
+
+    #[test]
+    fn test_remove_combined_criteria() {
+        let base_dir = tempdir().unwrap();
+        let small = make_sized_files(&base_dir, "a", 5, 10, 100);
+        let medium = make_sized_files(&base_dir, "b", 5, 1000, 2000);
+        let large = make_sized_files(&base_dir, "c", 5, 5000, 6000);
+        let paths = [base_dir.path()];
+
+        let criteria = vec![
+            Criterion::BySize(("500b", false)),
+            Criterion::BySize(("4kb", true)),
+        ];
+        automatic_remove(
+            &paths,
+            Mode::Standard,
+            Command::new(criteria, Combine::All),
+            false,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut Unlink,
+        )
+        .unwrap();
+
+        for f in small.iter().chain(large.iter()) {
+            assert!(f.exists());
+        }
+        for f in medium.iter() {
+            assert!(!f.exists());
+        }
+
+        let criteria = vec![
+            Criterion::BySize(("500b", true)),
+            Criterion::BySize(("4kb", false)),
+        ];
+        automatic_remove(
+            &paths,
+            Mode::Standard,
+            Command::new(criteria, Combine::Any),
+            false,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut Unlink,
+        )
+        .unwrap();
+
+        for f in small.iter().chain(large.iter()) {
+            assert!(!f.exists());
+        }
+    }
+
+    #[test]
+    fn test_remove_duplicates_after_criteria() {
+        let base_dir = tempdir().unwrap();
+        let small: Vec<PathBuf> = (0..4)
+            .map(|i| base_dir.path().join(format!("small_{}", i)))
+            .collect();
+        let large: Vec<PathBuf> = (0..4)
+            .map(|i| base_dir.path().join(format!("large_{}", i)))
+            .collect();
+        for f in small.iter() {
+            File::create(f).unwrap().write_all(&[0; 10]).unwrap();
+        }
+        for f in large.iter() {
+            File::create(f).unwrap().write_all(&[0; 1000]).unwrap();
+        }
+
+        let criteria = vec![Criterion::Duplicates, Criterion::BySize(("100b", true))];
+        automatic_remove(
+            &[base_dir.path()],
+            Mode::Standard,
+            Command::new(criteria, Combine::All),
+            false,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut Unlink,
+        )
+        .unwrap();
+
+        assert_eq!(small.iter().filter(|f| f.exists()).count(), 1);
+        for f in large.iter() {
+            assert!(f.exists());
+        }
+    }
+
+    #[test]
+    fn test_remove_size_range() {
+        let base_dir = tempdir().unwrap();
+        let small = make_sized_files(&base_dir, "a", 5, 10, 100);
+        let medium = make_sized_files(&base_dir, "b", 5, 1000, 2000);
+        let large = make_sized_files(&base_dir, "c", 5, 5000, 6000);
+
+        automatic_remove(
+            &[base_dir.path()],
+            Mode::Standard,
+            Command::new(vec![Criterion::SizeRange("500b..4kb")], Combine::All),
+            false,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut Unlink,
+        )
+        .unwrap();
+
+        for f in small.iter().chain(large.iter()) {
+            assert!(f.exists());
+        }
+        for f in medium.iter() {
+            assert!(!f.exists());
+        }
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let base_dir = tempdir().unwrap();
+        let sub_dir = base_dir.path().join("sub");
+        create_dir(&sub_dir).unwrap();
+        let small = make_sized_files_in(&sub_dir, "a", 5, 10, 100);
+
+        automatic_remove(
+            &[base_dir.path()],
+            Mode::Standard,
+            Command::new(vec![Criterion::BySize(("1kb", true))], Combine::All),
+            true,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut DryRun,
+        )
+        .unwrap();
+
+        for f in small.iter() {
+            assert!(f.exists());
+        }
+        assert!(sub_dir.exists());
+
+        let paths = [small[0].as_path(), sub_dir.as_path()];
+        remove(&paths, Mode::Standard, true, &mut None, &mut DryRun).unwrap();
+        assert!(small[0].exists());
+        let failures = remove(
+            &[Path::new("missing"), small[1].as_path()],
+            Mode::Standard,
+            false,
+            &mut None,
+            &mut DryRun,
+        )
+        .unwrap();
+        assert_eq!(failures.len(), 1);
+    }
+
+    /// Unlink every file but the first one.
+    struct FailFirst(bool);
+
+    impl RemoveAction for FailFirst {
+        fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+            if self.0 {
+                Unlink.remove_file(dir, name, path)
+            } else {
+                self.0 = true;
+                Err(Error::from(ErrorKind::PermissionDenied))
+            }
+        }
+
+        fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+            Unlink.remove_dir(dir, name, path)
+        }
+
+        fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+            Unlink.remove_dir_all(dir, name, path)
+        }
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let base_dir = tempdir().unwrap();
+        let sub_dir = base_dir.path().join("sub");
+        create_dir(&sub_dir).unwrap();
+        let small = make_sized_files_in(&sub_dir, "a", 5, 10, 100);
+        let command = || Command::new(vec![Criterion::BySize(("1kb", true))], Combine::All);
+        let paths = [base_dir.path()];
+
+        let result = automatic_remove(
+            &paths,
+            Mode::Standard,
+            command(),
+            true,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut FailFirst(false),
+        );
+        assert!(result.is_err());
+        assert_eq!(small.iter().filter(|f| f.exists()).count(), 5);
+
+        let failures = automatic_remove(
+            &paths,
+            Mode::KeepGoing,
+            command(),
+            true,
+            &mut None,
+            None,
+            None,
+            false,
+            &mut FailFirst(false),
+        )
+        .unwrap();
+        assert_eq!(failures.len(), 1);
+        let failure = failures.iter().next().unwrap();
+        assert!(failure.path.exists());
+        assert_eq!(failure.kind, ErrorKind::PermissionDenied);
+        assert_eq!(small.iter().filter(|f| f.exists()).count(), 1);
+        // the directory is not empty, so it is not cleaned
+        assert!(sub_dir.exists());
+
+        let missing = base_dir.path().join("missing");
+        let paths = [missing.as_path(), failure.path.as_path()];
+        let failures = remove(&paths, Mode::KeepGoing, false, &mut None, &mut Unlink).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures.iter().next().unwrap().kind, ErrorKind::NotFound);
+        assert_eq!(small.iter().filter(|f| f.exists()).count(), 0);
+    }
+
+    #[test]
+    fn test_remove_recursive() {
+        let base_dir = tempdir().unwrap();
+        let tree = base_dir.path().join("tree");
+        let deep = tree.join("a").join("b");
+        create_dir_all(&deep).unwrap();
+        let files = make_sized_files_in(&deep, "a", 3, 10, 100);
+        let paths = [tree.as_path()];
+
+        let failures = remove(&paths, Mode::Force, true, &mut None, &mut FailFirst(false)).unwrap();
+        assert_eq!(failures.len(), 1);
+        let failure = failures.iter().next().unwrap();
+        assert!(failure.path.starts_with(&deep));
+        assert_eq!(failure.kind, ErrorKind::PermissionDenied);
+        assert_eq!(files.iter().filter(|f| f.exists()).count(), 1);
+        assert!(deep.exists());
+
+        remove(&paths, Mode::Standard, true, &mut None, &mut Unlink).unwrap();
+        assert!(!tree.exists());
+    }
+
     fn make_sized_files(
         base_dir: &TempDir,
         ext: &str,
         count: usize,
         min_size: usize,
         max_size: usize,
+    ) -> Vec<PathBuf> {
+        make_sized_files_in(base_dir.path(), ext, count, min_size, max_size)
+    }
+
+    fn make_sized_files_in(
+        base_dir: &Path,
+        ext: &str,
+        count: usize,
+        min_size: usize,
+        max_size: usize,
     ) -> Vec<PathBuf> {
         let mut output = Vec::with_capacity(count);
         let size_step = (max_size - min_size) / count;
@@ -366,7 +921,7 @@ mod test {
         let buff: [u8; 1] = [0];
         for i in 0..count {
             let name = format!("size_temp_file_{}_size{}.{}", i, base_size, ext);
-            let full_name = base_dir.path().join(name);
+            let full_name = base_dir.join(name);
             let mut tmp = File::create(&full_name).unwrap();
             for _ in 0..base_size {
                 tmp.write(&buff).unwrap();
@@ -415,14 +970,15 @@ mod test {
         file.write(&[0, 1, 2, 3, 4]).unwrap();
 
         automatic_remove(
-            &[temp_dir.path().to_str().unwrap()],
+            &[temp_dir.path()],
             Mode::Standard,
-            Command::Duplicates,
+            Command::new(vec![Criterion::Duplicates], Combine::All),
             true,
             &mut None,
             None,
             None,
             false,
+            &mut Unlink,
         )
         .unwrap();
 
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// An error met while removing path.
+pub struct Failure {
+    pub path: PathBuf,
+    pub kind: ErrorKind,
+    pub error: Error,
+}
+
+/// Formatted like rm does: `cannot remove 'x': Permission denied`.
+/// A refusal that already names path is shown as it is.
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = describe(&self.error);
+        let path = quote(&self.path);
+        if self.error.raw_os_error().is_none() && message.contains(&path) {
+            write!(f, "{}", message)
+        } else {
+            write!(f, "cannot remove {}: {}", path, message)
+        }
+    }
+}
+
+/// The errors collected by a run that goes on
+/// after a failure instead of aborting.
+#[derive(Default)]
+pub struct Failures {
+    failures: Vec<Failure>,
+    ignore_missing: bool,
+}
+
+impl Failures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop errors about files that do not exist, like `rm -f`.
+    pub fn with_missing_ignored(mut self) -> Self {
+        self.ignore_missing = true;
+        self
+    }
+
+    pub fn record<P: AsRef<Path>>(&mut self, path: P, error: Error) {
+        if self.ignore_missing && error.kind() == ErrorKind::NotFound {
+            return;
+        }
+        self.failures.push(Failure {
+            path: path.as_ref().to_path_buf(),
+            kind: error.kind(),
+            error,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.failures.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Failure> {
+        self.failures.iter()
+    }
+}
+
+/// A summary: how many failures of each kind.
+impl fmt::Display for Failures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut kinds: Vec<(ErrorKind, usize)> = Vec::new();
+        for failure in &self.failures {
+            match kinds.iter_mut().find(|(kind, _)| *kind == failure.kind) {
+                Some((_, count)) => *count += 1,
+                None => kinds.push((failure.kind, 1)),
+            }
+        }
+        let count = self.failures.len();
+        write!(f, "{} error{}", count, if count == 1 { "" } else { "s" })?;
+        for (index, (kind, count)) in kinds.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { ", " };
+            write!(f, "{}{:?} ({})", separator, kind, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the value of result. On error, when failures are
+/// collected the error is recorded against path and None is
+/// returned, otherwise the error is returned, naming path
+/// when it comes from the system.
+pub fn keep_going<T, P: AsRef<Path>>(
+    result: Result<T>,
+    path: P,
+    failures: &mut Option<Failures>,
+) -> Result<Option<T>> {
+    match (result, failures) {
+        (Ok(value), _) => Ok(Some(value)),
+        (Err(error), Some(failures)) => {
+            failures.record(path, error);
+            Ok(None)
+        }
+        (Err(error), None) if error.raw_os_error().is_some() => {
+            let failure = Failure {
+                path: path.as_ref().to_path_buf(),
+                kind: error.kind(),
+                error,
+            };
+            Err(Error::new(failure.kind, failure.to_string()))
+        }
+        (Err(error), None) => Err(error),
+    }
+}
+
+/// Quote path like coreutils: in single quotes, or double
+/// ones when it contains a single quote. Control characters
+/// and bytes that are not UTF-8 are escaped the way a shell
+/// reads them back, as in `'caf'$'\351''.txt'`.
+pub fn quote(path: &Path) -> String {
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.is_empty() {
+        return "''".to_string();
+    }
+    let mut output = String::new();
+    let mut plain = String::new();
+    let mut escaped = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() {
+                push_plain(&mut output, &mut plain);
+                let mut buffer = [0; 4];
+                escape_bytes(&mut escaped, c.encode_utf8(&mut buffer).as_bytes());
+            } else {
+                push_escaped(&mut output, &mut escaped);
+                plain.push(c);
+            }
+        }
+        if !chunk.invalid().is_empty() {
+            push_plain(&mut output, &mut plain);
+            escape_bytes(&mut escaped, chunk.invalid());
+        }
+    }
+    push_plain(&mut output, &mut plain);
+    push_escaped(&mut output, &mut escaped);
+    output
+}
+
+fn push_plain(output: &mut String, plain: &mut String) {
+    if plain.is_empty() {
+        return;
+    }
+    if plain.contains('\'') {
+        output.push_str(&format!("\"{}\"", plain));
+    } else {
+        output.push_str(&format!("'{}'", plain));
+    }
+    plain.clear();
+}
+
+fn push_escaped(output: &mut String, escaped: &mut String) {
+    if !escaped.is_empty() {
+        output.push_str(&format!("$'{}'", escaped));
+        escaped.clear();
+    }
+}
+
+fn escape_bytes(escaped: &mut String, bytes: &[u8]) {
+    for byte in bytes {
+        match byte {
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\r' => escaped.push_str("\\r"),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+}
+
+/// The error message, without the `(os error N)` suffix.
+fn describe(error: &Error) -> String {
+    let message = error.to_string();
+    match error.raw_os_error() {
+        Some(code) => {
+            let suffix = format!(" (os error {})", code);
+            message.trim_end_matches(&suffix).to_string()
+        }
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_keep_going() {
+        let mut failures = None;
+        assert_eq!(keep_going(Ok(1), "a", &mut failures).unwrap(), Some(1));
+        let error = Error::from_raw_os_error(libc::ENOENT);
+        let error = keep_going::<(), _>(Err(error), "a", &mut failures).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(
+            error.to_string(),
+            "cannot remove 'a': No such file or directory"
+        );
+        let error = Error::other("custom");
+        let error = keep_going::<(), _>(Err(error), "a", &mut failures).unwrap_err();
+        assert_eq!(error.to_string(), "custom");
+
+        let mut failures = Some(Failures::new());
+        let error = Error::from_raw_os_error(libc::EACCES);
+        assert_eq!(
+            keep_going::<(), _>(Err(error), "it's", &mut failures).unwrap(),
+            None
+        );
+        let error = Error::from_raw_os_error(libc::ENOENT);
+        keep_going::<(), _>(Err(error), "b", &mut failures).unwrap();
+        let failures = failures.unwrap();
+        assert_eq!(failures.len(), 2);
+        let failure = failures.iter().next().unwrap();
+        assert_eq!(failure.path, PathBuf::from("it's"));
+        assert_eq!(failure.kind, ErrorKind::PermissionDenied);
+        assert_eq!(
+            failure.to_string(),
+            "cannot remove \"it's\": Permission denied"
+        );
+        assert_eq!(
+            failures.to_string(),
+            "2 errors: PermissionDenied (1), NotFound (1)"
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(Path::new("a b")), "'a b'");
+        assert_eq!(quote(Path::new("it's")), "\"it's\"");
+        assert_eq!(quote(Path::new("")), "''");
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        assert_eq!(quote(Path::new(name)), "'caf'$'\\351''.txt'");
+        assert_eq!(quote(Path::new("a\nb\x01")), "'a'$'\\n''b'$'\\001'");
+        assert_eq!(quote(Path::new(OsStr::from_bytes(b"\xff"))), "$'\\377'");
+    }
+
+    #[test]
+    fn test_missing_ignored() {
+        let mut failures = Failures::new().with_missing_ignored();
+        failures.record("a", Error::from_raw_os_error(libc::ENOENT));
+        assert!(failures.is_empty());
+        failures.record("b", Error::from_raw_os_error(libc::EISDIR));
+        assert_eq!(failures.to_string(), "1 error: IsADirectory (1)");
+    }
+}
@@ -1,15 +1,16 @@
 use std::collections::HashSet;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
 pub struct FileFilter {
-    ignore_dirs: Option<HashSet<String>>,
-    ignore_exts: Option<HashSet<String>>,
+    ignore_dirs: Option<HashSet<OsString>>,
+    ignore_exts: Option<HashSet<OsString>>,
     ignore_hiddens: bool,
 }
 
 impl FileFilter {
-    pub fn new(exts: Option<&[&str]>, dirs: Option<&[&str]>) -> Self {
+    pub fn new(exts: Option<&[&OsStr]>, dirs: Option<&[&OsStr]>) -> Self {
         let ignore_dirs = collect_string_slice(dirs);
         let ignore_exts = collect_string_slice(exts);
 
@@ -36,7 +37,7 @@ impl FileFilter {
     }
 }
 
-fn check_path(set: &Option<HashSet<String>>, os_str: Option<&OsStr>) -> bool {
+fn check_path(set: &Option<HashSet<OsString>>, os_str: Option<&OsStr>) -> bool {
     if let Some(ref set) = set {
         !contains(set, os_str)
     } else {
@@ -44,21 +45,17 @@ fn check_path(set: &Option<HashSet<String>>, os_str: Option<&OsStr>) -> bool {
     }
 }
 
-fn contains(set: &HashSet<String>, os_str: Option<&OsStr>) -> bool {
+fn contains(set: &HashSet<OsString>, os_str: Option<&OsStr>) -> bool {
     if let Some(os_str) = os_str {
-        if let Some(std_str) = os_str.to_str() {
-            set.contains(std_str)
-        } else {
-            false
-        }
+        set.contains(os_str)
     } else {
         false
     }
 }
 
-fn collect_string_slice(slice: Option<&[&str]>) -> Option<HashSet<String>> {
+fn collect_string_slice(slice: Option<&[&OsStr]>) -> Option<HashSet<OsString>> {
     if let Some(slice) = slice {
-        let tmp: HashSet<String> = slice.iter().map(|s| s.clone().to_owned()).collect();
+        let tmp: HashSet<OsString> = slice.iter().map(|s| (*s).to_owned()).collect();
         Some(tmp)
     } else {
         None
@@ -67,11 +64,7 @@ fn collect_string_slice(slice: Option<&[&str]>) -> Option<HashSet<String>> {
 
 fn is_hidden(path: &Path) -> bool {
     if let Some(name) = path.file_name() {
-        if let Some(name) = name.to_str() {
-            name.starts_with(".")
-        } else {
-            false
-        }
+        name.as_bytes().starts_with(b".")
     } else {
         false
     }
@@ -118,7 +111,7 @@ mod tests {
             &remove_extensions,
         );
 
-        let filter = FileFilter::new(Some(&preserve_extensions), None);
+        let filter = FileFilter::new(Some(&os(&preserve_extensions)), None);
         let filter = filter.ingnore_hidden();
 
         for file in &preserve_files {
@@ -152,7 +145,7 @@ mod tests {
             &remove_extensions,
         );
 
-        let filter = FileFilter::new(Some(&preserve_extensions), None);
+        let filter = FileFilter::new(Some(&os(&preserve_extensions)), None);
         for file in &preserve_files {
             assert!(!filter.process_path(file));
         }
@@ -173,7 +166,7 @@ mod tests {
         let preserve_dirs = create_complex_tree(root.path(), &path_tokens, &preserve_names);
         let remove_dirs = create_complex_tree(root.path(), &path_tokens, &remove_names);
 
-        let filter = FileFilter::new(None, Some(&preserve_names));
+        let filter = FileFilter::new(None, Some(&os(&preserve_names)));
 
         for dir in &preserve_dirs {
             assert!(!filter.process_path(dir));
@@ -203,7 +196,7 @@ mod tests {
         let preserve_dirs = create_complex_tree(root.path(), &path_tokens, &preserve_names);
         let remove_dirs = create_complex_tree(root.path(), &path_tokens, &remove_names);
 
-        let filter = FileFilter::new(Some(&preserve_extensions), Some(&preserve_names));
+        let filter = FileFilter::new(Some(&os(&preserve_extensions)), Some(&os(&preserve_names)));
         for file in &preserve_files {
             assert!(!filter.process_path(file));
         }
@@ -240,6 +233,10 @@ mod tests {
         output
     }
 
+    fn os<'a>(names: &[&'a str]) -> Vec<&'a OsStr> {
+        names.iter().map(|name| OsStr::new(*name)).collect()
+    }
+
     fn create_complex_tree(root: &Path, path_tokens: &[&str], names: &[&str]) -> Vec<PathBuf> {
         let mut output = Vec::new();
 
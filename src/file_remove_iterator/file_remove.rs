@@ -1,56 +1,161 @@
 use super::file_filter;
+use crate::dir_fd::DirFd;
+use crate::failures::{keep_going, Failures};
+use crate::io_engine::{self, Prompt};
 use crate::logger;
+use crate::remove_action::RemoveAction;
+use std::ffi::OsStr;
 use std::io::Error;
 use std::path::Path;
 
-use std::fs::{read_dir, remove_dir, remove_file};
-
 /// This trait's implementation
 /// can be passed as argument to file_remover.
 /// This allows to implement tailored file removes
-/// without redefine a file iterator
+/// without redefine a file iterator.
+/// Files are given as name in dir, so that they
+/// are inspected without following path again,
+/// path is only there to be shown.
 pub trait FileRemove {
-    fn remove(&mut self, path: &Path) -> Result<bool, Error>;
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool, Error>;
+
+    /// Called by file_remover before walking root, to
+    /// resolve anything that depends on it.
+    fn enter_root(&mut self, _root: &Path) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 /// This function iterates though the file
-/// tree starting from path. Each encountered files is
+/// tree starting from root. Each encountered files is
 /// passed as argument to the  remove.remove
-/// if this method returns true the file is removed.
-/// the file is left untouched otherwise
+/// if this method returns true the file is removed
+/// by action.
+/// the file is left untouched otherwise.
+/// Sub directories are opened relative to their
+/// parent and symbolic links are never followed.
+/// Files are asked for according to prompt.
+/// When failures is set errors are recorded there
+/// and the walk goes on, otherwise the first error
+/// stops it.
+#[allow(clippy::too_many_arguments)]
 pub fn file_remover(
-    path: &str,
+    root: &Path,
+    remove: &mut Box<dyn FileRemove>,
+    clean: bool,
+    log: &mut Option<logger::StatusLogger>,
+    file_filter: &file_filter::FileFilter,
+    action: &mut dyn RemoveAction,
+    prompt: Prompt,
+    failures: &mut Option<Failures>,
+) -> Result<bool, Error> {
+    let dir = DirFd::open(root).and_then(|dir| {
+        remove.enter_root(root)?;
+        action.enter_root(root)?;
+        Ok(dir)
+    });
+    match keep_going(dir, root, failures)? {
+        Some(dir) => dir_remover(
+            &dir,
+            root,
+            remove,
+            clean,
+            log,
+            file_filter,
+            action,
+            prompt,
+            failures,
+        ),
+        None => Ok(false),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn dir_remover(
+    dir: &DirFd,
+    path: &Path,
     remove: &mut Box<dyn FileRemove>,
     clean: bool,
     log: &mut Option<logger::StatusLogger>,
     file_filter: &file_filter::FileFilter,
+    action: &mut dyn RemoveAction,
+    prompt: Prompt,
+    failures: &mut Option<Failures>,
 ) -> Result<bool, Error> {
+    let entries = match keep_going(dir.entries(), path, failures)? {
+        Some(entries) => entries,
+        None => return Ok(false),
+    };
     let mut empty = true;
     let mut directory_queue = Vec::new();
-    for entry in read_dir(path)? {
-        let entry = entry?;
-        if !file_filter.process_path(&entry.path()) {
+    for name in entries {
+        let entry_path = path.join(&name);
+        if !file_filter.process_path(&entry_path) {
+            empty = false;
             continue;
         }
-        if entry.path().is_dir() {
-            let dir_path = entry.path();
-            directory_queue.push(dir_path);
-        } else if remove.remove(&entry.path())? {
-            logger::add_file_remove_log(log, &entry.path())?;
-            remove_file(entry.path())?;
-            logger::output_file_remove_log(log);
-        } else {
+        let removed = dir.metadata(&name).and_then(|stat| {
+            if stat.is_dir() {
+                directory_queue.push(name.clone());
+                Ok(true)
+            } else if remove.remove(dir, &name, &entry_path)?
+                && io_engine::ask_remove(prompt, dir, &name, &entry_path, log)?
+            {
+                logger::add_file_remove_log(log, &entry_path)?;
+                action.remove_file(dir, &name, &entry_path)?;
+                logger::output_file_remove_log(log);
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        });
+        if keep_going(removed, &entry_path, failures)? != Some(true) {
             empty = false;
         }
     }
 
-    for sub_dir in directory_queue {
-        let rm_dir = file_remover(sub_dir.to_str().unwrap(), remove, clean, log, file_filter)?;
+    for name in directory_queue {
+        let sub_path = path.join(&name);
+        let sub_dir = action.enter_dir(dir, &name, &sub_path).and_then(|enter| {
+            if enter {
+                dir.open_dir(&name).map(Some)
+            } else {
+                Ok(None)
+            }
+        });
+        let sub_dir = match keep_going(sub_dir, &sub_path, failures)? {
+            Some(Some(sub_dir)) => sub_dir,
+            _ => {
+                empty = false;
+                continue;
+            }
+        };
+        let rm_dir = dir_remover(
+            &sub_dir,
+            &sub_path,
+            remove,
+            clean,
+            log,
+            file_filter,
+            action,
+            prompt,
+            failures,
+        )?;
+        drop(sub_dir);
         if rm_dir {
             if clean {
-                logger::add_file_remove_log(log, &sub_dir)?;
-                remove_dir(&sub_dir)?;
-                logger::output_file_remove_log(log);
+                let removed = io_engine::ask_remove(prompt, dir, &name, &sub_path, log).and_then(
+                    |confirmed| {
+                        if confirmed {
+                            logger::add_file_remove_log(log, &sub_path)?;
+                            action.remove_dir(dir, &name, &sub_path)?;
+                            logger::output_file_remove_log(log);
+                        }
+                        Ok(confirmed)
+                    },
+                );
+                if keep_going(removed, &sub_path, failures)? != Some(true) {
+                    empty = false;
+                }
             }
         } else {
             empty = false;
@@ -59,3 +164,10 @@ pub fn file_remover(
 
     Ok(empty)
 }
+
+/// Ask remove about the file at path, like file_remover does.
+#[cfg(test)]
+pub(crate) fn remove_path(remove: &mut dyn FileRemove, path: &Path) -> Result<bool, Error> {
+    let (dir, name) = DirFd::open_parent(path)?;
+    remove.remove(&dir, &name, path)
+}
@@ -2,5 +2,9 @@ pub mod file_filter;
 pub mod file_remove;
 mod parser;
 pub mod remove_by_date;
+pub mod remove_by_expression;
 pub mod remove_by_size;
+pub mod remove_composite;
 pub mod remove_duplicates;
+
+pub use parser::{spec_string_parser, SpecError, Syntax, UnitError, UnitTable};
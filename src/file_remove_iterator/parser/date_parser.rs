@@ -0,0 +1,144 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Weekday};
+
+const DATE_TIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Parse an absolute point in time: a date like `2025-01-31`
+/// or a date and time like `2025-01-31T10:30:00`, both in
+/// local time, an RFC 3339 timestamp with its own time zone,
+/// like `2025-01-31T10:30:00+02:00`, or one of `now`, `today`,
+/// `yesterday` and `last WEEKDAY`. Days start at midnight.
+/// Return None if spec does not describe a point in time.
+pub fn date_parser(spec: &str, now: DateTime<Local>) -> Result<Option<DateTime<Local>>, String> {
+    let spec = spec.trim();
+    let lower = spec.to_lowercase();
+    let today = now.date_naive();
+    match lower.as_str() {
+        "now" => return Ok(Some(now)),
+        "today" => return local_midnight(today).map(Some),
+        "yesterday" => return local_midnight(previous_day(today)?).map(Some),
+        _ => {}
+    }
+
+    if let Some(day) = lower.strip_prefix("last ") {
+        let weekday: Weekday = day
+            .trim()
+            .parse()
+            .map_err(|_| format!("unknown week day `{}`", day.trim()))?;
+        let mut date = previous_day(today)?;
+        while date.weekday() != weekday {
+            date = previous_day(date)?;
+        }
+        return local_midnight(date).map(Some);
+    }
+
+    if !looks_like_date(spec) {
+        return Ok(None);
+    }
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(spec) {
+        return Ok(Some(date.with_timezone(&Local)));
+    }
+
+    for format in DATE_TIME_FORMATS.iter() {
+        if let Ok(date) = NaiveDateTime::parse_from_str(spec, format) {
+            return local_time(date).map(Some);
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return local_midnight(date).map(Some);
+    }
+
+    Err(format!("invalid date `{}`, expected YYYY-MM-DD", spec))
+}
+
+fn looks_like_date(spec: &str) -> bool {
+    let bytes = spec.as_bytes();
+    bytes.len() > 4 && bytes[..4].iter().all(u8::is_ascii_digit) && bytes[4] == b'-'
+}
+
+fn previous_day(date: NaiveDate) -> Result<NaiveDate, String> {
+    date.pred_opt()
+        .ok_or_else(|| format!("no day before {}", date))
+}
+
+fn local_midnight(date: NaiveDate) -> Result<DateTime<Local>, String> {
+    local_time(date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+fn local_time(date: NaiveDateTime) -> Result<DateTime<Local>, String> {
+    Local
+        .from_local_datetime(&date)
+        .earliest()
+        .ok_or_else(|| format!("{} does not exist in the local time zone", date))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_absolute_dates() {
+        let now = local(2025, 3, 12, 15, 30);
+        assert_eq!(
+            date_parser("2025-01-31", now),
+            Ok(Some(local(2025, 1, 31, 0, 0)))
+        );
+        assert_eq!(
+            date_parser("2025-01-31T10:30:00", now),
+            Ok(Some(local(2025, 1, 31, 10, 30)))
+        );
+        assert_eq!(
+            date_parser("2025-01-31 10:30", now),
+            Ok(Some(local(2025, 1, 31, 10, 30)))
+        );
+
+        let utc = DateTime::parse_from_rfc3339("2025-01-31T08:30:00Z").unwrap();
+        assert_eq!(
+            date_parser("2025-01-31T10:30:00+02:00", now),
+            Ok(Some(utc.with_timezone(&Local)))
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        // 2025-03-12 is a Wednesday
+        let now = local(2025, 3, 12, 15, 30);
+        assert_eq!(date_parser("now", now), Ok(Some(now)));
+        assert_eq!(
+            date_parser("today", now),
+            Ok(Some(local(2025, 3, 12, 0, 0)))
+        );
+        assert_eq!(
+            date_parser("yesterday", now),
+            Ok(Some(local(2025, 3, 11, 0, 0)))
+        );
+        assert_eq!(
+            date_parser("last monday", now),
+            Ok(Some(local(2025, 3, 10, 0, 0)))
+        );
+        assert_eq!(
+            date_parser("last Wed", now),
+            Ok(Some(local(2025, 3, 5, 0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_not_a_date() {
+        let now = local(2025, 3, 12, 15, 30);
+        assert_eq!(date_parser("1y2M", now), Ok(None));
+        assert_eq!(date_parser("20d", now), Ok(None));
+        assert!(date_parser("2025-13-01", now).is_err());
+        assert!(date_parser("last moonday", now).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+
+/// Abstract syntax tree of a filter expression like
+/// `(older 1y or larger 1gb) and not ext pdf`.
+#[derive(Debug, PartialEq)]
+pub enum FilterExpr<'a> {
+    And(Box<FilterExpr<'a>>, Box<FilterExpr<'a>>),
+    Or(Box<FilterExpr<'a>>, Box<FilterExpr<'a>>),
+    Not(Box<FilterExpr<'a>>),
+    Predicate(Predicate<'a>),
+}
+
+/// A leaf of the expression: a predicate name followed
+/// by its argument, both with their byte offset in the
+/// original expression.
+#[derive(Debug, PartialEq)]
+pub struct Predicate<'a> {
+    pub name: &'a str,
+    pub name_position: usize,
+    pub argument: &'a str,
+    pub argument_position: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ExpressionError {
+    expression: String,
+    position: usize,
+    message: String,
+}
+
+impl ExpressionError {
+    pub fn new(expression: &str, position: usize, message: String) -> Self {
+        Self {
+            expression: expression.to_owned(),
+            position,
+            message,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = self.expression[..self.position].chars().count();
+        writeln!(f, "{} at column {}", self.message, column + 1)?;
+        writeln!(f, "  {}", self.expression)?;
+        write!(f, "  {}^", " ".repeat(column))
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Token<'a> {
+    Open,
+    Close,
+    Word(&'a str),
+}
+
+struct Tokenizer<'a> {
+    string: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(string: &'a str) -> Self {
+        Self {
+            string,
+            position: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (usize, Token<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.string[self.position..];
+        let begin = self.position + (rest.len() - rest.trim_start().len());
+        let rest = &self.string[begin..];
+        let ch = rest.chars().next()?;
+        let (token, len) = match ch {
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            _ => {
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .unwrap_or(rest.len());
+                (Token::Word(&rest[..len]), len)
+            }
+        };
+        self.position = begin + len;
+        Some((begin, token))
+    }
+}
+
+/// Parse a filter expression. The grammar is
+/// ```text
+/// expr      := term ('or' term)*
+/// term      := factor (['and'] factor)*
+/// factor    := 'not' factor | '(' expr ')' | predicate
+/// predicate := NAME ARGUMENT
+/// ```
+/// so `and` can be omitted, like in find. Predicate
+/// names are not checked here.
+pub fn filter_expression_parser(expression: &str) -> Result<FilterExpr<'_>, ExpressionError> {
+    let tokens: Vec<(usize, Token)> = Tokenizer::new(expression).collect();
+    let mut parser = Parser {
+        expression,
+        tokens,
+        current: 0,
+    };
+    let output = parser.parse_expr()?;
+    if let Some((position, _)) = parser.peek() {
+        Err(parser.error(position, "unexpected `)`"))
+    } else {
+        Ok(output)
+    }
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    tokens: Vec<(usize, Token<'a>)>,
+    current: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_expr(&mut self) -> Result<FilterExpr<'a>, ExpressionError> {
+        let mut output = self.parse_term()?;
+        while let Some((_, Token::Word("or"))) = self.peek() {
+            self.current += 1;
+            let right = self.parse_term()?;
+            output = FilterExpr::Or(Box::new(output), Box::new(right));
+        }
+        Ok(output)
+    }
+
+    fn parse_term(&mut self) -> Result<FilterExpr<'a>, ExpressionError> {
+        let mut output = self.parse_factor()?;
+        loop {
+            match self.peek() {
+                Some((_, Token::Word("and"))) => {
+                    self.current += 1;
+                }
+                Some((_, Token::Word("or"))) | Some((_, Token::Close)) | None => break,
+                Some(_) => {}
+            }
+            let right = self.parse_factor()?;
+            output = FilterExpr::And(Box::new(output), Box::new(right));
+        }
+        Ok(output)
+    }
+
+    fn parse_factor(&mut self) -> Result<FilterExpr<'a>, ExpressionError> {
+        let (position, token) = self.next_token("an expression")?;
+        match token {
+            Token::Word("not") => {
+                let inner = self.parse_factor()?;
+                Ok(FilterExpr::Not(Box::new(inner)))
+            }
+            Token::Open => {
+                let inner = self.parse_expr()?;
+                match self.next_token("`)`")? {
+                    (_, Token::Close) => Ok(inner),
+                    (position, _) => Err(self.error(position, "expected `)`")),
+                }
+            }
+            Token::Close => Err(self.error(position, "unexpected `)`")),
+            Token::Word(word @ "and") | Token::Word(word @ "or") => {
+                Err(self.error(position, &format!("unexpected `{}`", word)))
+            }
+            Token::Word(name) => match self.next_token("an argument")? {
+                (argument_position, Token::Word(argument)) => {
+                    Ok(FilterExpr::Predicate(Predicate {
+                        name,
+                        name_position: position,
+                        argument,
+                        argument_position,
+                    }))
+                }
+                (position, _) => {
+                    Err(self.error(position, &format!("expected an argument for `{}`", name)))
+                }
+            },
+        }
+    }
+
+    fn peek(&self) -> Option<(usize, Token<'a>)> {
+        self.tokens.get(self.current).cloned()
+    }
+
+    fn next_token(&mut self, expected: &str) -> Result<(usize, Token<'a>), ExpressionError> {
+        if let Some(token) = self.peek() {
+            self.current += 1;
+            Ok(token)
+        } else {
+            let msg = format!("unexpected end of expression, expected {}", expected);
+            Err(self.error(self.expression.len(), &msg))
+        }
+    }
+
+    fn error(&self, position: usize, msg: &str) -> ExpressionError {
+        ExpressionError::new(self.expression, position, msg.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn pred<'a>(name: &'a str, argument: &'a str, expression: &str) -> FilterExpr<'a> {
+        let name_position = expression.find(name).unwrap();
+        let argument_position = name_position + expression[name_position..].find(argument).unwrap();
+        FilterExpr::Predicate(Predicate {
+            name,
+            name_position,
+            argument,
+            argument_position,
+        })
+    }
+
+    #[test]
+    fn test_precedence() {
+        let expr = "(older 1y or larger 1gb) and not ext pdf";
+        let ans = filter_expression_parser(expr).unwrap();
+        let expected = FilterExpr::And(
+            Box::new(FilterExpr::Or(
+                Box::new(pred("older", "1y", expr)),
+                Box::new(pred("larger", "1gb", expr)),
+            )),
+            Box::new(FilterExpr::Not(Box::new(pred("ext", "pdf", expr)))),
+        );
+        assert_eq!(ans, expected);
+
+        // and binds tighter than or, and can be omitted
+        let expr = "older 1y or larger 1gb ext pdf";
+        let ans = filter_expression_parser(expr).unwrap();
+        let expected = FilterExpr::Or(
+            Box::new(pred("older", "1y", expr)),
+            Box::new(FilterExpr::And(
+                Box::new(pred("larger", "1gb", expr)),
+                Box::new(pred("ext", "pdf", expr)),
+            )),
+        );
+        assert_eq!(ans, expected);
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let ans = filter_expression_parser("(older 1y or larger 1gb").unwrap_err();
+        assert_eq!(ans.position(), 23);
+
+        let ans = filter_expression_parser("older 1y or ) larger 1gb").unwrap_err();
+        assert_eq!(ans.position(), 12);
+
+        let ans = filter_expression_parser("older 1y and (larger)").unwrap_err();
+        assert_eq!(ans.position(), 20);
+
+        let ans = filter_expression_parser("older 1y and and larger 1b").unwrap_err();
+        assert_eq!(ans.position(), 13);
+
+        let ans = filter_expression_parser("  ").unwrap_err();
+        assert_eq!(ans.position(), 2);
+    }
+
+    #[test]
+    fn test_error_message() {
+        let ans = filter_expression_parser("not (ext)").unwrap_err();
+        assert_eq!(
+            format!("{}", ans),
+            "expected an argument for `ext` at column 9\n  not (ext)\n          ^"
+        );
+    }
+}
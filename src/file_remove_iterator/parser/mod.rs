@@ -1,4 +1,14 @@
+mod date_parser;
+mod filter_expression_parser;
+mod spec_error;
 mod specification_string_parser;
 pub mod specification_string_tokenizer;
+pub(crate) mod unit_table;
 
-pub use specification_string_parser::spec_string_parser;
+pub use date_parser::date_parser;
+pub use filter_expression_parser::{
+    filter_expression_parser, ExpressionError, FilterExpr, Predicate,
+};
+pub use spec_error::{SpecError, UnitError};
+pub use specification_string_parser::{spec_range_parser, spec_string_parser, Syntax};
+pub use unit_table::UnitTable;
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::ops::Range;
+
+/// An error in a specification string, with the byte
+/// span of the offending part.
+#[derive(Debug, PartialEq)]
+pub struct SpecError {
+    spec: String,
+    span: Range<usize>,
+    message: String,
+    suggestion: Option<String>,
+}
+
+impl SpecError {
+    pub fn new(spec: &str, span: Range<usize>, message: String) -> Self {
+        Self {
+            spec: spec.to_owned(),
+            span,
+            message,
+            suggestion: None,
+        }
+    }
+
+    pub fn from_unit(spec: &str, span: Range<usize>, error: UnitError) -> Self {
+        Self {
+            suggestion: error.suggestion,
+            ..Self::new(spec, span, error.message)
+        }
+    }
+
+    /// Move this error inside a longer spec,
+    /// where its own spec begins at offset.
+    pub fn within(self, spec: &str, offset: usize) -> Self {
+        Self {
+            spec: spec.to_owned(),
+            span: self.span.start + offset..self.span.end + offset,
+            ..self
+        }
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn suggestion(&self) -> Option<&str> {
+        self.suggestion.as_deref()
+    }
+
+    /// The message and the suggestion, without the caret.
+    pub fn summary(&self) -> String {
+        match &self.suggestion {
+            Some(suggestion) => format!("{}, did you mean `{}`?", self.message, suggestion),
+            None => self.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let column = self.spec[..self.span.start].chars().count();
+        let width = self.spec[self.span.clone()].chars().count().max(1);
+        writeln!(f, "{} at column {}", self.message, column + 1)?;
+        writeln!(f, "  {}", self.spec)?;
+        write!(f, "  {}{}", " ".repeat(column), "^".repeat(width))?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+/// Error given by a unit lookup, the span is
+/// added by the parser.
+#[derive(Debug, PartialEq)]
+pub struct UnitError {
+    message: String,
+    suggestion: Option<String>,
+}
+
+impl UnitError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            suggestion: None,
+        }
+    }
+
+    /// An unknown unit of the given kind, suggest the
+    /// closest one among known.
+    pub fn unknown<'a, I>(kind: &str, unit: &str, known: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        Self {
+            message: format!("unknown {} unit `{}`", kind, unit),
+            suggestion: suggest(unit, known).map(str::to_owned),
+        }
+    }
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+/// The known name closest to name, if it is close enough:
+/// a name differing only by case, or by a few edits,
+/// one every three characters. Ties go to the name
+/// with the closest length.
+fn suggest<'a, I>(name: &str, known: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let length = name.chars().count();
+    let limit = (length + 1) / 3;
+    let lower = name.to_lowercase();
+    known
+        .into_iter()
+        .filter_map(|candidate| {
+            let distance = if candidate.to_lowercase() == lower {
+                0
+            } else {
+                edit_distance(name, candidate)
+            };
+            if distance <= limit {
+                Some((distance, candidate))
+            } else {
+                None
+            }
+        })
+        .min_by_key(|(distance, candidate)| (*distance, candidate.chars().count().abs_diff(length)))
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance counting insertions, deletions,
+/// substitutions and swaps of adjacent characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (table[i - 1][j] + 1)
+                .min(table[i][j - 1] + 1)
+                .min(table[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(table[i - 2][j - 2] + 1);
+            }
+            table[i][j] = best;
+        }
+    }
+    table[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const UNITS: [&str; 6] = ["b", "kb", "mb", "mib", "mega", "M"];
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(suggest("mbi", UNITS.iter().cloned()), Some("mib"));
+        assert_eq!(suggest("MB", UNITS.iter().cloned()), Some("mb"));
+        assert_eq!(suggest("mgea", UNITS.iter().cloned()), Some("mega"));
+        assert_eq!(suggest("x", UNITS.iter().cloned()), None);
+        assert_eq!(suggest("parsec", UNITS.iter().cloned()), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let error = SpecError::from_unit(
+            "10mbi",
+            2..5,
+            UnitError::unknown("size", "mbi", UNITS.iter().cloned()),
+        );
+        assert_eq!(
+            format!("{}", error),
+            "unknown size unit `mbi` at column 3\n  10mbi\n    ^^^ did you mean `mib`?"
+        );
+
+        let error = SpecError::new("3", 1..1, "missing unit after `3`".to_owned());
+        assert_eq!(
+            format!("{}", error.within("1kb..3", 5)),
+            "missing unit after `3` at column 7\n  1kb..3\n        ^"
+        );
+    }
+}
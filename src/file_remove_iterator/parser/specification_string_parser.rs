@@ -1,36 +1,297 @@
+use std::convert::TryFrom;
+use std::ops::{Bound, Range};
+
+use super::spec_error::{SpecError, UnitError};
 use super::specification_string_tokenizer::{SpecToken, SpecTokenizer};
 
-pub fn spec_string_parser<F>(spec: &str, f: F) -> Result<u64, String>
+const DECIMAL_DIGITS: usize = 9;
+const SCALE: u128 = 1_000_000_000;
+
+/// How characters between tokens are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Syntax {
+    /// Any character that is not part of a number or of
+    /// a unit is ignored, so `1,d` and `1-d` are `1d`, but
+    /// a sign between two terms adds or subtracts the term
+    /// that follows, so `1d-2h` is `22h`.
+    #[default]
+    Lenient,
+    /// Only white spaces are allowed between tokens,
+    /// and a single sign between two terms, like `1d - 2h`,
+    /// where `-` subtracts the term that follows.
+    Strict,
+}
+
+/// Parse a specification string, like `1gb500mb` or `1.5d`,
+/// into a total amount: each number is multiplied by the
+/// value of the unit that follows it, given by f.
+/// A term after `-` is subtracted, like in `1d - 2h`,
+/// and the total must not be negative.
+/// Decimal numbers are converted exactly and only the total
+/// is rounded, half up, to the nearest integer.
+pub fn spec_string_parser<F>(spec: &str, f: F, syntax: Syntax) -> Result<u64, SpecError>
+where
+    F: Fn(&str) -> Result<u64, UnitError>,
+{
+    let total = parse_spec(spec, f, syntax, false)?;
+    if total < 0 {
+        let err_msg = format!("`{}` is negative", spec);
+        Err(SpecError::new(spec, 0..spec.len(), err_msg))
+    } else {
+        Ok(total as u64)
+    }
+}
+
+/// Like spec_string_parser, but the total can be negative.
+/// With whole, each term must give a whole amount: `1.5y`
+/// with a year worth 12 is fine, `0.5M` with a month worth
+/// 1 is an error.
+pub fn spec_signed_parser<F>(
+    spec: &str,
+    f: F,
+    syntax: Syntax,
+    whole: bool,
+) -> Result<i128, SpecError>
+where
+    F: Fn(&str) -> Result<u64, UnitError>,
+{
+    parse_spec(spec, f, syntax, whole)
+}
+
+/// The total is at most u64::MAX, in absolute value.
+fn parse_spec<F>(spec: &str, f: F, syntax: Syntax, whole: bool) -> Result<i128, SpecError>
 where
-    F: Fn(&str) -> Result<u64, String>,
+    F: Fn(&str) -> Result<u64, UnitError>,
 {
-    let mut previous: Option<SpecToken> = None;
-    let mut total: u64 = 0;
+    let mut number: Option<(Range<usize>, &str)> = None;
+    let mut negative = false;
+    let mut signed = false;
+    // total is scaled by SCALE to keep decimal digits
+    let mut total: i128 = 0;
     let mut run_once = false;
-    let tokenizer = SpecTokenizer::new(spec);
-    for token in tokenizer {
-        if let Some(prev) = previous {
-            match (prev, token) {
-                (SpecToken::Number(n), SpecToken::Text(t)) => {
-                    let tmp = f(t)?;
-                    total += n * tmp;
-                    previous = None;
-                    run_once = true;
+    let mut last_end = 0;
+    for (begin, token) in SpecTokenizer::new(spec) {
+        let span = begin..begin + token.as_str().len();
+        if syntax == Syntax::Lenient {
+            match token {
+                // a sign only counts between two terms, elsewhere it
+                // is a separator, like in `4-a`, as it always was
+                SpecToken::Sign(_) if number.is_some() || !run_once || signed => continue,
+                SpecToken::Compare(_) => continue,
+                _ => {}
+            }
+        } else {
+            check_separators(spec, last_end..begin)?;
+        }
+        last_end = span.end;
+
+        match (number.take(), token) {
+            (None, SpecToken::Number(n)) => number = Some((span, n)),
+            (Some((number_span, n)), SpecToken::Text(t)) => {
+                let term_span = number_span.start..span.end;
+                let term = &spec[term_span.clone()];
+                let unit = f(t).map_err(|err| SpecError::from_unit(spec, span, err))?;
+                let value = parse_number(n)
+                    .map_err(|msg| SpecError::new(spec, number_span, msg))?
+                    .and_then(|n| n.checked_mul(u128::from(unit)))
+                    .and_then(|n| i128::try_from(n).ok())
+                    .ok_or_else(|| overflow_error(spec, term_span.clone(), term))?;
+                if whole && value % SCALE as i128 != 0 {
+                    let err_msg = format!("`{}` is not a whole number of `{}`", term, t);
+                    return Err(SpecError::new(spec, term_span, err_msg));
                 }
-                _ => {
-                    let err_msg = format!("Cannot parse `{}`", spec);
-                    return Err(err_msg);
+                let value = if negative { -value } else { value };
+                total = total
+                    .checked_add(value)
+                    .filter(|total| round(*total).unsigned_abs() <= u128::from(u64::MAX))
+                    .ok_or_else(|| overflow_error(spec, term_span.clone(), term))?;
+                negative = false;
+                signed = false;
+                run_once = true;
+            }
+            (None, SpecToken::Sign(sign)) => {
+                if signed || !run_once {
+                    let err_msg = format!("unexpected `{}`", sign);
+                    return Err(SpecError::new(spec, span, err_msg));
                 }
+                negative = sign == "-";
+                signed = true;
+            }
+            (Some((_, n)), SpecToken::Sign(sign)) => {
+                let err_msg = format!("unexpected `{}` between `{}` and its unit", sign, n);
+                return Err(SpecError::new(spec, span, err_msg));
+            }
+            (_, SpecToken::Compare(op)) => {
+                let err_msg = format!("unexpected `{}`, a comparison must come first", op);
+                return Err(SpecError::new(spec, span, err_msg));
+            }
+            (None, SpecToken::Text(t)) => {
+                let err_msg = format!("expected a number before `{}`", t);
+                return Err(SpecError::new(spec, span, err_msg));
+            }
+            (Some(_), SpecToken::Number(n)) => {
+                let err_msg = format!("expected a unit, found `{}`", n);
+                return Err(SpecError::new(spec, span, err_msg));
             }
-        } else {
-            previous = Some(token);
         }
     }
-    if run_once {
-        Ok(total)
+    if syntax == Syntax::Strict {
+        check_separators(spec, last_end..spec.len())?;
+        if signed {
+            let err_msg = "expected a term after the sign".to_owned();
+            return Err(SpecError::new(spec, spec.len()..spec.len(), err_msg));
+        }
+    }
+
+    if let Some((span, n)) = number {
+        let err_msg = format!("missing unit after `{}`", n);
+        Err(SpecError::new(spec, span.end..span.end, err_msg))
+    } else if run_once {
+        Ok(round(total))
     } else {
         let err_msg = format!("`{}` does not contain any specification", spec);
-        Err(err_msg)
+        Err(SpecError::new(spec, 0..spec.len(), err_msg))
+    }
+}
+
+/// Only white spaces are allowed between
+/// tokens in strict syntax.
+fn check_separators(spec: &str, range: Range<usize>) -> Result<(), SpecError> {
+    let gap = &spec[range.clone()];
+    match gap.char_indices().find(|(_, ch)| !ch.is_whitespace()) {
+        Some((index, ch)) => {
+            let begin = range.start + index;
+            let err_msg = format!("unexpected `{}`", ch);
+            Err(SpecError::new(spec, begin..begin + ch.len_utf8(), err_msg))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Convert a decimal number into an integer scaled by SCALE,
+/// None if it does not fit.
+fn parse_number(number: &str) -> Result<Option<u128>, String> {
+    let (integer, decimal) = match number.find('.') {
+        Some(index) => (&number[..index], &number[index + 1..]),
+        None => (number, ""),
+    };
+    if decimal.len() > DECIMAL_DIGITS {
+        let err_msg = format!(
+            "`{}` has too many decimal digits, at most {} are allowed",
+            number, DECIMAL_DIGITS
+        );
+        return Err(err_msg);
+    }
+    let decimal = format!("{:0<width$}", decimal, width = DECIMAL_DIGITS);
+    // the tokenizer only gives digits, so parse fails just on overflow
+    let integer: Option<u128> = if integer.is_empty() {
+        Some(0)
+    } else {
+        integer.parse().ok()
+    };
+    let decimal: u128 = decimal.parse().unwrap();
+    Ok(integer
+        .and_then(|n| n.checked_mul(SCALE))
+        .and_then(|n| n.checked_add(decimal)))
+}
+
+/// Round half away from zero.
+fn round(scaled: i128) -> i128 {
+    let magnitude = scaled.unsigned_abs();
+    let magnitude = (magnitude / SCALE + u128::from(magnitude % SCALE >= SCALE / 2)) as i128;
+    if scaled < 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+fn overflow_error(spec: &str, span: Range<usize>, term: &str) -> SpecError {
+    let err_msg = format!("`{}` is too large, the maximum is {}", term, u64::MAX);
+    SpecError::new(spec, span, err_msg)
+}
+
+/// Parse a range of specification strings, like `10mb..1gb`.
+/// One of the two ends can be omitted to get an open range,
+/// like `10mb..` or `..1gb`, and both ends are included.
+/// A leading comparison, like `<=1gb` or `>10mb`, is
+/// also accepted. Each end is parsed with f, the lower end
+/// must not be greater than the upper one.
+pub fn spec_range_parser<T, F>(spec: &str, f: F) -> Result<(Bound<T>, Bound<T>), SpecError>
+where
+    T: PartialOrd + Clone,
+    F: Fn(&str) -> Result<T, SpecError>,
+{
+    if let Some((_, SpecToken::Compare(op))) = SpecTokenizer::new(spec)
+        .next()
+        .filter(|(begin, _)| spec[..*begin].trim().is_empty())
+    {
+        return parse_comparison(spec, op, f);
+    }
+
+    let index = match spec.find("..") {
+        Some(index) => index,
+        None => {
+            let err_msg = format!(
+                "`{}` is not a range, expected `MIN..MAX` or a comparison like `<=MAX`",
+                spec
+            );
+            return Err(SpecError::new(spec, 0..spec.len(), err_msg));
+        }
+    };
+    let (begin, end) = (&spec[..index], &spec[index + 2..]);
+    let begin = parse_range_end(begin, &f).map_err(|err| err.within(spec, 0))?;
+    let end = parse_range_end(end, &f).map_err(|err| err.within(spec, index + 2))?;
+    match (begin, end) {
+        (None, None) => {
+            let err_msg = format!("`{}` does not contain any specification", spec);
+            Err(SpecError::new(spec, 0..spec.len(), err_msg))
+        }
+        (Some(begin), Some(end)) if begin > end => {
+            let err_msg = format!(
+                "`{}` is an empty range: minimum is greater than maximum",
+                spec
+            );
+            Err(SpecError::new(spec, 0..spec.len(), err_msg))
+        }
+        (begin, end) => Ok((
+            begin.map_or(Bound::Unbounded, Bound::Included),
+            end.map_or(Bound::Unbounded, Bound::Included),
+        )),
+    }
+}
+
+fn parse_comparison<T, F>(spec: &str, op: &str, f: F) -> Result<(Bound<T>, Bound<T>), SpecError>
+where
+    T: Clone,
+    F: Fn(&str) -> Result<T, SpecError>,
+{
+    let offset = spec.find(op).unwrap() + op.len();
+    let value = &spec[offset..];
+    if let Some(index) = value.find("..") {
+        let begin = offset + index;
+        let err_msg = "a comparison cannot be used with a range".to_owned();
+        return Err(SpecError::new(spec, begin..begin + 2, err_msg));
+    }
+    let value = f(value).map_err(|err| err.within(spec, offset))?;
+    let output = match op {
+        "<" => (Bound::Unbounded, Bound::Excluded(value)),
+        "<=" => (Bound::Unbounded, Bound::Included(value)),
+        ">" => (Bound::Excluded(value), Bound::Unbounded),
+        ">=" => (Bound::Included(value), Bound::Unbounded),
+        _ => (Bound::Included(value.clone()), Bound::Included(value)),
+    };
+    Ok(output)
+}
+
+fn parse_range_end<T, F>(spec: &str, f: &F) -> Result<Option<T>, SpecError>
+where
+    F: Fn(&str) -> Result<T, SpecError>,
+{
+    if spec.trim().is_empty() {
+        Ok(None)
+    } else {
+        f(spec).map(Some)
     }
 }
 
@@ -48,12 +309,16 @@ mod test {
             spec -> 5440;
         */
         let spec = "4-a,4-b,5-c";
-        let ans = spec_string_parser(spec, |s| match s {
-            "a" => Ok(10),
-            "b" => Ok(100),
-            "c" => Ok(1000),
-            err => Err(format!("unknown specifier {}", err)),
-        })
+        let ans = spec_string_parser(
+            spec,
+            |s| match s {
+                "a" => Ok(10),
+                "b" => Ok(100),
+                "c" => Ok(1000),
+                err => Err(UnitError::unknown("test", err, vec!["a", "b", "c"])),
+            },
+            Syntax::Lenient,
+        )
         .unwrap();
         assert_eq!(ans, 5440);
     }
@@ -81,7 +346,7 @@ mod test {
     }
 
     #[test]
-    #[should_panic(expected = "unknown specifier tn")]
+    #[should_panic(expected = "unknown weight unit `tn`, did you mean `t`?")]
     fn test_with_malformatted_specifier() {
         let spec = "3kilo4gram2tn2t45k";
         let ans = run_weight_test(spec).unwrap();
@@ -89,7 +354,7 @@ mod test {
     }
 
     #[test]
-    #[should_panic(expected = "Cannot parse `ton43`")]
+    #[should_panic(expected = "expected a number before `ton`")]
     fn test_with_wrong_specifier() {
         let spec = "ton43";
         let ans = run_weight_test(spec).unwrap();
@@ -113,12 +378,213 @@ mod test {
         assert_eq!(ans, 0);
     }
 
+    #[test]
+    fn test_error_spans() {
+        let err = parse_weight("3kilo 4gramm", Syntax::Lenient).unwrap_err();
+        assert_eq!(err.span(), 7..12);
+        assert_eq!(err.suggestion(), Some("gram"));
+
+        let err = parse_weight("3kilo 4", Syntax::Lenient).unwrap_err();
+        assert_eq!(err.span(), 7..7);
+
+        let err = parse_weight("3kilo 4 5g", Syntax::Lenient).unwrap_err();
+        assert_eq!(err.span(), 8..9);
+
+        let err = spec_range_parser("1k..2x", |s| parse_weight(s, Syntax::Lenient)).unwrap_err();
+        assert_eq!(err.span(), 5..6);
+        assert_eq!(err.suggestion(), None);
+    }
+
+    #[test]
+    fn test_strict_syntax() {
+        assert_eq!(
+            parse_weight("3kilo + 4 gram  2t", Syntax::Strict),
+            Ok(2_003_004)
+        );
+        assert_eq!(parse_weight(" 1.5k ", Syntax::Strict), Ok(1500));
+
+        let err = parse_weight("1-k", Syntax::Strict).unwrap_err();
+        assert_eq!(err.span(), 1..2);
+        assert_eq!(
+            format!("{}", err),
+            "unexpected `-` between `1` and its unit at column 2\n  1-k\n   ^"
+        );
+
+        assert!(parse_weight("1k,2g", Syntax::Strict).is_err());
+        assert!(parse_weight("1k ++ 2g", Syntax::Strict).is_err());
+        assert!(parse_weight("+1k", Syntax::Strict).is_err());
+        assert!(parse_weight("1k+", Syntax::Strict).is_err());
+        assert!(parse_weight("1+k", Syntax::Strict).is_err());
+        assert_eq!(parse_weight("1-k", Syntax::Lenient), Ok(1000));
+    }
+
+    #[test]
+    fn test_decimal_numbers() {
+        assert_eq!(run_weight_test("1.5k"), Ok(1500));
+        assert_eq!(run_weight_test("0.0015t"), Ok(1500));
+        assert_eq!(run_weight_test(".5k 0.25g"), Ok(500));
+        // only the total is rounded, half up
+        assert_eq!(run_weight_test("0.5g"), Ok(1));
+        assert_eq!(run_weight_test("0.4g"), Ok(0));
+        assert_eq!(run_weight_test("0.3g0.3g"), Ok(1));
+        assert!(run_weight_test("0.0000000001k").is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let ans = run_weight_test("1g 99999999999999999t");
+        assert_eq!(
+            ans,
+            Err(
+                "`99999999999999999t` is too large, the maximum is 18446744073709551615".to_owned()
+            )
+        );
+
+        let ans = run_weight_test("18446744073709551615g 1g");
+        assert_eq!(
+            ans,
+            Err("`1g` is too large, the maximum is 18446744073709551615".to_owned())
+        );
+        assert_eq!(
+            run_weight_test("18446744073709551615g"),
+            Ok(18_446_744_073_709_551_615)
+        );
+        assert!(run_weight_test("999999999999999999999999999999999999999999k").is_err());
+    }
+
+    #[test]
+    fn test_subtraction() {
+        let strict = |spec| parse_weight(spec, Syntax::Strict).map_err(|err| err.summary());
+        assert_eq!(strict("1ton - 1kilo"), Ok(999_000));
+        assert_eq!(strict("1ton - 1kilo + 2g"), Ok(999_002));
+        assert_eq!(strict("1g - 1.5g + 1g"), Ok(1));
+        assert!(strict("1ton -- 1kilo").is_err());
+        assert!(strict("1ton -").is_err());
+        assert_eq!(strict("1k - 2k"), Err("`1k - 2k` is negative".to_owned()));
+
+        let unit = |_: &str| Ok(1);
+        assert_eq!(
+            spec_signed_parser("1g - 2g", unit, Syntax::Strict, false),
+            Ok(-1)
+        );
+    }
+
+    #[test]
+    fn test_lenient_separators() {
+        // a sign between two terms adds or subtracts, elsewhere
+        // signs and comparisons are separators, as they always were
+        assert_eq!(run_weight_test("1ton-1kilo"), Ok(999_000));
+        assert_eq!(run_weight_test("1ton - 1kilo"), Ok(999_000));
+        assert_eq!(run_weight_test("4-k"), Ok(4000));
+        assert_eq!(run_weight_test("4-k-1-k"), Ok(3000));
+        assert_eq!(run_weight_test("1k>2g<3g=4g"), Ok(1009));
+        assert_eq!(run_weight_test("-1k+2g"), Ok(1002));
+        assert_eq!(run_weight_test("1k--2g"), Ok(998));
+        assert_eq!(run_weight_test("1k-"), Ok(1000));
+        assert_eq!(
+            run_weight_test("1k-2k"),
+            Err("`1k-2k` is negative".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_comparison() {
+        let ans = spec_range_parser_test("<=1k").unwrap();
+        assert_eq!(ans, (Bound::Unbounded, Bound::Included(1000)));
+        let ans = spec_range_parser_test(" < 1k").unwrap();
+        assert_eq!(ans, (Bound::Unbounded, Bound::Excluded(1000)));
+        let ans = spec_range_parser_test(">1k-1g").unwrap();
+        assert_eq!(ans, (Bound::Excluded(999), Bound::Unbounded));
+        let ans = spec_range_parser(">1k - 1g", |s| parse_weight(s, Syntax::Strict)).unwrap();
+        assert_eq!(ans, (Bound::Excluded(999), Bound::Unbounded));
+        let ans = spec_range_parser_test(">=1k").unwrap();
+        assert_eq!(ans, (Bound::Included(1000), Bound::Unbounded));
+        let ans = spec_range_parser_test("=1k").unwrap();
+        assert_eq!(ans, (Bound::Included(1000), Bound::Included(1000)));
+
+        let err = spec_range_parser("<1k..2k", |s| parse_weight(s, Syntax::Lenient)).unwrap_err();
+        assert_eq!(err.span(), 3..5);
+        let err = spec_range_parser("<=1kk", |s| parse_weight(s, Syntax::Lenient)).unwrap_err();
+        assert_eq!(err.span(), 3..5);
+        let err = spec_range_parser("1k<=2k", |s| parse_weight(s, Syntax::Lenient)).unwrap_err();
+        assert!(err.summary().contains("not a range"));
+        assert!(parse_weight("<=1k", Syntax::Strict).is_err());
+    }
+
+    #[test]
+    fn test_whole_parser() {
+        let unit = |s: &str| match s {
+            "y" => Ok(12),
+            "M" => Ok(1),
+            _ => Ok(0),
+        };
+        assert_eq!(
+            spec_signed_parser("1.5y 2M 0.5d", unit, Syntax::Lenient, true),
+            Ok(20)
+        );
+        assert_eq!(
+            spec_signed_parser("1y - 13M", unit, Syntax::Strict, true),
+            Ok(-1)
+        );
+        let err = spec_signed_parser("1y 0.5M", unit, Syntax::Lenient, true).unwrap_err();
+        assert_eq!(err.summary(), "`0.5M` is not a whole number of `M`");
+        assert_eq!(err.span(), 3..7);
+    }
+
+    fn spec_range_parser_test(spec: &str) -> Result<(Bound<u64>, Bound<u64>), String> {
+        spec_range_parser(spec, |s| parse_weight(s, Syntax::Lenient)).map_err(|err| err.summary())
+    }
+
     fn run_weight_test(spec: &str) -> Result<u64, String> {
-        spec_string_parser(spec, |s| match s {
-            "k" | "kilo" => Ok(1000),
-            "g" | "gram" => Ok(1),
-            "t" | "ton" => Ok(1000_000),
-            err => Err(format!("unknown specifier {}", err)),
-        })
+        parse_weight(spec, Syntax::Lenient).map_err(|err| err.summary())
+    }
+
+    fn parse_weight(spec: &str, syntax: Syntax) -> Result<u64, SpecError> {
+        spec_string_parser(spec, weight_unit, syntax)
+    }
+
+    const WEIGHT_UNITS: [(&str, u64); 6] = [
+        ("k", 1000),
+        ("kilo", 1000),
+        ("g", 1),
+        ("gram", 1),
+        ("t", 1_000_000),
+        ("ton", 1_000_000),
+    ];
+
+    fn weight_unit(s: &str) -> Result<u64, UnitError> {
+        WEIGHT_UNITS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| UnitError::unknown("weight", s, WEIGHT_UNITS.iter().map(|(n, _)| *n)))
+    }
+
+    #[test]
+    fn test_range_parser() {
+        let ans = spec_range_parser_test("3kilo..1ton").unwrap();
+        assert_eq!(ans, (Bound::Included(3000), Bound::Included(1_000_000)));
+
+        let ans = spec_range_parser_test("3kilo..").unwrap();
+        assert_eq!(ans, (Bound::Included(3000), Bound::Unbounded));
+
+        let ans = spec_range_parser_test("..1ton 4kilo").unwrap();
+        assert_eq!(ans, (Bound::Unbounded, Bound::Included(1_004_000)));
+
+        let ans = spec_range_parser_test("1k..1k").unwrap();
+        assert_eq!(ans, (Bound::Included(1000), Bound::Included(1000)));
+    }
+
+    #[test]
+    fn test_wrong_range() {
+        let ans = spec_range_parser_test("1ton..3kilo");
+        assert_eq!(
+            ans,
+            Err("`1ton..3kilo` is an empty range: minimum is greater than maximum".to_owned())
+        );
+
+        assert!(spec_range_parser_test("..").is_err());
+        assert!(spec_range_parser_test("1ton").is_err());
+        assert!(spec_range_parser_test("1tn..").is_err());
     }
 }
@@ -1,13 +1,32 @@
+/// A number is kept as written, like `1.5`,
+/// so that the parser can convert it exactly.
 #[derive(Debug, PartialEq)]
 pub enum SpecToken<'a> {
-    Number(u64),
+    Number(&'a str),
     Text(&'a str),
+    /// `+` or `-` before a term
+    Sign(&'a str),
+    /// one of `<`, `<=`, `>`, `>=` and `=`
+    Compare(&'a str),
+}
+
+impl<'a> SpecToken<'a> {
+    pub fn as_str(&self) -> &'a str {
+        match self {
+            SpecToken::Number(s)
+            | SpecToken::Text(s)
+            | SpecToken::Sign(s)
+            | SpecToken::Compare(s) => s,
+        }
+    }
 }
 
 enum State {
     Init,
     Number,
     Text,
+    Sign,
+    Compare,
 }
 
 pub struct SpecTokenizer<'a> {
@@ -26,49 +45,87 @@ impl<'a> SpecTokenizer<'a> {
             end: 0,
         }
     }
+
+    /// A number is a sequence of digits with at most
+    /// one decimal point, followed by at least one digit.
+    /// A text is a sequence of letters, optionally after
+    /// a `%`, like `%fs`. Any other character that is not
+    /// a letter, a sign or a comparison operator is a separator.
     fn take_next(&mut self) {
+        let bytes = self.string.as_bytes();
         self.begin = self.end;
         self.state = State::Init;
-        let mut end = true;
-        for ch in self.string[self.begin..].chars() {
-            match self.state {
-                State::Init => {
-                    if ch.is_ascii_digit() {
-                        self.state = State::Number;
-                        self.end = self.begin;
-                    } else if ch.is_ascii_alphabetic() {
-                        self.state = State::Text;
-                        self.end = self.begin;
-                    } else {
-                        self.begin += 1;
-                    }
-                }
-                State::Number => {
-                    self.end += 1;
-                    if !ch.is_numeric() {
-                        end = false;
-                        break;
-                    }
-                }
-                State::Text => {
-                    self.end += 1;
-                    if !ch.is_ascii_alphabetic() {
-                        end = false;
-                        break;
-                    }
+        while self.begin < bytes.len() && !self.starts_token(self.begin) {
+            self.begin += 1;
+        }
+
+        let mut end = self.begin;
+        if end == bytes.len() {
+            self.end = end;
+            return;
+        }
+        if bytes[end].is_ascii_alphabetic() || bytes[end] == b'%' {
+            self.state = State::Text;
+            end += 1;
+            while end < bytes.len() && bytes[end].is_ascii_alphabetic() {
+                end += 1;
+            }
+        } else if is_sign(bytes[end]) {
+            self.state = State::Sign;
+            end += 1;
+        } else if is_compare(bytes[end]) {
+            self.state = State::Compare;
+            end += 1;
+            if bytes[end - 1] != b'=' && bytes.get(end) == Some(&b'=') {
+                end += 1;
+            }
+        } else {
+            self.state = State::Number;
+            let mut decimal = false;
+            while end < bytes.len() {
+                if bytes[end].is_ascii_digit() {
+                    end += 1;
+                } else if bytes[end] == b'.' && !decimal && self.is_digit(end + 1) {
+                    decimal = true;
+                    end += 1;
+                } else {
+                    break;
                 }
             }
         }
-        if end {
-            self.end = self.string.len();
-        }
+        self.end = end;
+    }
+
+    fn starts_token(&self, index: usize) -> bool {
+        let ch = self.string.as_bytes()[index];
+        ch.is_ascii_alphanumeric()
+            || ch == b'%'
+            || is_sign(ch)
+            || is_compare(ch)
+            || (ch == b'.' && self.is_digit(index + 1))
+    }
+
+    fn is_digit(&self, index: usize) -> bool {
+        self.string
+            .as_bytes()
+            .get(index)
+            .is_some_and(u8::is_ascii_digit)
     }
 }
 
+fn is_sign(ch: u8) -> bool {
+    ch == b'+' || ch == b'-'
+}
+
+fn is_compare(ch: u8) -> bool {
+    ch == b'<' || ch == b'>' || ch == b'='
+}
+
+/// Give each token with its byte offset in the string.
 impl<'a> Iterator for SpecTokenizer<'a> {
-    type Item = SpecToken<'a>;
+    type Item = (usize, SpecToken<'a>);
 
-    fn next(&mut self) -> Option<SpecToken<'a>> {
+    fn next(&mut self) -> Option<Self::Item> {
         self.take_next();
         if self.begin == self.string.len() {
             return None;
@@ -76,14 +133,13 @@ impl<'a> Iterator for SpecTokenizer<'a> {
 
         let tmp = &self.string[self.begin..self.end];
         let out = match self.state {
-            State::Number => {
-                let n: u64 = tmp.parse().unwrap();
-                SpecToken::Number(n)
-            }
+            State::Number => SpecToken::Number(tmp),
             State::Text => SpecToken::Text(tmp),
+            State::Sign => SpecToken::Sign(tmp),
+            State::Compare => SpecToken::Compare(tmp),
             State::Init => panic!(),
         };
-        Some(out)
+        Some((self.begin, out))
     }
 }
 
@@ -95,12 +151,15 @@ mod test {
     #[test]
     fn test_specification_parser() {
         let spec = "56year,,,12month++,+,5day";
-        let mut tokenizer = SpecTokenizer::new(spec);
-        assert_eq!(tokenizer.next(), Some(SpecToken::Number(56)));
+        let mut tokenizer = SpecTokenizer::new(spec).map(|(_, token)| token);
+        assert_eq!(tokenizer.next(), Some(SpecToken::Number("56")));
         assert_eq!(tokenizer.next(), Some(SpecToken::Text("year")));
-        assert_eq!(tokenizer.next(), Some(SpecToken::Number(12)));
+        assert_eq!(tokenizer.next(), Some(SpecToken::Number("12")));
         assert_eq!(tokenizer.next(), Some(SpecToken::Text("month")));
-        assert_eq!(tokenizer.next(), Some(SpecToken::Number(5)));
+        assert_eq!(tokenizer.next(), Some(SpecToken::Sign("+")));
+        assert_eq!(tokenizer.next(), Some(SpecToken::Sign("+")));
+        assert_eq!(tokenizer.next(), Some(SpecToken::Sign("+")));
+        assert_eq!(tokenizer.next(), Some(SpecToken::Number("5")));
         assert_eq!(tokenizer.next(), Some(SpecToken::Text("day")));
         assert_eq!(tokenizer.next(), None);
     }
@@ -112,6 +171,77 @@ mod test {
         assert_eq!(tokenizer.next(), None);
     }
 
+    #[test]
+    fn test_decimal_numbers() {
+        let spec = "1.5gb 2.,3d .5h 4.x";
+        let tokens: Vec<SpecToken> = SpecTokenizer::new(spec).map(|(_, token)| token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                SpecToken::Number("1.5"),
+                SpecToken::Text("gb"),
+                SpecToken::Number("2"),
+                SpecToken::Number("3"),
+                SpecToken::Text("d"),
+                SpecToken::Number(".5"),
+                SpecToken::Text("h"),
+                SpecToken::Number("4"),
+                SpecToken::Text("x"),
+            ]
+        );
+
+        // non ascii characters are separators too
+        let tokens: Vec<(usize, SpecToken)> = SpecTokenizer::new("3\u{e8}5d").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, SpecToken::Number("3")),
+                (3, SpecToken::Number("5")),
+                (4, SpecToken::Text("d"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let spec = "<=1d-2h >3 = >=<";
+        let tokens: Vec<SpecToken> = SpecTokenizer::new(spec).map(|(_, token)| token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                SpecToken::Compare("<="),
+                SpecToken::Number("1"),
+                SpecToken::Text("d"),
+                SpecToken::Sign("-"),
+                SpecToken::Number("2"),
+                SpecToken::Text("h"),
+                SpecToken::Compare(">"),
+                SpecToken::Number("3"),
+                SpecToken::Compare("="),
+                SpecToken::Compare(">="),
+                SpecToken::Compare("<"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percent() {
+        let spec = "1% 0.5%fs 2kb%";
+        let tokens: Vec<SpecToken> = SpecTokenizer::new(spec).map(|(_, token)| token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                SpecToken::Number("1"),
+                SpecToken::Text("%"),
+                SpecToken::Number("0.5"),
+                SpecToken::Text("%fs"),
+                SpecToken::Number("2"),
+                SpecToken::Text("kb"),
+                SpecToken::Text("%"),
+            ]
+        );
+    }
+
     #[test]
     fn test_take_next() {
         let spec = "55year,12month+5day";
@@ -124,6 +254,8 @@ mod test {
         run_take_next(&mut tokenizer, 7, 9);
         // month
         run_take_next(&mut tokenizer, 9, 14);
+        // +
+        run_take_next(&mut tokenizer, 14, 15);
         // 5
         run_take_next(&mut tokenizer, 15, 16);
         // day
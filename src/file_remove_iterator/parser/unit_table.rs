@@ -0,0 +1,320 @@
+use std::convert::TryFrom;
+
+use super::spec_error::{SpecError, UnitError};
+use super::specification_string_parser::{spec_signed_parser, spec_string_parser, Syntax};
+
+pub(crate) const BYTE: u64 = 1;
+
+//standard SI sizes
+pub(crate) const KILO_BYTE: u64 = 1000 * BYTE;
+pub(crate) const MEGA_BYTE: u64 = 1000 * KILO_BYTE;
+pub(crate) const GIGA_BYTE: u64 = 1000 * MEGA_BYTE;
+pub(crate) const TERA_BYTE: u64 = 1000 * GIGA_BYTE;
+pub(crate) const PETA_BYTE: u64 = 1000 * TERA_BYTE;
+
+//binary sizes
+pub(crate) const KIBI_BYTE: u64 = 1024 * BYTE;
+pub(crate) const MEBI_BYTE: u64 = 1024 * KIBI_BYTE;
+pub(crate) const GIBI_BYTE: u64 = 1024 * MEBI_BYTE;
+pub(crate) const TEBI_BYTE: u64 = 1024 * GIBI_BYTE;
+pub(crate) const PEBI_BYTE: u64 = 1024 * TEBI_BYTE;
+
+pub(crate) const SECONDS_IN_MINUTE: u64 = 60;
+pub(crate) const SECONDS_IN_HOUR: u64 = 60 * SECONDS_IN_MINUTE;
+pub(crate) const SECONDS_IN_DAY: u64 = 24 * SECONDS_IN_HOUR;
+pub(crate) const SECONDS_IN_WEEK: u64 = 7 * SECONDS_IN_DAY;
+pub(crate) const MONTHS_IN_YEAR: u64 = 12;
+
+const SIZE_UNITS: [(&str, u64, u64); 21] = [
+    ("b", BYTE, 0),
+    // standard SI
+    ("kb", KILO_BYTE, 0),
+    ("kilo", KILO_BYTE, 0),
+    ("mb", MEGA_BYTE, 0),
+    ("mega", MEGA_BYTE, 0),
+    ("gb", GIGA_BYTE, 0),
+    ("giga", GIGA_BYTE, 0),
+    ("tb", TERA_BYTE, 0),
+    ("tera", TERA_BYTE, 0),
+    ("pb", PETA_BYTE, 0),
+    ("peta", PETA_BYTE, 0),
+    // binary
+    ("kib", KIBI_BYTE, 0),
+    ("kibi", KIBI_BYTE, 0),
+    ("mib", MEBI_BYTE, 0),
+    ("mebi", MEBI_BYTE, 0),
+    ("gib", GIBI_BYTE, 0),
+    ("gibi", GIBI_BYTE, 0),
+    ("tib", TEBI_BYTE, 0),
+    ("tebi", TEBI_BYTE, 0),
+    ("pib", PEBI_BYTE, 0),
+    ("pebi", PEBI_BYTE, 0),
+];
+
+/// Each unit with its length in seconds and in
+/// calendar months, only one of the two is not zero.
+const TIME_UNITS: [(&str, u64, u64); 14] = [
+    ("s", 1, 0),
+    ("second", 1, 0),
+    ("m", SECONDS_IN_MINUTE, 0),
+    ("minute", SECONDS_IN_MINUTE, 0),
+    ("h", SECONDS_IN_HOUR, 0),
+    ("hour", SECONDS_IN_HOUR, 0),
+    ("d", SECONDS_IN_DAY, 0),
+    ("day", SECONDS_IN_DAY, 0),
+    ("w", SECONDS_IN_WEEK, 0),
+    ("week", SECONDS_IN_WEEK, 0),
+    ("M", 0, 1),
+    ("month", 0, 1),
+    ("y", 0, MONTHS_IN_YEAR),
+    ("year", 0, MONTHS_IN_YEAR),
+];
+
+/// The units of a quantity, each with its value in
+/// the smallest unit, used to parse specification
+/// strings like `1gb500mb`. Aliases are just other
+/// names with the same value. A unit can also count
+/// calendar months, whose length in seconds varies:
+/// those are only accepted by parse_calendar.
+///
+/// ```
+/// use rmd::file_remove_iterator::{Syntax, UnitTable};
+///
+/// let mut table = UnitTable::size();
+/// table.register("sector", 512).unwrap();
+/// assert_eq!(table.parse("2sector", Syntax::Lenient).unwrap(), 1024);
+///
+/// let mut table = UnitTable::time();
+/// table.register_spec("sprint", "14d").unwrap();
+/// assert_eq!(table.parse("1sprint - 1w", Syntax::Strict).unwrap(), 7 * 24 * 3600);
+/// table.register_spec("quarter", "3M").unwrap();
+/// assert_eq!(table.parse_calendar("1y 1quarter 1d", Syntax::Lenient).unwrap(), (15, 24 * 3600));
+/// ```
+#[derive(Clone, Debug)]
+pub struct UnitTable {
+    kind: String,
+    units: Vec<Unit>,
+}
+
+#[derive(Clone, Debug)]
+struct Unit {
+    name: String,
+    value: u64,
+    months: u64,
+}
+
+impl UnitTable {
+    /// An empty table, kind names the quantity in
+    /// error messages, like `unknown size unit`.
+    /// Unit names are case sensitive.
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_owned(),
+            units: Vec::new(),
+        }
+    }
+
+    /// Sizes in bytes, with SI (`kb`, `mb`...) and
+    /// binary (`kib`, `mib`...) units.
+    pub fn size() -> Self {
+        Self::from_units("size", &SIZE_UNITS)
+    }
+
+    /// Times in seconds, from `s` to `w`, and the calendar
+    /// units `M` (month) and `y` (year), see parse_calendar.
+    /// `m` is a minute.
+    pub fn time() -> Self {
+        Self::from_units("time", &TIME_UNITS)
+    }
+
+    fn from_units(kind: &str, units: &[(&str, u64, u64)]) -> Self {
+        let mut output = Self::new(kind);
+        output.units = units
+            .iter()
+            .map(|(name, value, months)| Unit {
+                name: (*name).to_owned(),
+                value: *value,
+                months: *months,
+            })
+            .collect();
+        output
+    }
+
+    /// Add a unit, or change the value of an existing one.
+    /// Names are made of ASCII letters only.
+    pub fn register(&mut self, name: &str, value: u64) -> Result<(), UnitError> {
+        self.insert(name, value, 0)
+    }
+
+    /// Add a unit counting calendar months, like a
+    /// quarter as 3, or change an existing one.
+    pub fn register_months(&mut self, name: &str, months: u64) -> Result<(), UnitError> {
+        self.insert(name, 0, months)
+    }
+
+    /// Add alias as another name of an existing unit.
+    pub fn register_alias(&mut self, alias: &str, name: &str) -> Result<(), UnitError> {
+        let unit = self.find(name)?;
+        let (value, months) = (unit.value, unit.months);
+        self.insert(alias, value, months)
+    }
+
+    /// Add a unit whose value is given by a specification
+    /// string of the units already in the table, like
+    /// `sprint` as `14d` or `quarter` as `3M`. Neither
+    /// the months nor the rest can be negative.
+    pub fn register_spec(&mut self, name: &str, spec: &str) -> Result<(), SpecError> {
+        let (months, value) = self.parse_calendar(spec, Syntax::Lenient)?;
+        // both are within u64 in absolute value
+        let (months, value) = match (u64::try_from(months), u64::try_from(value)) {
+            (Ok(months), Ok(value)) => (months, value),
+            _ => {
+                let err_msg = format!("`{}` is negative", spec);
+                return Err(SpecError::new(spec, 0..spec.len(), err_msg));
+            }
+        };
+        self.insert(name, value, months)
+            .map_err(|err| SpecError::from_unit(spec, 0..spec.len(), err))
+    }
+
+    fn insert(&mut self, name: &str, value: u64, months: u64) -> Result<(), UnitError> {
+        if name.is_empty() || !name.bytes().all(|ch| ch.is_ascii_alphabetic()) {
+            let err_msg = format!(
+                "`{}` is not a valid unit name, only ASCII letters are allowed",
+                name
+            );
+            return Err(UnitError::new(err_msg));
+        }
+        match self.units.iter_mut().find(|unit| unit.name == name) {
+            Some(unit) => {
+                unit.value = value;
+                unit.months = months;
+            }
+            None => self.units.push(Unit {
+                name: name.to_owned(),
+                value,
+                months,
+            }),
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Result<&Unit, UnitError> {
+        self.units
+            .iter()
+            .find(|unit| unit.name == name)
+            .ok_or_else(|| UnitError::unknown(&self.kind, name, self.names()))
+    }
+
+    /// The value of the unit called name, an error
+    /// for a unit counting calendar months.
+    pub fn lookup(&self, name: &str) -> Result<u64, UnitError> {
+        let unit = self.find(name)?;
+        if unit.months != 0 {
+            let err_msg = format!(
+                "`{}` counts calendar months, it has no fixed {}",
+                name, self.kind
+            );
+            return Err(UnitError::new(err_msg));
+        }
+        Ok(unit.value)
+    }
+
+    /// Parse a specification string, see spec_string_parser.
+    pub fn parse(&self, spec: &str, syntax: Syntax) -> Result<u64, SpecError> {
+        spec_string_parser(spec, |name| self.lookup(name), syntax)
+    }
+
+    /// Parse a specification string into calendar months
+    /// and the remaining value, both may be negative with
+    /// strict syntax, like `1M - 1d`. Calendar units must
+    /// add up to a whole number of months.
+    pub fn parse_calendar(&self, spec: &str, syntax: Syntax) -> Result<(i128, i128), SpecError> {
+        let months = spec_signed_parser(spec, |name| Ok(self.find(name)?.months), syntax, true)?;
+        let value = spec_signed_parser(spec, |name| Ok(self.find(name)?.value), syntax, false)?;
+        Ok((months, value))
+    }
+
+    /// The name of each unit, aliases included.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.units.iter().map(|unit| unit.name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_builtin_tables() {
+        let size = UnitTable::size();
+        assert_eq!(size.parse("1kb 4mib", Syntax::Lenient), Ok(4_195_304));
+        assert_eq!(size.parse("1gib", Syntax::Lenient), Ok(GIBI_BYTE));
+        let err = size.parse("1GiB", Syntax::Lenient).unwrap_err();
+        assert_eq!(err.suggestion(), Some("gib"));
+
+        let time = UnitTable::time();
+        assert_eq!(time.parse("1m", Syntax::Lenient), Ok(SECONDS_IN_MINUTE));
+        assert!(time.parse("1M", Syntax::Lenient).is_err());
+        assert_eq!(
+            time.parse_calendar("1y 1M 1d", Syntax::Lenient),
+            Ok((13, SECONDS_IN_DAY as i128))
+        );
+        let err = time.parse("1D", Syntax::Lenient).unwrap_err();
+        assert_eq!(err.suggestion(), Some("d"));
+    }
+
+    #[test]
+    fn test_custom_units() {
+        let mut table = UnitTable::new("length");
+        table.register("m", 1000).unwrap();
+        table.register("mm", 1).unwrap();
+        table.register_alias("meter", "m").unwrap();
+        table.register_spec("km", "1000m").unwrap();
+        assert_eq!(table.parse("1km 2meter 3mm", Syntax::Strict), Ok(1_002_003));
+
+        // register again to change a value
+        table.register("m", 1).unwrap();
+        assert_eq!(table.lookup("m"), Ok(1));
+        // aliases keep their own value
+        assert_eq!(table.lookup("meter"), Ok(1000));
+
+        assert!(table.register("m2", 1).is_err());
+        assert!(table.register_alias("inch", "foot").is_err());
+        assert!(table.register_spec("mile", "1 league").is_err());
+        assert!(table.lookup("M").is_err());
+        assert!(table.lookup("KM").is_err());
+    }
+
+    #[test]
+    fn test_calendar_units() {
+        let mut table = UnitTable::time();
+        table.register_months("quarter", 3).unwrap();
+        table.register_alias("mo", "month").unwrap();
+        table.register_spec("sprint", "2w").unwrap();
+        table.register_spec("term", "1M 1w").unwrap();
+        table.register_spec("shift", "1d-16h").unwrap();
+        assert_eq!(
+            table.parse("1shift", Syntax::Lenient),
+            Ok(8 * SECONDS_IN_HOUR)
+        );
+        let err = table.register_spec("late", "1M-1d").unwrap_err();
+        assert_eq!(err.summary(), "`1M-1d` is negative");
+        assert!(table.register_spec("early", "1h-1d").is_err());
+        assert_eq!(
+            table.parse_calendar("1quarter 2mo 1sprint", Syntax::Lenient),
+            Ok((5, 2 * SECONDS_IN_WEEK as i128))
+        );
+        assert_eq!(
+            table.parse_calendar("1term - 1d", Syntax::Strict),
+            Ok((1, (SECONDS_IN_WEEK - SECONDS_IN_DAY) as i128))
+        );
+        assert!(table.parse_calendar("0.5quarter", Syntax::Lenient).is_err());
+        assert!(table.parse("1term", Syntax::Lenient).is_err());
+        assert_eq!(
+            table.parse("1sprint", Syntax::Lenient),
+            Ok(2 * SECONDS_IN_WEEK)
+        );
+    }
+}
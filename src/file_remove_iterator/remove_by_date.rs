@@ -1,82 +1,195 @@
-use std::io::{Error, ErrorKind};
+use std::cmp::Reverse;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fs::Metadata;
+use std::io::Error;
+use std::ops::{Bound, RangeBounds};
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use super::parser::spec_string_parser;
+use chrono::{DateTime, Local, Months};
+
+use super::parser::{date_parser, spec_range_parser, SpecError, Syntax, UnitTable};
+use crate::dir_fd::DirFd;
 use crate::file_remove_iterator::file_remove::FileRemove;
 
-const SECONDS_IN_MINUTE: u64 = 60;
-const SECONDS_IN_HOUR: u64 = 60 * SECONDS_IN_MINUTE;
-const SECONDS_IN_DAY: u64 = 24 * SECONDS_IN_HOUR;
-const SECONDS_IN_WEEK: u64 = 7 * SECONDS_IN_DAY;
-const SECONDS_IN_MONTH: u64 = 30 * SECONDS_IN_DAY;
-const SECONDS_IN_YEAR: u64 = 365 * SECONDS_IN_DAY;
+/// The file timestamp compared by TimeRemove.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TimeField {
+    #[default]
+    Accessed,
+    Modified,
+    Changed,
+    Created,
+}
+
+impl TimeField {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "atime" => Some(TimeField::Accessed),
+            "mtime" => Some(TimeField::Modified),
+            "ctime" => Some(TimeField::Changed),
+            "btime" => Some(TimeField::Created),
+            _ => None,
+        }
+    }
+
+    fn get_time(self, metadata: &Metadata) -> std::io::Result<SystemTime> {
+        match self {
+            TimeField::Accessed => metadata.accessed(),
+            TimeField::Modified => metadata.modified(),
+            TimeField::Changed => {
+                let offset = Duration::new(metadata.ctime().unsigned_abs(), 0);
+                let time = if metadata.ctime() < 0 {
+                    UNIX_EPOCH - offset
+                } else {
+                    UNIX_EPOCH + offset
+                };
+                Ok(time + Duration::new(0, metadata.ctime_nsec() as u32))
+            }
+            TimeField::Created => metadata.created(),
+        }
+    }
+}
 
+/// Remove files whose timestamp is in the given bounds.
+/// Time specifications are converted into points in time
+/// when the remover is built, so a timestamp in the future
+/// is simply newer than any specification.
 pub struct TimeRemove {
-    time: Duration,
-    older: bool,
-    now: SystemTime,
+    after: Bound<SystemTime>,
+    before: Bound<SystemTime>,
+    field: TimeField,
 }
 
 impl TimeRemove {
-    pub fn new(time: &str, older: bool) -> std::io::Result<Self> {
-        let ans = Self::convert_time_spec(time);
+    /// Remove files older, or newer, than time, whose
+    /// units are looked up in units, see UnitTable::time.
+    pub fn new(
+        time: &str,
+        older: bool,
+        syntax: Syntax,
+        units: &UnitTable,
+    ) -> std::io::Result<Self> {
+        let ans = resolve_time_spec(time, Local::now(), syntax, units);
         match ans {
             Ok(time) => Ok(Self::factory(time, older)),
             Err(msg) => Err(Self::error_factory(msg)),
         }
     }
 
-    fn factory(time: u64, older: bool) -> Self {
-        let time = Duration::new(time, 0);
+    /// Remove files whose age is in the given range,
+    /// like `7d..30d`, `7d..` or `..30d`, where both ends
+    /// are included, or satisfies the given comparison,
+    /// like `>2w`.
+    pub fn from_range(
+        range_spec: &str,
+        syntax: Syntax,
+        units: &UnitTable,
+    ) -> std::io::Result<Self> {
+        let now = Local::now();
+        // the younger end of the range is the later point in time
+        let ans = spec_range_parser(range_spec, |s| {
+            resolve_time_spec(s, now, syntax, units).map(Reverse)
+        });
+        match ans {
+            Ok((min, max)) => Ok(TimeRemove {
+                after: max.map(|Reverse(t)| t),
+                before: min.map(|Reverse(t)| t),
+                field: TimeField::default(),
+            }),
+            Err(msg) => Err(Self::error_factory(msg)),
+        }
+    }
+
+    /// Use the timestamp of reference as threshold, like
+    /// `find -newer`. The same timestamp field is then
+    /// compared on every file, and a file as old as the
+    /// reference is never removed.
+    pub fn from_reference(
+        reference: &Path,
+        older: bool,
+        field: TimeField,
+    ) -> std::io::Result<Self> {
+        let metadata = reference.metadata()?;
+        let time = field.get_time(&metadata)?;
+        Ok(Self::factory(time, older).with_field(field))
+    }
+
+    /// Select which file timestamp is compared,
+    /// by default the last access time.
+    pub fn with_field(mut self, field: TimeField) -> Self {
+        self.field = field;
+        self
+    }
+
+    fn factory(time: SystemTime, older: bool) -> Self {
+        let (after, before) = if older {
+            (Bound::Unbounded, Bound::Excluded(time))
+        } else {
+            (Bound::Excluded(time), Bound::Unbounded)
+        };
         TimeRemove {
-            time,
-            older,
-            now: SystemTime::now(),
+            after,
+            before,
+            field: TimeField::default(),
         }
     }
 
-    fn error_factory(msg: String) -> Error {
-        Error::new(ErrorKind::Other, msg)
+    fn error_factory(msg: SpecError) -> Error {
+        Error::other(msg)
     }
+}
 
-    fn convert_time_spec(time: &str) -> Result<u64, String> {
-        spec_string_parser(time, |s| match s {
-            "s" | "second" => Ok(1),
-            "m" | "minute" => Ok(SECONDS_IN_MINUTE),
-            "h" | "hour" => Ok(SECONDS_IN_HOUR),
-            "d" | "day" => Ok(SECONDS_IN_DAY),
-            "w" | "week" => Ok(SECONDS_IN_WEEK),
-            "M" | "month" => Ok(SECONDS_IN_MONTH),
-            "y" | "year" => Ok(SECONDS_IN_YEAR),
-            _ => {
-                let err_msg = format!("unknown time specifier {}", s);
-                Err(err_msg)
-            }
-        })
+/// Convert a time specification into a point in time.
+/// It can be an absolute date, see date_parser, or an
+/// amount of time in the past from now. Months and years
+/// follow the calendar: one month ago is the same day
+/// of the previous month, or its last day if shorter.
+fn resolve_time_spec(
+    spec: &str,
+    now: DateTime<Local>,
+    syntax: Syntax,
+    units: &UnitTable,
+) -> Result<SystemTime, SpecError> {
+    let whole_spec = |msg| SpecError::new(spec, 0..spec.len(), msg);
+    if let Some(date) = date_parser(spec, now).map_err(whole_spec)? {
+        return Ok(date.into());
     }
 
-    fn get_time_diff(&self, path: &Path) -> std::io::Result<Duration> {
-        let metadata = path.metadata()?;
-        let access = metadata.accessed()?;
-        let diff = self.now.duration_since(access).unwrap();
-        Ok(diff)
+    let (months, seconds) = units.parse_calendar(spec, syntax)?;
+    let date = u32::try_from(months.unsigned_abs())
+        .ok()
+        .and_then(|count| {
+            if months < 0 {
+                now.checked_add_months(Months::new(count))
+            } else {
+                now.checked_sub_months(Months::new(count))
+            }
+        })
+        .ok_or_else(|| whole_spec(format!("`{}` is too far in the past", spec)))?;
+    let time: SystemTime = date.into();
+    // the parser keeps seconds within u64
+    let offset = Duration::new(seconds.unsigned_abs() as u64, 0);
+    let time = if seconds < 0 {
+        time.checked_add(offset)
+            .ok_or_else(|| whole_spec(format!("`{}` is negative", spec)))?
+    } else {
+        time.checked_sub(offset).unwrap_or(UNIX_EPOCH)
+    };
+    if time > SystemTime::from(now) {
+        Err(whole_spec(format!("`{}` is negative", spec)))
+    } else {
+        Ok(time)
     }
 }
 
 impl FileRemove for TimeRemove {
-    fn remove(&mut self, path: &Path) -> std::io::Result<bool> {
-        let time_since_access = self.get_time_diff(path)?;
-        if self.older {
-            if time_since_access > self.time {
-                return Ok(true);
-            }
-        } else {
-            if time_since_access < self.time {
-                return Ok(true);
-            }
-        }
-        Ok(false)
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, _: &Path) -> std::io::Result<bool> {
+        let metadata = dir.file_metadata(name)?;
+        let time = self.field.get_time(&metadata)?;
+        Ok((self.after, self.before).contains(&time))
     }
 }
 
@@ -84,16 +197,31 @@ impl FileRemove for TimeRemove {
 mod test {
 
     use super::*;
+    use crate::file_remove_iterator::file_remove::remove_path;
+    use crate::file_remove_iterator::parser::unit_table::{SECONDS_IN_DAY, SECONDS_IN_WEEK};
+    use chrono::TimeZone;
+    use std::fs::{File, FileTimes};
+    use tempfile::TempDir;
+
+    fn convert_time_spec(time: &str, syntax: Syntax) -> Result<(i128, i128), SpecError> {
+        UnitTable::time().parse_calendar(time, syntax)
+    }
 
     #[test]
     fn test_time_convertion() {
-        assert_eq!(TimeRemove::convert_time_spec("1y1M1w"), Ok(34732800));
+        assert_eq!(
+            convert_time_spec("1y1M1w", Syntax::Lenient),
+            Ok((13, 604800))
+        );
         // 67 day + 1 year -> the order does not metter
-        assert_eq!(TimeRemove::convert_time_spec("67d1y"), Ok(37324800));
+        assert_eq!(
+            convert_time_spec("67d1y", Syntax::Lenient),
+            Ok((12, 5788800))
+        );
         // 1 year + 1 month + 1 week + 1 day + 1 hour + 1 minute + 1 second
         assert_eq!(
-            TimeRemove::convert_time_spec("1y1M1w1d1h1m1s"),
-            Ok(34822861)
+            convert_time_spec("1y1M1w1d1h1m1s", Syntax::Lenient),
+            Ok((13, 694861))
         );
     }
 
@@ -101,16 +229,171 @@ mod test {
     fn test_long_time_convertion() {
         //accept both long and short time specifier
         assert_eq!(
-            TimeRemove::convert_time_spec("1year+1month+1w"),
-            Ok(34732800)
+            convert_time_spec("1year+1month+1w", Syntax::Lenient),
+            Ok((13, 604800))
         );
 
         // another test to ensure that non alphanumeric characters are ignored
         assert_eq!(
-            TimeRemove::convert_time_spec(
-                "1 year + 1 month + 1 week + 1 day + 1 hour + 1 minute + 1 second"
+            convert_time_spec(
+                "1 year + 1 month + 1 week + 1 day + 1 hour + 1 minute + 1 second",
+                Syntax::Lenient
             ),
-            Ok(34822861)
+            Ok((13, 694861))
         )
     }
+
+    #[test]
+    fn test_decimal_time_convertion() {
+        assert_eq!(convert_time_spec("0.5d", Syntax::Lenient), Ok((0, 43200)));
+        assert_eq!(convert_time_spec("1.5y", Syntax::Lenient), Ok((18, 0)));
+        assert_eq!(
+            convert_time_spec("1.5h0.5s", Syntax::Lenient),
+            Ok((0, 5401))
+        );
+        assert!(convert_time_spec("0.5M", Syntax::Lenient).is_err());
+        assert_eq!(convert_time_spec("1d-2h", Syntax::Lenient), Ok((0, 79200)));
+        assert_eq!(convert_time_spec("1d - 2h", Syntax::Strict), Ok((0, 79200)));
+        assert_eq!(
+            convert_time_spec("1M - 1d", Syntax::Strict),
+            Ok((1, -86400))
+        );
+        assert!(convert_time_spec("99999999999999999999w", Syntax::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_calendar_time() {
+        let now = Local.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
+        let time_units = UnitTable::time();
+        let resolve = |spec| resolve_time_spec(spec, now, Syntax::Lenient, &time_units).unwrap();
+
+        let expected = Local.with_ymd_and_hms(2024, 2, 29, 12, 0, 0).unwrap();
+        assert_eq!(resolve("1M"), SystemTime::from(expected));
+
+        let expected = Local.with_ymd_and_hms(2023, 2, 28, 11, 0, 0).unwrap();
+        assert_eq!(resolve("1y1M1h"), SystemTime::from(expected));
+
+        let expected = Local.with_ymd_and_hms(2025, 1, 31, 0, 0, 0).unwrap();
+        assert_eq!(resolve("2025-01-31"), SystemTime::from(expected));
+
+        let expected = Local.with_ymd_and_hms(2024, 3, 30, 0, 0, 0).unwrap();
+        assert_eq!(resolve("yesterday"), SystemTime::from(expected));
+
+        assert!(resolve_time_spec("2025-02-30", now, Syntax::Lenient, &time_units).is_err());
+
+        let mut units = UnitTable::time();
+        units.register_spec("sprint", "14d").unwrap();
+        let expected = Local.with_ymd_and_hms(2024, 3, 17, 12, 0, 0).unwrap();
+        let sprint = resolve_time_spec("1sprint", now, Syntax::Lenient, &units).unwrap();
+        assert_eq!(sprint, SystemTime::from(expected));
+
+        let expected = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let strict = resolve_time_spec("1M - 1d", now, Syntax::Strict, &time_units).unwrap();
+        assert_eq!(strict, SystemTime::from(expected));
+        assert!(resolve_time_spec("1d - 1M", now, Syntax::Strict, &time_units).is_err());
+    }
+
+    #[test]
+    fn test_time_range() {
+        let range = TimeRemove::from_range("1w..4w", Syntax::Lenient, &UnitTable::time()).unwrap();
+        match (range.after, range.before) {
+            (Bound::Included(after), Bound::Included(before)) => {
+                let diff = before.duration_since(after).unwrap();
+                assert_eq!(diff, Duration::new(3 * SECONDS_IN_WEEK, 0));
+            }
+            _ => panic!("expected a closed range"),
+        }
+
+        let range = TimeRemove::from_range("7d..", Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert_eq!(range.after, Bound::Unbounded);
+
+        assert!(TimeRemove::from_range("30d..7d", Syntax::Lenient, &UnitTable::time()).is_err());
+
+        // older than two weeks
+        let range = TimeRemove::from_range(">2w", Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert_eq!(range.after, Bound::Unbounded);
+        assert!(matches!(range.before, Bound::Excluded(_)));
+
+        let range =
+            TimeRemove::from_range("<=2025-01-01", Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert!(matches!(range.after, Bound::Included(_)));
+        assert_eq!(range.before, Bound::Unbounded);
+    }
+
+    #[test]
+    fn test_time_field() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("file");
+        let file = File::create(&path).unwrap();
+        let old = SystemTime::now() - Duration::new(10 * SECONDS_IN_DAY, 0);
+        file.set_times(FileTimes::new().set_modified(old)).unwrap();
+
+        let mut by_access =
+            TimeRemove::new("1d", true, Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert!(!remove_path(&mut by_access, &path).unwrap());
+
+        let mut by_modify = TimeRemove::new("1d", true, Syntax::Lenient, &UnitTable::time())
+            .unwrap()
+            .with_field(TimeField::Modified);
+        assert!(remove_path(&mut by_modify, &path).unwrap());
+
+        let mut by_change = TimeRemove::new("1d", false, Syntax::Lenient, &UnitTable::time())
+            .unwrap()
+            .with_field(TimeField::Changed);
+        assert!(remove_path(&mut by_change, &path).unwrap());
+    }
+
+    #[test]
+    fn test_reference_file() {
+        let root = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let mut paths = Vec::new();
+        for (i, days) in [10, 5, 1].iter().enumerate() {
+            let path = root.path().join(format!("file_{}", i));
+            let file = File::create(&path).unwrap();
+            let time = now - Duration::new(days * SECONDS_IN_DAY, 0);
+            file.set_times(FileTimes::new().set_modified(time)).unwrap();
+            paths.push(path);
+        }
+
+        let mut older = TimeRemove::from_reference(&paths[1], true, TimeField::Modified).unwrap();
+        let mut newer = TimeRemove::from_reference(&paths[1], false, TimeField::Modified).unwrap();
+        let ans: Vec<(bool, bool)> = paths
+            .iter()
+            .map(|p| {
+                (
+                    remove_path(&mut older, p).unwrap(),
+                    remove_path(&mut newer, p).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ans, vec![(true, false), (false, false), (false, true)]);
+
+        let missing = root.path().join("missing");
+        assert!(TimeRemove::from_reference(&missing, true, TimeField::Modified).is_err());
+    }
+
+    #[test]
+    fn test_future_time() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("file");
+        let file = File::create(&path).unwrap();
+        let future = SystemTime::now() + Duration::new(365 * SECONDS_IN_DAY, 0);
+        let times = FileTimes::new().set_modified(future).set_accessed(future);
+        file.set_times(times).unwrap();
+
+        let mut older = TimeRemove::new("1d", true, Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert!(!remove_path(&mut older, &path).unwrap());
+
+        let mut newer = TimeRemove::new("1d", false, Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert!(remove_path(&mut newer, &path).unwrap());
+
+        let mut range =
+            TimeRemove::from_range("..1d", Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert!(remove_path(&mut range, &path).unwrap());
+
+        let mut range =
+            TimeRemove::from_range("0s..1d", Syntax::Lenient, &UnitTable::time()).unwrap();
+        assert!(!remove_path(&mut range, &path).unwrap());
+    }
 }
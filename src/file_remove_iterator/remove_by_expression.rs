@@ -0,0 +1,214 @@
+use std::ffi::OsStr;
+use std::io::Error;
+use std::path::Path;
+
+use super::file_remove::FileRemove;
+use super::parser::{
+    filter_expression_parser, ExpressionError, FilterExpr, Predicate, SpecError, Syntax, UnitTable,
+};
+use super::remove_by_date::{TimeField, TimeRemove};
+use super::remove_by_size::SizeRemove;
+use super::remove_composite::{Combine, CompositeRemove, NotRemove};
+use crate::dir_fd::DirFd;
+
+/// A FileRemove described by a filter expression, like
+/// `(older 1y or larger 1gb) and not ext pdf`.
+/// Available predicates are `older`, `newer`, `smaller`,
+/// `larger`, `size` and `age`, taking the same specification
+/// strings as the corresponding options, and `ext`, taking a
+/// file extension.
+pub struct ExpressionRemove {
+    remover: Box<dyn FileRemove>,
+}
+
+impl ExpressionRemove {
+    /// Sizes and ages in expression use the units
+    /// of size_units and time_units.
+    pub fn new(
+        expression: &str,
+        time_field: TimeField,
+        syntax: Syntax,
+        size_units: &UnitTable,
+        time_units: &UnitTable,
+    ) -> std::io::Result<Self> {
+        let tree = filter_expression_parser(expression).map_err(Error::other)?;
+        let context = Context {
+            expression,
+            time_field,
+            syntax,
+            size_units,
+            time_units,
+        };
+        let remover = compile(&context, tree).map_err(Error::other)?;
+        Ok(Self { remover })
+    }
+}
+
+/// What the predicates of an expression are compiled with.
+struct Context<'a> {
+    expression: &'a str,
+    time_field: TimeField,
+    syntax: Syntax,
+    size_units: &'a UnitTable,
+    time_units: &'a UnitTable,
+}
+
+impl FileRemove for ExpressionRemove {
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool, Error> {
+        self.remover.remove(dir, name, path)
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<(), Error> {
+        self.remover.enter_root(root)
+    }
+}
+
+struct ExtensionRemove {
+    extension: String,
+}
+
+impl FileRemove for ExtensionRemove {
+    fn remove(&mut self, _: &DirFd, name: &OsStr, _: &Path) -> Result<bool, Error> {
+        let ans = Path::new(name).extension() == Some(OsStr::new(&self.extension));
+        Ok(ans)
+    }
+}
+
+fn compile(context: &Context, tree: FilterExpr) -> Result<Box<dyn FileRemove>, ExpressionError> {
+    let output: Box<dyn FileRemove> = match tree {
+        FilterExpr::And(left, right) => {
+            let removers = vec![compile(context, *left)?, compile(context, *right)?];
+            Box::new(CompositeRemove::new(removers, Combine::All))
+        }
+        FilterExpr::Or(left, right) => {
+            let removers = vec![compile(context, *left)?, compile(context, *right)?];
+            Box::new(CompositeRemove::new(removers, Combine::Any))
+        }
+        FilterExpr::Not(inner) => Box::new(NotRemove::new(compile(context, *inner)?)),
+        FilterExpr::Predicate(predicate) => compile_predicate(context, predicate)?,
+    };
+    Ok(output)
+}
+
+fn compile_predicate(
+    context: &Context,
+    predicate: Predicate,
+) -> Result<Box<dyn FileRemove>, ExpressionError> {
+    let Context {
+        expression,
+        time_field,
+        syntax,
+        size_units,
+        time_units,
+    } = *context;
+    let argument = predicate.argument;
+    let output: std::io::Result<Box<dyn FileRemove>> = match predicate.name {
+        "older" => TimeRemove::new(argument, true, syntax, time_units)
+            .map(|r| Box::new(r.with_field(time_field)) as Box<dyn FileRemove>),
+        "newer" => TimeRemove::new(argument, false, syntax, time_units)
+            .map(|r| Box::new(r.with_field(time_field)) as Box<dyn FileRemove>),
+        "smaller" => SizeRemove::new(argument, true, syntax, size_units)
+            .map(|r| Box::new(r) as Box<dyn FileRemove>),
+        "larger" => SizeRemove::new(argument, false, syntax, size_units)
+            .map(|r| Box::new(r) as Box<dyn FileRemove>),
+        "size" => SizeRemove::from_range(argument, syntax, size_units)
+            .map(|r| Box::new(r) as Box<dyn FileRemove>),
+        "age" => TimeRemove::from_range(argument, syntax, time_units)
+            .map(|r| Box::new(r.with_field(time_field)) as Box<dyn FileRemove>),
+        "ext" => Ok(Box::new(ExtensionRemove {
+            extension: argument.trim_start_matches('.').to_owned(),
+        })),
+        name => {
+            let msg = format!("unknown predicate `{}`", name);
+            return Err(ExpressionError::new(
+                expression,
+                predicate.name_position,
+                msg,
+            ));
+        }
+    };
+    // point inside the argument when the error has a span
+    output.map_err(|err| {
+        match err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<SpecError>())
+        {
+            Some(err) => ExpressionError::new(
+                expression,
+                predicate.argument_position + err.span().start,
+                err.summary(),
+            ),
+            None => ExpressionError::new(expression, predicate.argument_position, err.to_string()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::file_remove_iterator::file_remove::remove_path;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_expression_remove() {
+        let root = TempDir::new().unwrap();
+        let files = [
+            ("small.txt", 10, false),
+            ("small.pdf", 10, false),
+            ("large.txt", 5000, true),
+            ("large.pdf", 5000, false),
+            ("medium.txt", 1000, false),
+        ];
+
+        let mut remove = ExpressionRemove::new(
+            "(smaller 1b or larger 4kb) and not ext pdf",
+            TimeField::default(),
+            Syntax::Lenient,
+            &UnitTable::size(),
+            &UnitTable::time(),
+        )
+        .unwrap();
+        let mut range = ExpressionRemove::new(
+            "size 500b..2kb",
+            TimeField::default(),
+            Syntax::Lenient,
+            &UnitTable::size(),
+            &UnitTable::time(),
+        )
+        .unwrap();
+        for (name, size, ans) in files.iter() {
+            let path = root.path().join(name);
+            let mut file = File::create(&path).unwrap();
+            file.write_all(&vec![0; *size]).unwrap();
+            assert_eq!(remove_path(&mut remove, &path).unwrap(), *ans, "{}", name);
+            assert_eq!(
+                remove_path(&mut range, &path).unwrap(),
+                *size == 1000,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let (size_units, time_units) = (UnitTable::size(), UnitTable::time());
+        let compile_error = |expression| {
+            let context = Context {
+                expression,
+                time_field: TimeField::default(),
+                syntax: Syntax::Lenient,
+                size_units: &size_units,
+                time_units: &time_units,
+            };
+            compile(&context, filter_expression_parser(expression).unwrap())
+                .err()
+                .unwrap()
+        };
+        assert_eq!(compile_error("older 1y or bigger 1gb").position(), 12);
+        assert_eq!(compile_error("older 1y or larger 1zb").position(), 20);
+    }
+}
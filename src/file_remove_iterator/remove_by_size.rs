@@ -1,80 +1,180 @@
+use std::ffi::OsStr;
 use std::fs::metadata;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
+use std::ops::{Bound, RangeBounds};
 use std::path::Path;
 
 use super::file_remove::FileRemove;
-use super::parser::spec_string_parser;
+use super::parser::{
+    spec_range_parser, spec_string_parser, SpecError, Syntax, UnitError, UnitTable,
+};
+use crate::dir_fd::DirFd;
 
-const BYTE: u64 = 1;
+/// Units worth one hundredth of the size of the
+/// filesystem holding the root, like `2%` or `0.5%fs`.
+/// Like the units of the table, they are case sensitive.
+const PERCENT_UNITS: [&str; 2] = ["%", "%fs"];
 
-//standard SI sizes
-const KILO_BYTE: u64 = 1000 * BYTE;
-const MEGA_BYTE: u64 = 1000 * KILO_BYTE;
-const GIGA_BYTE: u64 = 1000 * MEGA_BYTE;
-const TERA_BYTE: u64 = 1000 * GIGA_BYTE;
-const PETA_BYTE: u64 = 1000 * TERA_BYTE;
+pub struct SizeRemove {
+    min: Bound<u64>,
+    max: Bound<u64>,
+    relative: Option<RelativeSize>,
+}
 
-//binary sizes
-const KIBI_BYTE: u64 = 1024 * BYTE;
-const MEBI_BYTE: u64 = 1024 * KIBI_BYTE;
-const GIBI_BYTE: u64 = 1024 * MEBI_BYTE;
-const TEBI_BYTE: u64 = 1024 * GIBI_BYTE;
-const PEBI_BYTE: u64 = 1024 * TEBI_BYTE;
+/// A spec using percent units, resolved again on each root.
+struct RelativeSize {
+    spec: String,
+    syntax: Syntax,
+    units: UnitTable,
+    kind: SpecKind,
+    resolved: bool,
+}
 
-pub struct SizeRemove {
-    size: u64,
-    smaller: bool,
+#[derive(Clone, Copy)]
+enum SpecKind {
+    Threshold { smaller: bool },
+    Range,
 }
 
 impl SizeRemove {
-    pub fn new(size_spec: &str, smaller: bool) -> std::io::Result<Self> {
-        let tmp = Self::size_converter(size_spec);
-        match tmp {
-            Ok(size) => Ok(Self::factory(size, smaller)),
-            Err(msg) => Err(Self::error_factory(msg)),
+    /// Remove files smaller, or larger, than size_spec, whose
+    /// units are looked up in units, see UnitTable::size.
+    pub fn new(
+        size_spec: &str,
+        smaller: bool,
+        syntax: Syntax,
+        units: &UnitTable,
+    ) -> std::io::Result<Self> {
+        Self::build(size_spec, SpecKind::Threshold { smaller }, syntax, units)
+    }
+
+    /// Remove files whose size is in the given
+    /// range, like `10mb..1gb`, `10mb..` or `..1gb`,
+    /// where both ends are included, or satisfies the
+    /// given comparison, like `<=10mb` or `>1gb`.
+    pub fn from_range(
+        range_spec: &str,
+        syntax: Syntax,
+        units: &UnitTable,
+    ) -> std::io::Result<Self> {
+        Self::build(range_spec, SpecKind::Range, syntax, units)
+    }
+
+    /// Use the size of reference as threshold. Only files
+    /// strictly smaller or larger than reference are
+    /// removed, so reference itself is always kept.
+    pub fn from_reference(reference: &Path, smaller: bool) -> std::io::Result<Self> {
+        let size = metadata(reference)?.len();
+        let (min, max) = if smaller {
+            (Bound::Unbounded, Bound::Excluded(size))
+        } else {
+            (Bound::Excluded(size), Bound::Unbounded)
+        };
+        Ok(Self {
+            min,
+            max,
+            relative: None,
+        })
+    }
+
+    /// A spec using percent units is only checked here,
+    /// its thresholds are resolved by enter_root.
+    fn build(
+        spec: &str,
+        kind: SpecKind,
+        syntax: Syntax,
+        units: &UnitTable,
+    ) -> std::io::Result<Self> {
+        let relative = if spec.contains('%') {
+            Some(RelativeSize {
+                spec: spec.to_owned(),
+                syntax,
+                units: units.clone(),
+                kind,
+                resolved: false,
+            })
+        } else {
+            None
+        };
+        let tmp = if relative.is_some() {
+            Self::bounds(spec, kind, |s| {
+                Self::size_converter(s, syntax, units, 1).map(|_| 0)
+            })
+        } else {
+            Self::bounds(spec, kind, |s| Self::size_converter(s, syntax, units, 0))
+        };
+        let (min, max) = tmp.map_err(Self::error_factory)?;
+        Ok(Self { min, max, relative })
+    }
+
+    fn bounds<F>(spec: &str, kind: SpecKind, f: F) -> Result<(Bound<u64>, Bound<u64>), SpecError>
+    where
+        F: Fn(&str) -> Result<u64, SpecError>,
+    {
+        match kind {
+            SpecKind::Threshold { smaller } => Ok(Self::factory(f(spec)?, smaller)),
+            SpecKind::Range => spec_range_parser(spec, f),
         }
     }
 
-    fn size_converter(size_spec: &str) -> Result<u64, String> {
-        spec_string_parser(size_spec, |s| {
-            match s {
-                "b" => Ok(BYTE),
-                // standard SI
-                "kb" | "kilo" => Ok(KILO_BYTE),
-                "mb" | "mega" => Ok(MEGA_BYTE),
-                "gb" | "giga" => Ok(GIGA_BYTE),
-                "tb" | "tera" => Ok(TERA_BYTE),
-                "pb" | "peta" => Ok(PETA_BYTE),
-                // binary
-                "kib" | "kibi" => Ok(KIBI_BYTE),
-                "mib" | "mebi" => Ok(MEBI_BYTE),
-                "gib" | "gibi" => Ok(GIBI_BYTE),
-                "tib" | "tebi" => Ok(TEBI_BYTE),
-                "pib" | "pebi" => Ok(PEBI_BYTE),
-                _ => Err(format!("unknown size specifier {}", s)),
+    /// Parse size_spec, where each percent unit is worth percent bytes.
+    fn size_converter(
+        size_spec: &str,
+        syntax: Syntax,
+        table: &UnitTable,
+        percent: u64,
+    ) -> Result<u64, SpecError> {
+        let lookup = |name: &str| {
+            if PERCENT_UNITS.contains(&name) {
+                return Ok(percent);
             }
-        })
+            table.lookup(name).map_err(|_| {
+                let known = table.names().chain(PERCENT_UNITS.iter().cloned());
+                UnitError::unknown("size", name, known)
+            })
+        };
+        spec_string_parser(size_spec, lookup, syntax)
     }
 
-    fn factory(size: u64, smaller: bool) -> Self {
-        SizeRemove { size, smaller }
+    fn factory(size: u64, smaller: bool) -> (Bound<u64>, Bound<u64>) {
+        if smaller {
+            (Bound::Unbounded, Bound::Included(size))
+        } else {
+            (Bound::Included(size), Bound::Unbounded)
+        }
     }
 
-    fn error_factory(msg: String) -> Error {
-        Error::new(ErrorKind::Other, msg)
+    fn error_factory(msg: SpecError) -> Error {
+        Error::other(msg)
     }
 }
 
 impl FileRemove for SizeRemove {
-    fn remove(&mut self, path: &Path) -> Result<bool, Error> {
-        let meta = metadata(path)?;
-        let size = meta.len();
-        let output = if self.smaller {
-            size <= self.size
-        } else {
-            size >= self.size
-        };
-        Ok(output)
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, _: &Path) -> Result<bool, Error> {
+        if let Some(relative) = self.relative.as_ref().filter(|r| !r.resolved) {
+            let msg = format!(
+                "size `{}` is relative to a filesystem, but no root was given",
+                relative.spec
+            );
+            return Err(Error::other(msg));
+        }
+        let size = dir.metadata(name)?.len();
+        Ok((self.min, self.max).contains(&size))
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<(), Error> {
+        if let Some(relative) = self.relative.as_mut() {
+            let total = DirFd::open(root)?.filesystem_size()?;
+            let percent = total / 100 + u64::from(total % 100 >= 50);
+            let (syntax, units) = (relative.syntax, &relative.units);
+            let converter = |s: &str| Self::size_converter(s, syntax, units, percent);
+            let (min, max) = Self::bounds(&relative.spec, relative.kind, converter)
+                .map_err(Self::error_factory)?;
+            self.min = min;
+            self.max = max;
+            relative.resolved = true;
+        }
+        Ok(())
     }
 }
 
@@ -82,6 +182,13 @@ impl FileRemove for SizeRemove {
 mod test {
 
     use super::*;
+    use crate::file_remove_iterator::file_remove::remove_path;
+    use crate::file_remove_iterator::parser::unit_table::{
+        KIBI_BYTE, KILO_BYTE, MEBI_BYTE, MEGA_BYTE,
+    };
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::TempDir;
 
     #[test]
     fn test_size_converter() {
@@ -95,8 +202,121 @@ mod test {
         run_test("5kib 4mb 6pebi 4kb ", 6755399445064864);
     }
 
+    #[test]
+    fn test_decimal_size_converter() {
+        run_test("1.5gb", 1_500_000_000);
+        run_test("0.5kib", 512);
+        run_test("0.001kib", 1);
+        assert!(
+            SizeRemove::size_converter("99999999pb", Syntax::Lenient, &UnitTable::size(), 0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_unknown_unit() {
+        let err = SizeRemove::size_converter("1gb 10mbi", Syntax::Lenient, &UnitTable::size(), 0)
+            .unwrap_err();
+        assert_eq!(err.span(), 6..9);
+        assert_eq!(err.suggestion(), Some("mib"));
+        let err =
+            SizeRemove::size_converter("10MB", Syntax::Lenient, &UnitTable::size(), 0).unwrap_err();
+        assert_eq!(err.suggestion(), Some("mb"));
+    }
+
+    #[test]
+    fn test_custom_unit() {
+        let mut units = UnitTable::size();
+        units.register("sector", 512).unwrap();
+        let range = SizeRemove::from_range("1sector..8sector", Syntax::Lenient, &units).unwrap();
+        assert_eq!(range.min, Bound::Included(512));
+        assert_eq!(range.max, Bound::Included(4 * KIBI_BYTE));
+        assert!(SizeRemove::new("1sector", true, Syntax::Lenient, &UnitTable::size()).is_err());
+    }
+
     fn run_test(spec: &str, size: u64) {
-        let tmp = SizeRemove::size_converter(spec).unwrap();
+        let tmp = SizeRemove::size_converter(spec, Syntax::Lenient, &UnitTable::size(), 0).unwrap();
         assert_eq!(tmp, size);
     }
+
+    #[test]
+    fn test_size_range() {
+        let range =
+            SizeRemove::from_range("1kb..1mb", Syntax::Lenient, &UnitTable::size()).unwrap();
+        assert_eq!(range.min, Bound::Included(KILO_BYTE));
+        assert_eq!(range.max, Bound::Included(MEGA_BYTE));
+
+        let range = SizeRemove::from_range("..1kib", Syntax::Lenient, &UnitTable::size()).unwrap();
+        assert_eq!(range.min, Bound::Unbounded);
+        assert_eq!(range.max, Bound::Included(KIBI_BYTE));
+
+        assert!(SizeRemove::from_range("1mb..1kb", Syntax::Lenient, &UnitTable::size()).is_err());
+        assert!(SizeRemove::from_range("1mb", Syntax::Lenient, &UnitTable::size()).is_err());
+
+        let range =
+            SizeRemove::from_range("<=1gib - 512mib", Syntax::Strict, &UnitTable::size()).unwrap();
+        assert_eq!(range.min, Bound::Unbounded);
+        assert_eq!(range.max, Bound::Included(512 * MEBI_BYTE));
+
+        let range = SizeRemove::from_range(">1kb", Syntax::Lenient, &UnitTable::size()).unwrap();
+        assert_eq!(range.min, Bound::Excluded(KILO_BYTE));
+        assert_eq!(range.max, Bound::Unbounded);
+    }
+
+    #[test]
+    fn test_reference_file() {
+        let root = TempDir::new().unwrap();
+        let mut paths = Vec::new();
+        for size in [10, 100, 1000].iter() {
+            let path = root.path().join(format!("file_{}", size));
+            let mut file = File::create(&path).unwrap();
+            file.write_all(&vec![0; *size]).unwrap();
+            paths.push(path);
+        }
+
+        let mut smaller = SizeRemove::from_reference(&paths[1], true).unwrap();
+        let mut larger = SizeRemove::from_reference(&paths[1], false).unwrap();
+        let ans: Vec<(bool, bool)> = paths
+            .iter()
+            .map(|p| {
+                (
+                    remove_path(&mut smaller, p).unwrap(),
+                    remove_path(&mut larger, p).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ans, vec![(true, false), (false, false), (false, true)]);
+    }
+
+    #[test]
+    fn test_relative_size() {
+        let root = TempDir::new().unwrap();
+        let total = DirFd::open(root.path()).unwrap().filesystem_size().unwrap();
+        let percent = total / 100 + u64::from(total % 100 >= 50);
+
+        let mut larger = SizeRemove::new("2%", false, Syntax::Lenient, &UnitTable::size()).unwrap();
+        let path = root.path().join("file");
+        File::create(&path).unwrap();
+        assert!(remove_path(&mut larger, &path).is_err());
+        larger.enter_root(root.path()).unwrap();
+        assert_eq!(larger.min, Bound::Included(2 * percent));
+        assert!(!remove_path(&mut larger, &path).unwrap());
+
+        let mut range =
+            SizeRemove::from_range("1kb..0.5%fs", Syntax::Lenient, &UnitTable::size()).unwrap();
+        range.enter_root(root.path()).unwrap();
+        assert_eq!(range.min, Bound::Included(1000));
+        assert_eq!(range.max, Bound::Included(percent / 2 + percent % 2));
+
+        let err =
+            SizeRemove::size_converter("1%fz", Syntax::Lenient, &UnitTable::size(), 0).unwrap_err();
+        assert_eq!(err.suggestion(), Some("%fs"));
+        assert_eq!(
+            SizeRemove::size_converter("3%fs", Syntax::Lenient, &UnitTable::size(), 7),
+            Ok(21)
+        );
+        let err =
+            SizeRemove::size_converter("3%FS", Syntax::Lenient, &UnitTable::size(), 7).unwrap_err();
+        assert_eq!(err.suggestion(), Some("%fs"));
+    }
 }
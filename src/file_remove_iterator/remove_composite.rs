@@ -0,0 +1,133 @@
+use std::ffi::OsStr;
+use std::io::Error;
+use std::path::Path;
+
+use super::file_remove::FileRemove;
+use crate::dir_fd::DirFd;
+
+/// How the results of the inner removers
+/// are combined together.
+#[derive(Clone, Copy)]
+pub enum Combine {
+    All,
+    Any,
+}
+
+/// A FileRemove made of other FileRemove.
+/// Inner removers are called in order and the
+/// evaluation stops as soon as the result is known,
+/// so a stateful remover (like the duplicates one)
+/// placed last only sees the files matched by
+/// the previous ones.
+pub struct CompositeRemove {
+    removers: Vec<Box<dyn FileRemove>>,
+    combine: Combine,
+}
+
+impl CompositeRemove {
+    pub fn new(removers: Vec<Box<dyn FileRemove>>, combine: Combine) -> Self {
+        Self { removers, combine }
+    }
+}
+
+impl FileRemove for CompositeRemove {
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool, Error> {
+        for remover in self.removers.iter_mut() {
+            let ans = remover.remove(dir, name, path)?;
+            match self.combine {
+                Combine::All if !ans => return Ok(false),
+                Combine::Any if ans => return Ok(true),
+                _ => {}
+            }
+        }
+        match self.combine {
+            Combine::All => Ok(true),
+            Combine::Any => Ok(false),
+        }
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<(), Error> {
+        for remover in self.removers.iter_mut() {
+            remover.enter_root(root)?;
+        }
+        Ok(())
+    }
+}
+
+/// Invert the answer of the inner remover.
+pub struct NotRemove {
+    remover: Box<dyn FileRemove>,
+}
+
+impl NotRemove {
+    pub fn new(remover: Box<dyn FileRemove>) -> Self {
+        Self { remover }
+    }
+}
+
+impl FileRemove for NotRemove {
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool, Error> {
+        let ans = self.remover.remove(dir, name, path)?;
+        Ok(!ans)
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<(), Error> {
+        self.remover.enter_root(root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    struct FixedRemove {
+        answer: bool,
+    }
+
+    impl FileRemove for FixedRemove {
+        fn remove(&mut self, _: &DirFd, _: &OsStr, _: &Path) -> Result<bool, Error> {
+            Ok(self.answer)
+        }
+    }
+
+    fn fixed(answers: &[bool]) -> Vec<Box<dyn FileRemove>> {
+        answers
+            .iter()
+            .map(|answer| {
+                let tmp: Box<dyn FileRemove> = Box::new(FixedRemove { answer: *answer });
+                tmp
+            })
+            .collect()
+    }
+
+    /// The fixed removers never look at the file.
+    fn ask(remove: &mut dyn FileRemove) -> bool {
+        let dir = DirFd::open(".").unwrap();
+        remove
+            .remove(&dir, OsStr::new("file"), Path::new("file"))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_combine_all() {
+        let mut remove = CompositeRemove::new(fixed(&[true, true]), Combine::All);
+        assert!(ask(&mut remove));
+        let mut remove = CompositeRemove::new(fixed(&[true, false, true]), Combine::All);
+        assert!(!ask(&mut remove));
+    }
+
+    #[test]
+    fn test_combine_any() {
+        let mut remove = CompositeRemove::new(fixed(&[false, false]), Combine::Any);
+        assert!(!ask(&mut remove));
+        let mut remove = CompositeRemove::new(fixed(&[false, true, false]), Combine::Any);
+        assert!(ask(&mut remove));
+    }
+
+    #[test]
+    fn test_not() {
+        let mut remove = NotRemove::new(fixed(&[true]).pop().unwrap());
+        assert!(!ask(&mut remove));
+    }
+}
@@ -2,6 +2,7 @@ extern crate data_encoding;
 extern crate ring;
 
 use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::Error;
@@ -10,6 +11,7 @@ use std::path::Path;
 use data_encoding::HEXUPPER;
 use ring::digest::{Context, SHA256};
 
+use crate::dir_fd::DirFd;
 use crate::file_remove_iterator::file_remove::FileRemove;
 
 pub struct FileIndex {
@@ -24,9 +26,20 @@ impl FileIndex {
     }
 }
 
+impl Default for FileIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FileRemove for FileIndex {
-    fn remove(&mut self, path: &Path) -> Result<bool, Error> {
-        let hash = hash_file(path)?;
+    fn remove(&mut self, dir: &DirFd, name: &OsStr, _: &Path) -> Result<bool, Error> {
+        // only regular files have a content to compare,
+        // symbolic links are not followed
+        if !dir.metadata(name)?.is_file() {
+            return Ok(false);
+        }
+        let hash = hash_reader(dir.open_file(name)?)?;
         if self.store.contains(&hash) {
             return Ok(true);
         }
@@ -35,8 +48,12 @@ impl FileRemove for FileIndex {
     }
 }
 
+#[cfg(test)]
 fn hash_file(path: &Path) -> Result<String, Error> {
-    let mut input = File::open(path)?;
+    hash_reader(File::open(path)?)
+}
+
+pub(crate) fn hash_reader(mut input: File) -> Result<String, Error> {
     let mut buff = [0; 1024];
     let mut context = Context::new(&SHA256);
 
@@ -59,6 +76,7 @@ mod test {
     extern crate tempfile;
 
     use super::*;
+    use crate::file_remove_iterator::file_remove::remove_path;
     use std::io::prelude::Write;
     use tempfile::tempdir;
 
@@ -69,7 +87,7 @@ mod test {
         let path = dir.path().to_owned();
         let path = path.join("RANDOM_NAME");
         let mut file = File::create(&path).unwrap();
-        file.write(msg.as_bytes()).unwrap();
+        file.write_all(msg.as_bytes()).unwrap();
 
         let ans = "2FF711FDB1CB48EA4B1BBD34C5CE5817921AC0FC852B34DAEB250D1293DE8B63";
         assert_eq!(ans, hash_file(&path).unwrap());
@@ -77,7 +95,7 @@ mod test {
 
     #[test]
     fn test_file_index() {
-        let names = vec!["unique", "equal_1", "equal_2"];
+        let names = ["unique", "equal_1", "equal_2"];
         let dir = tempdir().unwrap();
 
         let eq_data = "EQUAL FILES";
@@ -87,19 +105,34 @@ mod test {
 
         let file_path = base_dir.join(names[0]);
         let mut unique_file = File::create(&file_path).unwrap();
-        unique_file.write(uniq_data.as_bytes()).unwrap();
+        unique_file.write_all(uniq_data.as_bytes()).unwrap();
 
         for name in names.iter().skip(1) {
             let mut eq_file = File::create(dir.path().join(name)).unwrap();
-            eq_file.write(eq_data.as_bytes()).unwrap();
+            eq_file.write_all(eq_data.as_bytes()).unwrap();
         }
 
-        let ans = vec![true, true, false];
+        let ans = [true, true, false];
 
         let mut index = FileIndex::new();
         for (n, c) in names.iter().zip(ans.iter()) {
-            let a = !index.remove(dir.path().join(n).as_path()).unwrap();
+            let a = !remove_path(&mut index, &dir.path().join(n)).unwrap();
             assert_eq!(a, *c);
         }
     }
+
+    #[test]
+    fn test_symlink_is_not_a_duplicate() {
+        let dir = tempdir().unwrap();
+        for name in &["a", "b"] {
+            let mut file = File::create(dir.path().join(name)).unwrap();
+            file.write_all(b"EQUAL FILES").unwrap();
+        }
+        std::os::unix::fs::symlink("a", dir.path().join("link")).unwrap();
+
+        let mut index = FileIndex::new();
+        assert!(!remove_path(&mut index, &dir.path().join("link")).unwrap());
+        assert!(!remove_path(&mut index, &dir.path().join("a")).unwrap());
+        assert!(remove_path(&mut index, &dir.path().join("b")).unwrap());
+    }
 }
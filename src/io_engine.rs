@@ -1,45 +1,199 @@
-use crate::file_remove_iterator::file_remove::FileRemove;
+use crate::dir_fd::DirFd;
+use crate::failures::quote;
+use crate::logger::{self, StatusLogger};
+use std::ffi::OsStr;
 use std::io::*;
 use std::path::Path;
 
-pub fn remove_question(name: &str) -> Result<bool> {
+/// When to ask before removing a file, like rm.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Prompt {
+    /// with -f, or when stdin is not a terminal
+    Never,
+    /// only for write-protected files
+    WriteProtected,
+    /// with -i
+    Always,
+}
+
+impl Prompt {
+    /// Write-protected files are only asked
+    /// about when someone can answer.
+    pub fn write_protected() -> Self {
+        if stdin().is_terminal() {
+            Prompt::WriteProtected
+        } else {
+            Prompt::Never
+        }
+    }
+}
+
+/// Ask question, by default the answer is no.
+pub fn confirm(question: &str) -> Result<bool> {
     let mut buffer = String::new();
 
-    println!("confirm remove: {}? [y/N]", name);
+    println!("{} [y/N]", question);
     std::io::stdin().read_line(&mut buffer)?;
 
     let lower = buffer.to_lowercase();
     let ans = lower.trim();
 
-    if ans.len() > 0 && "yes".starts_with(ans) {
+    if !ans.is_empty() && "yes".starts_with(ans) {
         Ok(true)
     } else {
         Ok(false)
     }
 }
 
-pub struct InteractiveFileRemove {
-    file_remove: Box<dyn FileRemove>,
+/// Whether the directory name, in dir, can be walked before
+/// removing its content, asking like rm -r when needed.
+pub fn ask_descend(prompt: Prompt, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool> {
+    if prompt == Prompt::Never {
+        return Ok(true);
+    }
+    match write_protected(dir, name)? {
+        Some(_) => confirm(&format!(
+            "descend into write-protected directory {}?",
+            quote(path)
+        )),
+        None if prompt == Prompt::Always => {
+            confirm(&format!("descend into directory {}?", quote(path)))
+        }
+        None => Ok(true),
+    }
 }
 
-impl InteractiveFileRemove {
-    pub fn new(file_remove: Box<dyn FileRemove>) -> Self {
-        Self { file_remove }
+/// Whether name, in dir, can be removed according to prompt,
+/// asking like rm when needed: `remove regular file 'x'?`.
+/// Write-protected files that are kept are counted by log.
+pub fn ask_remove(
+    prompt: Prompt,
+    dir: &DirFd,
+    name: &OsStr,
+    path: &Path,
+    log: &mut Option<StatusLogger>,
+) -> Result<bool> {
+    if prompt == Prompt::Never {
+        return Ok(true);
     }
+    let protected = write_protected(dir, name)?.is_some();
+    let kind = dir.metadata(name)?.kind_name();
+    answer_remove(prompt, protected, kind, path, log, confirm)
 }
 
-impl FileRemove for InteractiveFileRemove {
-    fn remove(&mut self, path: &Path) -> Result<bool> {
-        if self.file_remove.remove(path)? {
-            let name = if let Some(name) = path.to_str() {
-                name
-            } else {
-                eprintln!("WARNING: CANNOT DISPLAY FILE NAME!");
-                ""
-            };
-            remove_question(name)
-        } else {
-            Ok(false)
+/// Ask through ask what rm asks before removing path, a
+/// file of kind that may be write-protected, and count
+/// a write-protected file that is kept.
+fn answer_remove<F: FnOnce(&str) -> Result<bool>>(
+    prompt: Prompt,
+    protected: bool,
+    kind: &str,
+    path: &Path,
+    log: &mut Option<StatusLogger>,
+    ask: F,
+) -> Result<bool> {
+    let answer = if protected {
+        ask(&format!("remove write-protected {} {}?", kind, quote(path)))?
+    } else if prompt == Prompt::Always {
+        ask(&format!("remove {} {}?", kind, quote(path)))?
+    } else {
+        true
+    };
+    if !answer && protected {
+        logger::add_skipped_log(log, path);
+    }
+    Ok(answer)
+}
+
+/// The kind of name when rm calls it write-protected: it
+/// cannot be written, or it is immutable or append-only.
+/// Symbolic links never are.
+pub fn write_protected(dir: &DirFd, name: &OsStr) -> Result<Option<&'static str>> {
+    let stat = dir.metadata(name)?;
+    if stat.is_symlink() {
+        return Ok(None);
+    }
+    let protected = match dir.is_writable(name) {
+        Ok(writable) => !writable,
+        // faccessat fails so for an immutable file, even for root
+        Err(err) if err.raw_os_error() == Some(libc::EPERM) => {
+            (stat.is_file() || stat.is_dir()) && dir.is_immutable(name)?
         }
+        Err(err) => return Err(err),
+    };
+    if protected {
+        Ok(Some(stat.kind_name()))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{self, create_dir};
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_protected() {
+        let root = TempDir::new().unwrap();
+        fs::write(root.path().join("file"), "content").unwrap();
+        create_dir(root.path().join("dir")).unwrap();
+        symlink("missing", root.path().join("link")).unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        for name in &["file", "dir", "link"] {
+            assert_eq!(write_protected(&dir, OsStr::new(name)).unwrap(), None);
+        }
+        assert!(write_protected(&dir, OsStr::new("missing")).is_err());
+        let path = root.path().join("file");
+        assert!(ask_remove(Prompt::Never, &dir, OsStr::new("file"), &path, &mut None).unwrap());
+        assert!(ask_remove(
+            Prompt::WriteProtected,
+            &dir,
+            OsStr::new("file"),
+            &path,
+            &mut None
+        )
+        .unwrap());
+        assert_eq!(
+            dir.metadata(OsStr::new("file")).unwrap().kind_name(),
+            "regular file"
+        );
+    }
+
+    #[test]
+    fn test_answer_remove() {
+        let path = Path::new("dir/file");
+        let mut log = Some(StatusLogger::new());
+        let mut asked = Vec::new();
+
+        // the check is faked: running as root, no file is write-protected
+        let kind = "regular file";
+        let mut ask = |question: &str, answer| {
+            asked.push(question.to_string());
+            Ok(answer)
+        };
+        let removed = answer_remove(Prompt::WriteProtected, true, kind, path, &mut log, |q| {
+            ask(q, false)
+        });
+        assert!(!removed.unwrap());
+        let removed = answer_remove(Prompt::WriteProtected, false, kind, path, &mut log, |q| {
+            ask(q, false)
+        });
+        assert!(removed.unwrap());
+        let removed = answer_remove(Prompt::Always, false, "directory", path, &mut log, |q| {
+            ask(q, true)
+        });
+        assert!(removed.unwrap());
+        assert_eq!(
+            asked,
+            vec![
+                "remove write-protected regular file 'dir/file'?",
+                "remove directory 'dir/file'?"
+            ]
+        );
     }
 }
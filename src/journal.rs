@@ -0,0 +1,650 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use super::dir_fd::DirFd;
+use super::failures::quote;
+use super::file_remove_iterator::file_remove::FileRemove;
+use super::file_remove_iterator::remove_by_date::{TimeField, TimeRemove};
+use super::file_remove_iterator::remove_duplicates::hash_reader;
+use super::file_remove_iterator::{Syntax, UnitTable};
+use super::move_to::move_across;
+use super::paths::{absolute, data_home, url_escape, url_unescape};
+use super::remove_action::RemoveAction;
+
+const JOURNAL_NAME: &str = "journal";
+const FILES_NAME: &str = "files";
+
+/// The default directory holding staging areas,
+/// `$XDG_DATA_HOME/rmd/journal`.
+pub fn default_base() -> Result<PathBuf> {
+    Ok(data_home()?.join("rmd").join(JOURNAL_NAME))
+}
+
+/// Move entries into a staging area instead of unlinking
+/// them, recording each one in the journal of the area so
+/// that restore can put it back. The record is synced before
+/// the entry is moved, so no staged entry is left without
+/// one, a record whose entry never came is skipped. The
+/// staging area is created inside base on the first removal.
+/// Entries on another filesystem are copied there, see
+/// move_across.
+pub struct Journal {
+    base: PathBuf,
+    area: Option<StagingArea>,
+}
+
+struct StagingArea {
+    files: DirFd,
+    journal: File,
+    count: usize,
+}
+
+/// A removed entry: where it was and what it was like.
+struct Record {
+    index: usize,
+    kind: char,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: i64,
+    // SHA256 of the content, for regular files only
+    hash: Option<String>,
+    original: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(base: P) -> Self {
+        Self {
+            base: base.as_ref().to_path_buf(),
+            area: None,
+        }
+    }
+
+    fn stage(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let original = absolute(path)?;
+        let area = self.staging_area()?;
+        let record = Record::new(area.count, dir, name, original)?;
+        writeln!(area.journal, "{}", record.to_line())?;
+        area.journal.sync_data()?;
+        // the index is taken even if the move fails,
+        // as the record cannot be taken back
+        let staged_name = area.count.to_string();
+        area.count += 1;
+        move_across(dir, name, &area.files, OsStr::new(&staged_name))
+    }
+
+    fn staging_area(&mut self) -> Result<&mut StagingArea> {
+        if self.area.is_none() {
+            let mut builder = DirBuilder::new();
+            builder.recursive(true).mode(0o700).create(&self.base)?;
+            let name = format!(
+                "{}-{}",
+                Local::now().format("%Y%m%dT%H%M%S"),
+                std::process::id()
+            );
+            let root = self.base.join(name);
+            builder.recursive(false).create(&root)?;
+            builder.create(root.join(FILES_NAME))?;
+            let files = DirFd::open(root.join(FILES_NAME))?;
+            let journal_path = root.join(JOURNAL_NAME);
+            let journal = OpenOptions::new()
+                .append(true)
+                .create_new(true)
+                .open(&journal_path)?;
+            eprintln!("rmd: removed entries are kept in {}", quote(&root));
+            self.area = Some(StagingArea {
+                files,
+                journal,
+                count: 0,
+            });
+        }
+        Ok(self.area.as_mut().unwrap())
+    }
+}
+
+impl RemoveAction for Journal {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.stage(dir, name, path)
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.stage(dir, name, path)
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.stage(dir, name, path)
+    }
+
+    fn keeps_trees(&self) -> bool {
+        true
+    }
+}
+
+impl Record {
+    /// The record of name, in dir, removed from original.
+    fn new(index: usize, dir: &DirFd, name: &OsStr, original: PathBuf) -> Result<Self> {
+        let meta = dir.file_metadata(name)?;
+        let file_type = meta.file_type();
+        let (kind, hash) = if file_type.is_file() {
+            ('f', Some(hash_reader(dir.open_file(name)?)?))
+        } else if file_type.is_dir() {
+            ('d', None)
+        } else if file_type.is_symlink() {
+            ('l', None)
+        } else {
+            ('o', None)
+        };
+        Ok(Self {
+            index,
+            kind,
+            mode: meta.mode(),
+            uid: meta.uid(),
+            gid: meta.gid(),
+            size: meta.len(),
+            mtime: meta.mtime(),
+            hash,
+            original,
+        })
+    }
+
+    /// Move the staged entry back to its original path
+    /// and return the directory now holding it.
+    fn restore(&self, files: &DirFd, staged: &OsStr) -> Result<DirFd> {
+        let fail = |reason: &str| {
+            let msg = format!("cannot restore {}: {}", quote(&self.original), reason);
+            Error::new(ErrorKind::AlreadyExists, msg)
+        };
+        let name = self
+            .original
+            .file_name()
+            .ok_or_else(|| fail("invalid path"))?;
+        if let Some(parent) = self.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let (dir, _) = DirFd::open_parent(&self.original)?;
+        match dir.metadata(name) {
+            // created when its content was restored
+            Ok(existing) if self.kind == 'd' && existing.is_dir() => {
+                if !merge(files, staged, &dir, name)? {
+                    return Err(fail("it already exists"));
+                }
+                files.remove_dir(staged)?;
+                return Ok(dir);
+            }
+            Ok(_) => return Err(fail("it already exists")),
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+        if let Some(hash) = &self.hash {
+            if *hash != hash_reader(files.open_file(staged)?)? {
+                let msg = format!(
+                    "cannot restore {}: its content changed",
+                    quote(&self.original)
+                );
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        }
+        move_across(files, staged, &dir, name)?;
+        Ok(dir)
+    }
+
+    /// Give the restored entry, in dir, its recorded owner,
+    /// permissions and modification time. Only root may
+    /// give an entry away, an unprivileged restore keeps
+    /// the entry as its own.
+    fn restore_status(&self, dir: &DirFd) -> Result<()> {
+        let name = self.original.file_name().unwrap_or_default();
+        match dir.set_owner(name, self.uid, self.gid) {
+            Err(err) if err.raw_os_error() == Some(libc::EPERM) => (),
+            result => result?,
+        }
+        if self.kind != 'l' {
+            dir.set_permissions(name, (self.mode & 0o7777) as libc::mode_t)?;
+        }
+        dir.set_times(name, None, (self.mtime as libc::time_t, 0))
+    }
+
+    /// One line of tab separated fields,
+    /// the original path is escaped.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{:o}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.index,
+            self.kind,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+            self.mtime,
+            self.hash.as_deref().unwrap_or("-"),
+            url_escape(self.original.as_os_str())
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let malformed = || {
+            let msg = format!("malformed journal line `{}`", line);
+            Error::new(ErrorKind::InvalidData, msg)
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 9 || fields[1].chars().count() != 1 {
+            return Err(malformed());
+        }
+        Ok(Self {
+            index: fields[0].parse().map_err(|_| malformed())?,
+            kind: fields[1].chars().next().unwrap(),
+            mode: u32::from_str_radix(fields[2], 8).map_err(|_| malformed())?,
+            uid: fields[3].parse().map_err(|_| malformed())?,
+            gid: fields[4].parse().map_err(|_| malformed())?,
+            size: fields[5].parse().map_err(|_| malformed())?,
+            mtime: fields[6].parse().map_err(|_| malformed())?,
+            hash: Some(fields[7])
+                .filter(|hash| *hash != "-")
+                .map(str::to_owned),
+            original: PathBuf::from(url_unescape(fields[8])?),
+        })
+    }
+}
+
+/// Move what is left in the staged directory into the
+/// directory name of dir, created when a part of it was
+/// restored. Return false if an entry is taken in both.
+fn merge(files: &DirFd, staged: &OsStr, dir: &DirFd, name: &OsStr) -> Result<bool> {
+    let source = files.open_dir(staged)?;
+    let target = dir.open_dir(name)?;
+    let mut merged = true;
+    for entry in source.entries()? {
+        match target.metadata(&entry) {
+            Ok(existing) if existing.is_dir() && source.metadata(&entry)?.is_dir() => {
+                if merge(&source, &entry, &target, &entry)? {
+                    source.remove_dir(&entry)?;
+                } else {
+                    merged = false;
+                }
+            }
+            Ok(_) => merged = false,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                move_across(&source, &entry, &target, &entry)?
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(merged)
+}
+
+/// What restore did: the paths put back, and an error
+/// for each entry that had to stay in the staging area.
+#[derive(Default)]
+pub struct Restored {
+    pub restored: Vec<PathBuf>,
+    pub skipped: Vec<Error>,
+}
+
+/// Put back the entries recorded in journal, the staging
+/// area or its journal file. With some filters, only the
+/// entries at, or inside, one of them are restored, a filter
+/// inside a staged directory restores just that part of it,
+/// and a filter with nothing to restore is reported. An entry
+/// already restored is skipped, an entry whose original path
+/// is taken again, or whose content changed, is reported and
+/// left in the staging area. Directories are restored before
+/// their content, a directory already created for its content
+/// just gets its recorded status back, and every entry gets
+/// its recorded owner, permissions and modification time.
+/// The staging area is removed once it is empty.
+pub fn restore<P: AsRef<Path>>(journal: P, filters: &[&Path]) -> Result<Restored> {
+    let journal = journal.as_ref();
+    let root = if journal.is_dir() {
+        journal
+    } else {
+        journal.parent().unwrap_or_else(|| Path::new("."))
+    };
+    // a filter inside a removed directory cannot be resolved
+    let filters = filters
+        .iter()
+        .map(|filter| absolute(filter).or_else(|_| Ok(env::current_dir()?.join(filter))))
+        .collect::<Result<Vec<PathBuf>>>()?;
+
+    let files = DirFd::open(root.join(FILES_NAME))?;
+    let file = File::open(root.join(JOURNAL_NAME))?;
+    let mut records = Vec::new();
+    let mut found = vec![false; filters.len()];
+    for line in BufReader::new(file).lines() {
+        let record = Record::from_line(&line?)?;
+        let staged = PathBuf::from(record.index.to_string());
+        if !is_staged(&files, &staged)? {
+            continue;
+        }
+        let mut selected = filters.is_empty();
+        for (filter, found) in filters.iter().zip(found.iter_mut()) {
+            if record.original.starts_with(filter) {
+                selected = true;
+                *found = true;
+            }
+        }
+        if selected {
+            records.push((record, staged));
+            continue;
+        }
+        // the filters inside a staged directory
+        for (filter, found) in filters.iter().zip(found.iter_mut()) {
+            let inner = match filter.strip_prefix(&record.original) {
+                Ok(inner) if record.kind == 'd' => staged.join(inner),
+                _ => continue,
+            };
+            if is_staged(&files, &inner)? {
+                let part = Record::new(record.index, &files, inner.as_os_str(), filter.clone())?;
+                records.push((part, inner));
+                *found = true;
+            }
+        }
+    }
+    // parents first, the sort is stable
+    records.sort_by_key(|(record, _)| record.original.components().count());
+
+    let mut output = Restored::default();
+    for (filter, _) in filters.iter().zip(found).filter(|(_, found)| !found) {
+        let msg = format!("nothing to restore for {}", quote(filter));
+        output.skipped.push(Error::new(ErrorKind::NotFound, msg));
+    }
+    let mut done = Vec::new();
+    for (record, staged) in records {
+        match record.restore(&files, staged.as_os_str()) {
+            Ok(dir) => done.push((record, dir)),
+            Err(err) => output.skipped.push(err),
+        }
+    }
+    // children change the times of their directory
+    for (record, dir) in done.into_iter().rev() {
+        match record.restore_status(&dir) {
+            Ok(()) => output.restored.push(record.original),
+            Err(err) => output.skipped.push(err),
+        }
+    }
+    output.restored.reverse();
+
+    if files.entries()?.is_empty() {
+        fs::remove_dir_all(root)?;
+    }
+    Ok(output)
+}
+
+/// Whether staged, relative to files, is still there.
+fn is_staged(files: &DirFd, staged: &Path) -> Result<bool> {
+    match files.metadata(staged.as_os_str()) {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Delete the staging areas inside base whose last
+/// removal is older than the given time specification,
+/// or all of them. Return the deleted staging areas.
+pub fn purge<P: AsRef<Path>>(base: P, older: Option<&str>, syntax: Syntax) -> Result<Vec<PathBuf>> {
+    let mut older = match older {
+        Some(spec) => Some(
+            TimeRemove::new(spec, true, syntax, &UnitTable::time())?
+                .with_field(TimeField::Modified),
+        ),
+        None => None,
+    };
+    let base = base.as_ref();
+    let mut purged = Vec::new();
+    if !base.exists() {
+        return Ok(purged);
+    }
+    for entry in fs::read_dir(base)? {
+        let root = entry?.path();
+        let journal = root.join(JOURNAL_NAME);
+        if !journal.is_file() {
+            continue;
+        }
+        let purge = match &mut older {
+            Some(older) => {
+                let (dir, name) = DirFd::open_parent(&journal)?;
+                older.remove(&dir, &name, &journal)?
+            }
+            None => true,
+        };
+        if purge {
+            fs::remove_dir_all(&root)?;
+            purged.push(root);
+        }
+    }
+    purged.sort();
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{create_dir, create_dir_all, read_to_string, set_permissions};
+    use std::fs::{FileTimes, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tempfile::TempDir;
+
+    /// The only staging area inside base.
+    fn staging_area(base: &Path) -> PathBuf {
+        let mut areas = fs::read_dir(base).unwrap();
+        let area = areas.next().unwrap().unwrap().path();
+        assert!(areas.next().is_none());
+        area
+    }
+
+    #[test]
+    fn test_record_line() {
+        let record = Record {
+            index: 3,
+            kind: 'f',
+            mode: 0o100644,
+            uid: 1000,
+            gid: 100,
+            size: 12,
+            mtime: 1_600_000_000,
+            hash: Some("ABCD".to_owned()),
+            original: PathBuf::from("/home/a b"),
+        };
+        let line = record.to_line();
+        assert_eq!(
+            line,
+            "3\tf\t100644\t1000\t100\t12\t1600000000\tABCD\t/home/a%20b"
+        );
+        let parsed = Record::from_line(&line).unwrap();
+        assert_eq!(parsed.to_line(), line);
+
+        assert!(Record::from_line("3\tf\t100644").is_err());
+        assert!(Record::from_line("x\tf\t1\t1\t1\t1\t1\t-\t/a").is_err());
+    }
+
+    #[test]
+    fn test_restore() {
+        let root = TempDir::new().unwrap();
+        let base = root.path().join("journal");
+        let work = root.path().join("work");
+        create_dir(&work).unwrap();
+        let keep = work.join("keep");
+        fs::write(&keep, "keep").unwrap();
+        let file = work.join("file");
+        fs::write(&file, "data").unwrap();
+        let sub = work.join("sub");
+        create_dir(&sub).unwrap();
+        fs::write(sub.join("inner"), "inner").unwrap();
+
+        let mut journal = Journal::new(&base);
+        let dir = DirFd::open(&work).unwrap();
+        for (name, path) in [("keep", &keep), ("file", &file)].iter() {
+            journal.remove_file(&dir, OsStr::new(name), path).unwrap();
+        }
+        journal
+            .remove_dir_all(&dir, OsStr::new("sub"), &sub)
+            .unwrap();
+        assert!(!keep.exists() && !file.exists() && !sub.exists());
+
+        let area = staging_area(&base);
+        let lines = read_to_string(area.join(JOURNAL_NAME)).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+
+        let filters = [file.as_path(), sub.as_path()];
+        let restored = restore(area.join(JOURNAL_NAME), &filters).unwrap();
+        assert_eq!(restored.restored, vec![file.clone(), sub.clone()]);
+        assert_eq!(read_to_string(&file).unwrap(), "data");
+        assert_eq!(read_to_string(sub.join("inner")).unwrap(), "inner");
+        assert!(!keep.exists());
+
+        // the original path is taken again
+        fs::write(&keep, "new").unwrap();
+        let restored = restore(&area, &[]).unwrap();
+        assert!(restored.restored.is_empty());
+        assert_eq!(restored.skipped.len(), 1);
+        assert_eq!(read_to_string(&keep).unwrap(), "new");
+        fs::remove_file(&keep).unwrap();
+        assert_eq!(restore(&area, &[]).unwrap().restored.len(), 1);
+        assert_eq!(read_to_string(&keep).unwrap(), "keep");
+        assert!(!area.exists());
+    }
+
+    #[test]
+    fn test_restore_directories() {
+        let root = TempDir::new().unwrap();
+        let base = root.path().join("journal");
+        let sub = root.path().join("work").join("sub");
+        create_dir_all(&sub).unwrap();
+        let file = sub.join("file");
+        fs::write(&file, "data").unwrap();
+
+        // like --clean: the content first, then the emptied directory
+        let mut journal = Journal::new(&base);
+        let dir = DirFd::open(&sub).unwrap();
+        journal
+            .remove_file(&dir, OsStr::new("file"), &file)
+            .unwrap();
+        let old = SystemTime::now() - Duration::new(3600, 0);
+        File::open(&sub)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(old))
+            .unwrap();
+        set_permissions(&sub, Permissions::from_mode(0o750)).unwrap();
+        let dir = DirFd::open(root.path().join("work")).unwrap();
+        journal.remove_dir(&dir, OsStr::new("sub"), &sub).unwrap();
+        assert!(!sub.exists());
+
+        // the content alone, then its directory
+        let area = staging_area(&base);
+        let restored = restore(&area, &[file.as_path()]).unwrap();
+        assert_eq!(restored.restored, vec![file.clone()]);
+        assert_eq!(read_to_string(&file).unwrap(), "data");
+        let restored = restore(&area, &[]).unwrap();
+        assert!(restored.skipped.is_empty());
+        assert_eq!(restored.restored, vec![sub.clone()]);
+        let meta = fs::metadata(&sub).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o750);
+        assert_eq!(
+            meta.mtime(),
+            old.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+        );
+        assert!(!area.exists());
+    }
+
+    #[test]
+    fn test_restore_inside_directory() {
+        let root = TempDir::new().unwrap();
+        let base = root.path().join("journal");
+        let work = root.path().join("work");
+        let sub = work.join("sub");
+        create_dir_all(sub.join("deep")).unwrap();
+        fs::write(sub.join("file"), "file").unwrap();
+        let inner = sub.join("deep").join("inner");
+        fs::write(&inner, "inner").unwrap();
+
+        let mut journal = Journal::new(&base);
+        let dir = DirFd::open(&work).unwrap();
+        journal
+            .remove_dir_all(&dir, OsStr::new("sub"), &sub)
+            .unwrap();
+        let area = staging_area(&base);
+
+        // just the part of the staged directory
+        let restored = restore(&area, &[inner.as_path()]).unwrap();
+        assert_eq!(restored.restored, vec![inner.clone()]);
+        assert_eq!(read_to_string(&inner).unwrap(), "inner");
+        assert!(!sub.join("file").exists());
+
+        let missing = work.join("missing");
+        let restored = restore(&area, &[inner.as_path(), missing.as_path()]).unwrap();
+        assert!(restored.restored.is_empty());
+        let skipped: Vec<String> = restored.skipped.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                format!("nothing to restore for {}", quote(&inner)),
+                format!("nothing to restore for {}", quote(&missing)),
+            ]
+        );
+
+        // the rest is merged into the directory
+        let restored = restore(&area, &[]).unwrap();
+        assert!(restored.skipped.is_empty());
+        assert_eq!(restored.restored, vec![sub.clone()]);
+        assert_eq!(read_to_string(sub.join("file")).unwrap(), "file");
+        assert_eq!(read_to_string(&inner).unwrap(), "inner");
+        assert!(!area.exists());
+    }
+
+    #[test]
+    fn test_record_before_move() {
+        let root = TempDir::new().unwrap();
+        let base = root.path().join("journal");
+        let file = root.path().join("file");
+        fs::write(&file, "data").unwrap();
+
+        // a record whose entry never came is skipped
+        let mut journal = Journal::new(&base);
+        let dir = DirFd::open(root.path()).unwrap();
+        let area = journal.staging_area().unwrap();
+        let record = Record::new(0, &dir, OsStr::new("file"), file.clone()).unwrap();
+        writeln!(area.journal, "{}", record.to_line()).unwrap();
+        area.count += 1;
+        journal
+            .remove_file(&dir, OsStr::new("file"), &file)
+            .unwrap();
+
+        let area = staging_area(&base);
+        let lines = read_to_string(area.join(JOURNAL_NAME)).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        let restored = restore(&area, &[]).unwrap();
+        assert!(restored.skipped.is_empty());
+        assert_eq!(restored.restored, vec![file.clone()]);
+        assert_eq!(read_to_string(&file).unwrap(), "data");
+    }
+
+    #[test]
+    fn test_purge() {
+        let root = TempDir::new().unwrap();
+        let base = root.path().join("journal");
+        let file = root.path().join("file");
+        fs::write(&file, "data").unwrap();
+
+        let mut journal = Journal::new(&base);
+        let dir = DirFd::open(root.path()).unwrap();
+        journal
+            .remove_file(&dir, OsStr::new("file"), &file)
+            .unwrap();
+        let area = staging_area(&base);
+
+        let purged = purge(&base, Some("1d"), Syntax::Lenient).unwrap();
+        assert!(purged.is_empty());
+        let purged = purge(&base, None, Syntax::Lenient).unwrap();
+        assert_eq!(purged, vec![area.clone()]);
+        assert!(!area.exists());
+    }
+}
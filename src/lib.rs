@@ -1,4 +1,15 @@
+pub mod archive;
+pub mod dir_fd;
 pub mod engine;
+pub mod failures;
 pub mod file_remove_iterator;
 pub mod io_engine;
+pub mod journal;
 pub mod logger;
+pub mod move_to;
+mod paths;
+pub mod plan;
+pub mod protect;
+pub mod remove_action;
+pub mod shred;
+pub mod trash;
@@ -3,7 +3,7 @@ use std::io::Result;
 use std::path::Path;
 
 use log;
-use log::info;
+use log::{info, warn};
 use syslog;
 
 pub enum VerboseLevel {
@@ -27,6 +27,7 @@ enum Kind {
 pub struct StatusLogger {
     verbose: Option<LogBuilder>,
     logger: Option<LogBuilder>,
+    dry_run: bool,
 }
 
 impl StatusLogger {
@@ -34,29 +35,32 @@ impl StatusLogger {
         StatusLogger {
             verbose: None,
             logger: None,
+            dry_run: false,
         }
     }
 
     pub fn add_verbose(&mut self, level: VerboseLevel) {
-        let tmp = LogBuilder::new(level, Kind::Verbose);
+        let tmp = LogBuilder::new(level, Kind::Verbose, self.dry_run);
         self.verbose = Some(tmp);
     }
 
     pub fn add_logger(&mut self, level: VerboseLevel) {
-        let tmp = LogBuilder::new(level, Kind::Log);
+        let tmp = LogBuilder::new(level, Kind::Log, self.dry_run);
         self.logger = Some(tmp);
     }
 
-    pub fn is_used(&mut self) -> bool {
-        if let Some(_) = self.logger {
-            true
-        } else if let Some(_) = self.verbose {
-            true
-        } else {
-            false
+    /// Report removals as planned, not done.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+        for builder in self.verbose.iter_mut().chain(self.logger.iter_mut()) {
+            builder.dry_run = dry_run;
         }
     }
 
+    pub fn is_used(&mut self) -> bool {
+        self.logger.is_some() || self.verbose.is_some()
+    }
+
     pub fn log_file_remove<P: AsRef<Path>>(&mut self, file: P) -> Result<()> {
         if let Some(ref mut verb) = self.verbose {
             verb.log_file_remove(&file)?;
@@ -67,6 +71,28 @@ impl StatusLogger {
         Ok(())
     }
 
+    /// Report that file was stored as member of archive.
+    pub fn log_archive_member(&mut self, file: &Path, member: &Path, archive: &Path) {
+        for builder in self.verbose.iter_mut().chain(self.logger.iter_mut()) {
+            builder.log_archive_member(file, member, archive);
+        }
+    }
+
+    /// Report that the write-protected file was kept.
+    pub fn log_skipped(&mut self, file: &Path) {
+        for builder in self.verbose.iter_mut().chain(self.logger.iter_mut()) {
+            builder.log_skipped(file);
+        }
+    }
+
+    /// Report something the user should know
+    /// about a removal that still took place.
+    pub fn log_warning(&mut self, msg: &str) {
+        for builder in self.verbose.iter_mut().chain(self.logger.iter_mut()) {
+            builder.log_warning(msg);
+        }
+    }
+
     pub fn output_log(&mut self) {
         if let Some(ref mut verb) = self.verbose {
             verb.output_log();
@@ -88,19 +114,27 @@ impl StatusLogger {
     }
 }
 
+impl Default for StatusLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 struct LogBuilder {
     total_size: u64,
     file_count: usize,
     dir_count: usize,
+    skipped_count: usize,
     curr_size: u64,
     is_dir: bool,
     cache_log: String,
     level: VerboseLevel,
     kind: Kind,
+    dry_run: bool,
 }
 
 impl LogBuilder {
-    fn new(level: VerboseLevel, kind: Kind) -> Self {
+    fn new(level: VerboseLevel, kind: Kind, dry_run: bool) -> Self {
         if let Kind::Log = kind {
             LogBuilder::init_logger();
         }
@@ -109,6 +143,7 @@ impl LogBuilder {
             total_size: 0,
             file_count: 0,
             dir_count: 0,
+            skipped_count: 0,
 
             curr_size: 0,
             is_dir: false,
@@ -116,6 +151,7 @@ impl LogBuilder {
             level,
             cache_log: String::new(),
             kind,
+            dry_run,
         }
     }
 
@@ -129,13 +165,20 @@ impl LogBuilder {
         let result = match self.level {
             VerboseLevel::Low => writeln!(&mut self.cache_log, "{:?}", file),
             VerboseLevel::High => {
+                let (remove, freed) = if self.dry_run {
+                    ("Would Remove", "would free")
+                } else {
+                    ("Remove", "freed")
+                };
                 if file.is_dir() {
-                    writeln!(&mut self.cache_log, "Remove Directory: {:?}", file)
+                    writeln!(&mut self.cache_log, "{} Directory: {:?}", remove, file)
                 } else {
                     writeln!(
                         &mut self.cache_log,
-                        "Remove File: {:?} - freed {}",
+                        "{} File: {:?} - {} {}",
+                        remove,
                         file,
+                        freed,
                         format_size(size)
                     )
                 }
@@ -145,6 +188,34 @@ impl LogBuilder {
         Ok(())
     }
 
+    fn log_archive_member(&mut self, file: &Path, member: &Path, archive: &Path) {
+        if let VerboseLevel::High = self.level {
+            let msg = format!("Archive File: {:?} as {:?} in {:?}", file, member, archive);
+            match self.kind {
+                Kind::Verbose => println!("{}", msg),
+                Kind::Log => info!("{}", msg),
+            }
+        }
+    }
+
+    fn log_skipped(&mut self, file: &Path) {
+        self.skipped_count += 1;
+        if let VerboseLevel::High = self.level {
+            let msg = format!("Skip Write-Protected File: {:?}", file);
+            match self.kind {
+                Kind::Verbose => println!("{}", msg),
+                Kind::Log => info!("{}", msg),
+            }
+        }
+    }
+
+    fn log_warning(&mut self, msg: &str) {
+        match self.kind {
+            Kind::Verbose => eprintln!("warning: {}", msg),
+            Kind::Log => warn!("{}", msg),
+        }
+    }
+
     fn output_log(&mut self) {
         if self.is_dir {
             self.dir_count += 1;
@@ -161,26 +232,45 @@ impl LogBuilder {
         self.curr_size = 0;
     }
 
+    /// The totals are given at the high level, and
+    /// always by a dry run, as they are what it is for.
     fn log_statistics(&mut self) {
-        if let VerboseLevel::High = self.level {
+        if self.dry_run || matches!(self.level, VerboseLevel::High) {
+            let (removed, freed) = if self.dry_run {
+                ("would be removed", "would be freed")
+            } else {
+                ("removed", "freed")
+            };
             writeln!(&mut self.cache_log, "Final job statistics:")
                 .expect("unable to format log message");
             writeln!(
                 &mut self.cache_log,
-                "{} director{} removed",
+                "{} director{} {}",
                 self.dir_count,
-                if self.dir_count < 2 { "y" } else { "ies" }
+                if self.dir_count < 2 { "y" } else { "ies" },
+                removed
             )
             .expect("unable to format log message");
             writeln!(
                 &mut self.cache_log,
-                "{} file{} removed",
+                "{} file{} {}",
                 self.file_count,
-                if self.file_count < 2 { "" } else { "s" }
+                if self.file_count < 2 { "" } else { "s" },
+                removed
             )
             .expect("unable to format log message");
             let tmp = format_size(self.total_size);
-            writeln!(&mut self.cache_log, "{} freed", tmp).expect("unable to format log message");
+            writeln!(&mut self.cache_log, "{} {}", tmp, freed)
+                .expect("unable to format log message");
+            if self.skipped_count > 0 {
+                writeln!(
+                    &mut self.cache_log,
+                    "{} write-protected file{} skipped",
+                    self.skipped_count,
+                    if self.skipped_count < 2 { "" } else { "s" }
+                )
+                .expect("unable to format log message");
+            }
         }
     }
 
@@ -220,7 +310,25 @@ pub fn output_file_remove_log(log: &mut Option<StatusLogger>) {
     }
 }
 
-fn format_size(size: u64) -> String {
+pub fn add_skipped_log(log: &mut Option<StatusLogger>, file: &Path) {
+    if let Some(log) = log {
+        log.log_skipped(file);
+    }
+}
+
+pub fn add_warning_log(log: &mut Option<StatusLogger>, msg: &str) {
+    if let Some(log) = log {
+        log.log_warning(msg);
+    }
+}
+
+pub fn add_archive_log(log: &mut Option<StatusLogger>, file: &Path, member: &Path, archive: &Path) {
+    if let Some(log) = log {
+        log.log_archive_member(file, member, archive);
+    }
+}
+
+pub fn format_size(size: u64) -> String {
     let sizes = ["", "k", "M", "G", "T", "P", "E", "Z"];
     let mut size: f64 = size as f64;
     let mut count = 0;
@@ -260,10 +368,10 @@ mod test {
         let mut large_file = File::create(&file_path).unwrap();
 
         for _ in 0..1000 {
-            large_file.write(&[1, 2, 3, 4, 5]).unwrap();
+            large_file.write_all(&[1, 2, 3, 4, 5]).unwrap();
         }
 
-        let mut log = LogBuilder::new(VerboseLevel::Low, Kind::Verbose);
+        let mut log = LogBuilder::new(VerboseLevel::Low, Kind::Verbose, false);
 
         log.log_file_remove(&file_path).unwrap();
         assert_eq!(log.cache_log, format!("{:?}\n", file_path));
@@ -277,7 +385,7 @@ mod test {
         log.output_log();
         assert_eq!(log.cache_log, "");
 
-        let mut log = LogBuilder::new(VerboseLevel::High, Kind::Verbose);
+        let mut log = LogBuilder::new(VerboseLevel::High, Kind::Verbose, false);
 
         log.log_file_remove(&file_path).unwrap();
         assert_eq!(
@@ -300,4 +408,59 @@ mod test {
         log.output_log();
         assert_eq!(log.cache_log, "");
     }
+
+    #[test]
+    fn test_dry_run_log() {
+        let base_dir = TempDir::new().unwrap();
+        let file_path = base_dir.path().join("file.dat");
+        File::create(&file_path)
+            .unwrap()
+            .write_all(&[0; 1500])
+            .unwrap();
+
+        let mut log = LogBuilder::new(VerboseLevel::High, Kind::Verbose, true);
+        log.log_file_remove(&file_path).unwrap();
+        assert_eq!(
+            log.cache_log,
+            format!("Would Remove File: {:?} - would free 1.50 kb\n", file_path)
+        );
+        log.output_log();
+
+        log.log_statistics();
+        assert_eq!(
+            log.cache_log,
+            "Final job statistics:\n0 directory would be removed\n1 file would be removed\n1.50 kb would be freed\n"
+        );
+    }
+
+    #[test]
+    fn test_skipped_log() {
+        let mut log = LogBuilder::new(VerboseLevel::High, Kind::Verbose, false);
+        log.log_skipped(Path::new("file"));
+        log.log_statistics();
+        assert!(log
+            .cache_log
+            .ends_with("\n1 write-protected file skipped\n"));
+        log.output_log();
+
+        log.log_skipped(Path::new("other"));
+        log.log_statistics();
+        assert!(log
+            .cache_log
+            .ends_with("\n2 write-protected files skipped\n"));
+    }
+
+    #[test]
+    fn test_dry_run_statistics() {
+        let mut log = LogBuilder::new(VerboseLevel::Low, Kind::Verbose, false);
+        log.log_statistics();
+        assert_eq!(log.cache_log, "");
+
+        let mut log = LogBuilder::new(VerboseLevel::Low, Kind::Verbose, true);
+        log.log_statistics();
+        assert_eq!(
+            log.cache_log,
+            "Final job statistics:\n0 directory would be removed\n0 file would be removed\n0.00 b would be freed\n"
+        );
+    }
 }
@@ -1,14 +1,65 @@
 extern crate clap;
 
-use clap::{App, Arg, ArgGroup, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use rmd::archive::ArchiveTo;
 use rmd::engine;
+use rmd::failures::{quote, Failures};
+use rmd::journal::{self, Journal};
 use rmd::logger;
+use rmd::move_to::{Collision, MoveTo};
+use rmd::protect::{PreserveRoot, Protected, Protection};
+use rmd::remove_action::{DryRun, RemoveAction, Unlink};
+use rmd::shred::{Fill, Shred};
+use rmd::trash::Trash;
+use std::ffi::OsStr;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::process::ExitCode;
 
-pub fn parse_args<'a>() -> ArgMatches<'a> {
+const USAGE_ERROR: u8 = 2;
+
+pub fn parse_args<'a>() -> clap::Result<ArgMatches<'a>> {
     let parser = App::new("rmd")
         .about("rm able to remove duplicate files")
         .version("0.5.3")
-        .author("Filippo Ranza");
+        .author("Filippo Ranza")
+        .setting(AppSettings::ArgsNegateSubcommands);
+
+    let parser = parser.subcommand(
+        SubCommand::with_name("restore")
+            .about("put back the files removed with --journal")
+            .arg(
+                Arg::with_name("journal")
+                    .required(true)
+                    .help("the staging area, or its journal file"),
+            )
+            .arg(Arg::with_name("paths").multiple(true).help(
+                "restore only these paths, and their content, even from inside a removed directory",
+            ))
+            .arg(
+                Arg::with_name("verbose")
+                    .short("-v")
+                    .long("--verbose")
+                    .help("print restored files"),
+            ),
+    );
+
+    let parser = parser.subcommand(
+        SubCommand::with_name("purge")
+            .about("delete the staging areas left by --journal")
+            .arg(
+                Arg::with_name("older")
+                    .long("--older")
+                    .help("delete only staging areas older then the given time specification")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("verbose")
+                    .short("-v")
+                    .long("--verbose")
+                    .help("print deleted staging areas"),
+            ),
+    );
 
     let parser = parser.arg(
         Arg::with_name("force")
@@ -18,6 +69,32 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
             .conflicts_with("interactive"),
     );
 
+    let parser = parser.arg(
+        Arg::with_name("keep-going")
+            .short("-k")
+            .long("--keep-going")
+            .help("do not stop at the first error: report all of them at the end and exit with an error")
+            .conflicts_with_all(&["force", "interactive", "interactive-once", "interactive-when"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("preserve-root")
+            .long("--preserve-root")
+            .value_name("all")
+            .help("do not work recursively on '/', the default; with 'all' also refuse any operand on another device than its parent")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["all"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("no-preserve-root")
+            .long("--no-preserve-root")
+            .help("do not treat '/' specially, other protected paths are still refused")
+            .conflicts_with("preserve-root"),
+    );
+
     let parser = parser.arg(
         Arg::with_name("clean")
             .short("-c")
@@ -33,6 +110,100 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
             .conflicts_with("force"),
     );
 
+    let parser = parser.arg(
+        Arg::with_name("interactive-once")
+            .short("-I")
+            .help("prompt once, with a summary of the files to remove, before a recursive or automatic removal or before removing more than three files")
+            .conflicts_with_all(&["force", "interactive"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("interactive-when")
+            .long("--interactive")
+            .value_name("WHEN")
+            .help("prompt never, once (-I) or always (-i), always when WHEN is omitted")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .possible_values(&["never", "once", "always"])
+            .conflicts_with_all(&["force", "interactive", "interactive-once"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("dry-run")
+            .short("-n")
+            .long("--dry-run")
+            .help("show what would be removed and the space freed, without removing anything"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("trash")
+            .long("--trash")
+            .help("move files and directories to the trash instead of removing them")
+            .conflicts_with("dry-run"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("journal")
+            .long("--journal")
+            .help("move files and directories to a staging area instead of removing them, use `rmd restore` to put them back")
+            .conflicts_with_all(&["dry-run", "trash"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("move-to")
+            .long("--move-to")
+            .help("move files and directories into the given directory instead of removing them, keeping their path relative to the scanned directory")
+            .takes_value(true)
+            .conflicts_with_all(&["dry-run", "trash", "journal"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("on-collision")
+            .long("--on-collision")
+            .help("what --move-to does when the target path is taken, by default it adds a numeric suffix")
+            .takes_value(true)
+            .possible_values(&["suffix", "skip", "overwrite"])
+            .requires("move-to"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("archive-to")
+            .long("--archive-to")
+            .help("store the files selected by an automatic remover in the given .tar.zst, .zip or .tar archive, removing them once the archive is written and synced to disk")
+            .takes_value(true)
+            .requires("automatic removal")
+            .conflicts_with_all(&["dry-run", "trash", "journal", "move-to"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("shred")
+            .long("--shred")
+            .help("overwrite files before removing them and scrub their names, of little use on copy-on-write filesystems")
+            .conflicts_with_all(&["dry-run", "trash", "journal", "move-to", "archive-to"]),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("shred-passes")
+            .long("--shred-passes")
+            .help("how many times --shred overwrites each file, 3 by default")
+            .takes_value(true)
+            .validator(|passes| match passes.parse::<usize>() {
+                Ok(passes) if passes > 0 => Ok(()),
+                _ => Err(format!("{} is not a positive number", passes)),
+            })
+            .requires("shred"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("shred-fill")
+            .long("--shred-fill")
+            .help("what --shred writes over each file, random data by default")
+            .takes_value(true)
+            .possible_values(&["random", "zeros"])
+            .requires("shred"),
+    );
+
     let parser = parser.arg(
         Arg::with_name("duplicates")
             .short("-d")
@@ -68,6 +239,81 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
             .takes_value(true),
     );
 
+    let parser = parser.arg(
+        Arg::with_name("older-than-file")
+            .long("--older-than-file")
+            .help("remove file older then the given reference file")
+            .takes_value(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("newer-than-file")
+            .long("--newer-than-file")
+            .help("remove file newer then the given reference file")
+            .takes_value(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("smaller-than-file")
+            .long("--smaller-than-file")
+            .help("remove file smaller then the given reference file")
+            .takes_value(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("larger-than-file")
+            .long("--larger-than-file")
+            .help("remove file larger then the given reference file")
+            .takes_value(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("time-field")
+            .long("--time-field")
+            .help("file timestamp compared by time based criteria: last access, modification, status change or birth")
+            .takes_value(true)
+            .possible_values(&["atime", "mtime", "ctime", "btime"])
+            .default_value("atime"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("strict")
+            .long("--strict")
+            .help("reject size and time specifications with characters other than white spaces and a `+` or `-` between terms"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("unit")
+            .long("--unit")
+            .help("define a size or time unit from the existing ones, like 'sector=512b' or 'sprint=14d'")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("NAME=SPEC")
+            .requires("automatic removal"),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("size")
+            .long("--size")
+            .help("remove file whose size is in the given range of size specifications, like '10mb..1gb', '10mb..' or '..1gb', or satisfies a comparison like '<=10mb'")
+            .takes_value(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("age")
+            .long("--age")
+            .help("remove file whose age is in the given range of time specifications, like '7d..30d', '7d..' or '..30d', or satisfies a comparison like '>2w'")
+            .takes_value(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("where")
+            .long("--where")
+            .help("remove file matching the given filter expression, like '(older 1y or larger 1gb) and not ext pdf'")
+            .takes_value(true),
+    );
+
     let parser = parser.arg(
         Arg::with_name("verbose")
             .short("-v")
@@ -84,13 +330,31 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
             .help("send status messages to syslog"),
     );
 
-    let parser = parser.group(ArgGroup::with_name("automatic removal").args(&[
-        "older",
-        "newer",
-        "smaller",
-        "larger",
-        "duplicates",
-    ]));
+    let parser = parser.group(
+        ArgGroup::with_name("automatic removal")
+            .args(&[
+                "older",
+                "newer",
+                "smaller",
+                "larger",
+                "size",
+                "age",
+                "older-than-file",
+                "newer-than-file",
+                "smaller-than-file",
+                "larger-than-file",
+                "where",
+                "duplicates",
+            ])
+            .multiple(true),
+    );
+
+    let parser = parser.arg(
+        Arg::with_name("any")
+            .long("--any")
+            .help("remove files matching any of the given criteria, by default all of them must match")
+            .requires("automatic removal"),
+    );
 
     let parser = parser.arg(
         Arg::with_name("recursive")
@@ -125,42 +389,109 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
         .help("ignore any hidden file or directory, WORKS ONLY WITH UNIX HIDDEN: FILE NAME MUST START WITH '.'")
     );
 
-    parser.get_matches()
+    parser.get_matches_safe()
 }
 
-fn get_mode(force: bool, interactive: bool) -> engine::Mode {
+/// When to prompt: never, once or always.
+fn get_interactive<'a>(args: &'a ArgMatches<'a>) -> Option<&'a str> {
+    if args.is_present("interactive") {
+        Some("always")
+    } else if args.is_present("interactive-once") {
+        Some("once")
+    } else if args.is_present("interactive-when") {
+        Some(args.value_of("interactive-when").unwrap_or("always"))
+    } else {
+        None
+    }
+}
+
+fn get_mode(force: bool, interactive: Option<&str>, keep_going: bool) -> engine::Mode {
     if force {
         engine::Mode::Force
-    } else if interactive {
+    } else if interactive == Some("always") {
         engine::Mode::Interactive
+    } else if interactive == Some("once") {
+        engine::Mode::Once
+    } else if keep_going {
+        engine::Mode::KeepGoing
     } else {
         engine::Mode::Standard
     }
 }
 
 fn build_command<'a>(args: &'a ArgMatches<'a>) -> Option<engine::Command<'a>> {
+    let mut criteria = Vec::new();
     if args.is_present("duplicates") {
-        Some(engine::Command::Duplicates)
-    } else if args.is_present("older") {
-        let time_spec = args.value_of("older").unwrap();
-        Some(engine::Command::ByDate((time_spec, true)))
-    } else if args.is_present("newer") {
-        let time_spec = args.value_of("newer").unwrap();
-        Some(engine::Command::ByDate((time_spec, false)))
-    } else if args.is_present("smaller") {
-        let size_spec = args.value_of("smaller").unwrap();
-        Some(engine::Command::BySize((size_spec, true)))
-    } else if args.is_present("larger") {
-        let size_spec = args.value_of("larger").unwrap();
-        Some(engine::Command::BySize((size_spec, false)))
+        criteria.push(engine::Criterion::Duplicates);
+    }
+    if let Some(time_spec) = args.value_of("older") {
+        criteria.push(engine::Criterion::ByDate((time_spec, true)));
+    }
+    if let Some(time_spec) = args.value_of("newer") {
+        criteria.push(engine::Criterion::ByDate((time_spec, false)));
+    }
+    if let Some(size_spec) = args.value_of("smaller") {
+        criteria.push(engine::Criterion::BySize((size_spec, true)));
+    }
+    if let Some(size_spec) = args.value_of("larger") {
+        criteria.push(engine::Criterion::BySize((size_spec, false)));
+    }
+
+    if let Some(range) = args.value_of("size") {
+        criteria.push(engine::Criterion::SizeRange(range));
+    }
+    if let Some(range) = args.value_of("age") {
+        criteria.push(engine::Criterion::AgeRange(range));
+    }
+    if let Some(reference) = args.value_of_os("older-than-file").map(Path::new) {
+        criteria.push(engine::Criterion::ByDateReference((reference, true)));
+    }
+    if let Some(reference) = args.value_of_os("newer-than-file").map(Path::new) {
+        criteria.push(engine::Criterion::ByDateReference((reference, false)));
+    }
+    if let Some(reference) = args.value_of_os("smaller-than-file").map(Path::new) {
+        criteria.push(engine::Criterion::BySizeReference((reference, true)));
+    }
+    if let Some(reference) = args.value_of_os("larger-than-file").map(Path::new) {
+        criteria.push(engine::Criterion::BySizeReference((reference, false)));
+    }
+    if let Some(expression) = args.value_of("where") {
+        criteria.push(engine::Criterion::Where(expression));
+    }
+
+    let combine = if args.is_present("any") {
+        engine::Combine::Any
+    } else {
+        engine::Combine::All
+    };
+
+    let time_field = args
+        .value_of("time-field")
+        .and_then(engine::TimeField::from_name)
+        .unwrap_or_default();
+
+    let syntax = if args.is_present("strict") {
+        engine::Syntax::Strict
     } else {
+        engine::Syntax::Lenient
+    };
+
+    let command = engine::Command::new(criteria, combine)
+        .with_time_field(time_field)
+        .with_syntax(syntax);
+    if command.is_empty() {
         None
+    } else {
+        Some(command)
     }
 }
 
 fn build_logger<'a>(args: &'a ArgMatches<'a>) -> Option<logger::StatusLogger> {
     let mut status_logger = logger::StatusLogger::new();
-    if args.is_present("verbose") {
+    let dry_run = args.is_present("dry-run");
+    status_logger.set_dry_run(dry_run);
+    // a dry run is useless without some output
+    if args.is_present("verbose") || dry_run {
         let level = logger::get_levle_from_int(args.occurrences_of("verbose"));
         status_logger.add_verbose(level);
     }
@@ -177,52 +508,196 @@ fn build_logger<'a>(args: &'a ArgMatches<'a>) -> Option<logger::StatusLogger> {
     }
 }
 
-fn get_multiple_args<'a>(args: &'a ArgMatches<'a>, name: &str) -> Option<Vec<&'a str>> {
+fn build_action<'a>(args: &'a ArgMatches<'a>) -> std::io::Result<Box<dyn RemoveAction>> {
+    let action: Box<dyn RemoveAction> = if args.is_present("dry-run") {
+        Box::new(DryRun)
+    } else if args.is_present("trash") {
+        Box::new(Trash::new()?)
+    } else if args.is_present("journal") {
+        Box::new(Journal::new(journal::default_base()?))
+    } else if let Some(target) = args.value_of_os("move-to") {
+        let collision = args
+            .value_of("on-collision")
+            .and_then(Collision::from_name)
+            .unwrap_or_default();
+        Box::new(MoveTo::new(target).with_collision(collision))
+    } else if let Some(archive) = args.value_of_os("archive-to") {
+        Box::new(ArchiveTo::new(archive)?)
+    } else if args.is_present("shred") {
+        let passes = args
+            .value_of("shred-passes")
+            .map_or(3, |passes| passes.parse().unwrap());
+        let fill = args
+            .value_of("shred-fill")
+            .and_then(Fill::from_name)
+            .unwrap_or_default();
+        Box::new(Shred::new(passes).with_fill(fill))
+    } else {
+        Box::new(Unlink)
+    };
+    Ok(Box::new(Protected::new(action, build_protection(args)?)))
+}
+
+fn build_protection<'a>(args: &'a ArgMatches<'a>) -> std::io::Result<Protection> {
+    let preserve_root = if args.is_present("no-preserve-root") {
+        PreserveRoot::No
+    } else if args.value_of("preserve-root") == Some("all") {
+        PreserveRoot::All
+    } else {
+        PreserveRoot::Root
+    };
+    Ok(Protection::load()?.with_preserve_root(preserve_root))
+}
+
+/// The built-in units, with the ones defined by `--unit`
+/// added to the size table, or to the time table when
+/// their specification is not a size.
+fn build_units<'a>(
+    args: &'a ArgMatches<'a>,
+) -> std::io::Result<(engine::UnitTable, engine::UnitTable)> {
+    let mut size_units = engine::UnitTable::size();
+    let mut time_units = engine::UnitTable::time();
+    for definition in args.values_of("unit").into_iter().flatten() {
+        let (name, spec) = definition.split_once('=').ok_or_else(|| {
+            let msg = format!("invalid unit `{}`, expected NAME=SPEC", definition);
+            Error::new(ErrorKind::InvalidInput, msg)
+        })?;
+        if size_units.register_spec(name, spec).is_err() {
+            time_units
+                .register_spec(name, spec)
+                .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        }
+    }
+    Ok((size_units, time_units))
+}
+
+fn get_multiple_args<'a>(args: &'a ArgMatches<'a>, name: &str) -> Option<Vec<&'a OsStr>> {
     if args.is_present(name) {
-        let tmp: Vec<&str> = args.values_of(name).unwrap().collect();
+        let tmp: Vec<&OsStr> = args.values_of_os(name).unwrap().collect();
         Some(tmp)
     } else {
         None
     }
 }
 
-fn run_remove<'a>(args: ArgMatches<'a>) -> std::io::Result<()> {
-    let mode = get_mode(args.is_present("force"), args.is_present("interactive"));
+fn run_remove<'a>(args: ArgMatches<'a>) -> std::io::Result<ExitCode> {
+    let mode = get_mode(
+        args.is_present("force"),
+        get_interactive(&args),
+        args.is_present("keep-going"),
+    );
 
-    let (files, arg_set) = match args.values_of("files") {
-        Some(file_args) => (file_args.collect(), true),
-        None => (vec!["."], false),
+    let (files, arg_set) = match args.values_of_os("files") {
+        Some(file_args) => (file_args.map(Path::new).collect(), true),
+        None => (vec![Path::new(".")], false),
     };
 
     let command = build_command(&args);
     let mut log = build_logger(&args);
-    if let Some(command) = command {
+    let mut action = build_action(&args)?;
+    let failures = if let Some(command) = command {
         let clean = args.is_present("clean");
+        let (size_units, time_units) = build_units(&args)?;
         engine::automatic_remove(
             &files,
             mode,
-            command,
+            command.with_units(size_units, time_units),
             clean,
             &mut log,
             get_multiple_args(&args, "ignore-extensions"),
             get_multiple_args(&args, "ignore-directories"),
             args.is_present("hidden"),
-        )?;
+            action.as_mut(),
+        )?
     } else if arg_set {
-        engine::remove(&files, mode, args.is_present("recursive"), &mut log)?;
-    }
+        engine::remove(
+            &files,
+            mode,
+            args.is_present("recursive"),
+            &mut log,
+            action.as_mut(),
+        )?
+    } else if args.is_present("force") {
+        Failures::new()
+    } else {
+        let msg = "missing operand\nTry 'rmd --help' for more information.";
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    };
 
     if let Some(mut log) = log {
         log.log_statistics();
     }
 
+    if failures.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    for failure in failures.iter() {
+        eprintln!("rmd: {}", failure);
+    }
+    if args.is_present("keep-going") {
+        eprintln!("rmd: {}", failures);
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+fn run_restore<'a>(args: &'a ArgMatches<'a>) -> std::io::Result<ExitCode> {
+    let journal = args.value_of_os("journal").unwrap();
+    let paths: Vec<&Path> = args
+        .values_of_os("paths")
+        .map(|paths| paths.map(Path::new).collect())
+        .unwrap_or_default();
+    let restored = journal::restore(journal, &paths)?;
+    if args.is_present("verbose") {
+        for path in restored.restored {
+            println!("{}", quote(&path));
+        }
+    }
+    for error in restored.skipped.iter() {
+        eprintln!("rmd: {}", error);
+    }
+    if restored.skipped.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn run_purge<'a>(args: &'a ArgMatches<'a>) -> std::io::Result<()> {
+    let base = journal::default_base()?;
+    let purged = journal::purge(base, args.value_of("older"), engine::Syntax::Lenient)?;
+    if args.is_present("verbose") {
+        for path in purged {
+            println!("{}", quote(&path));
+        }
+    }
     Ok(())
 }
 
-fn main() {
-    let args = parse_args();
-    match run_remove(args) {
-        Ok(()) => {}
-        Err(error) => eprintln!("{}", error),
+fn run<'a>(args: ArgMatches<'a>) -> std::io::Result<ExitCode> {
+    match args.subcommand() {
+        ("restore", Some(args)) => run_restore(args),
+        ("purge", Some(args)) => run_purge(args).map(|_| ExitCode::SUCCESS),
+        _ => run_remove(args),
+    }
+}
+
+/// Exit statuses follow rm: 0 when everything was removed,
+/// 1 when something failed and 2 for an invalid command line.
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) if error.use_stderr() => {
+            eprintln!("{}", error.message);
+            return ExitCode::from(USAGE_ERROR);
+        }
+        // help and version
+        Err(error) => error.exit(),
     };
+    match run(args) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("rmd: {}", error);
+            ExitCode::FAILURE
+        }
+    }
 }
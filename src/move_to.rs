@@ -0,0 +1,402 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Error, ErrorKind, Result};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use super::dir_fd::DirFd;
+use super::failures::quote;
+use super::remove_action::RemoveAction;
+
+/// What MoveTo does when the target path is taken.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Collision {
+    /// move to the first free name among `name.1`, `name.2`...
+    #[default]
+    Suffix,
+    /// leave the entry where it is
+    Skip,
+    /// replace the existing target
+    Overwrite,
+}
+
+impl Collision {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "suffix" => Some(Collision::Suffix),
+            "skip" => Some(Collision::Skip),
+            "overwrite" => Some(Collision::Overwrite),
+            _ => None,
+        }
+    }
+}
+
+/// Move entries into a mirror directory instead of unlinking
+/// them, keeping their path relative to the root being walked,
+/// or just their name outside of a walk. Moves across
+/// filesystems fall back to copy, fsync and unlink.
+/// A target inside a walked root is not walked.
+pub struct MoveTo {
+    target: PathBuf,
+    collision: Collision,
+    root: Option<PathBuf>,
+    target_id: Option<(u64, u64)>,
+}
+
+impl MoveTo {
+    pub fn new<P: AsRef<Path>>(target: P) -> Self {
+        Self {
+            target: target.as_ref().to_path_buf(),
+            collision: Collision::default(),
+            root: None,
+            target_id: None,
+        }
+    }
+
+    pub fn with_collision(mut self, collision: Collision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    /// The device and inode of the target, once it exists.
+    fn target_id(&mut self) -> Option<(u64, u64)> {
+        if self.target_id.is_none() {
+            self.target_id = fs::metadata(&self.target)
+                .ok()
+                .map(|stat| (stat.dev(), stat.ino()));
+        }
+        self.target_id
+    }
+
+    /// Where path goes in the mirror directory.
+    fn mirror_path(&self, path: &Path) -> PathBuf {
+        let relative = self
+            .root
+            .as_ref()
+            .and_then(|root| path.strip_prefix(root).ok())
+            .filter(|relative| !relative.as_os_str().is_empty());
+        match relative {
+            Some(relative) => relative
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .fold(self.target.clone(), |acc, c| acc.join(c)),
+            None => self
+                .target
+                .join(path.file_name().unwrap_or(path.as_os_str())),
+        }
+    }
+
+    /// The target for path, according to the collision
+    /// policy, None when the entry must be skipped.
+    /// An existing target is left to move_entry.
+    fn resolve_target(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let target = self.mirror_path(path);
+        if fs::symlink_metadata(&target).is_err() {
+            return Ok(Some(target));
+        }
+        match self.collision {
+            Collision::Skip => Ok(None),
+            Collision::Overwrite => Ok(Some(target)),
+            Collision::Suffix => {
+                let name = target.file_name().unwrap_or_default().to_os_string();
+                let free = (1..)
+                    .map(|count| {
+                        let mut candidate = name.clone();
+                        candidate.push(format!(".{}", count));
+                        target.with_file_name(candidate)
+                    })
+                    .find(|candidate| fs::symlink_metadata(candidate).is_err());
+                Ok(free)
+            }
+        }
+    }
+
+    /// Move the entry to its target. A target to overwrite
+    /// is replaced at once by rename, unless one of the two
+    /// is a directory: then the target is moved aside and
+    /// only deleted once the entry took its place.
+    fn move_entry(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let target = match self.resolve_target(path)? {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        let parent = target.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(parent)?;
+        let target_dir = DirFd::open(parent)?;
+        let target_name = target.file_name().unwrap_or_default();
+
+        let aside = match target_dir.metadata(target_name) {
+            Ok(old) if old.is_dir() || dir.metadata(name)?.is_dir() => {
+                let aside = free_name(&target_dir, target_name, "rmd-old")?;
+                target_dir.rename_in(target_name, &aside)?;
+                Some(aside)
+            }
+            _ => None,
+        };
+        let result = move_across(dir, name, &target_dir, target_name);
+        match (aside, result) {
+            (Some(aside), Ok(())) => target_dir.remove_dir_all(&aside),
+            (Some(aside), Err(err)) => {
+                let _ = target_dir.rename_in(&aside, target_name);
+                Err(err)
+            }
+            (None, result) => result,
+        }
+    }
+}
+
+impl RemoveAction for MoveTo {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.move_entry(dir, name, path)
+    }
+
+    /// An empty directory whose mirror already exists,
+    /// as it holds the entries moved before, is just removed.
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        if self.mirror_path(path).is_dir() {
+            dir.remove_dir(name)
+        } else {
+            self.move_entry(dir, name, path)
+        }
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.move_entry(dir, name, path)
+    }
+
+    fn keeps_trees(&self) -> bool {
+        true
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<()> {
+        self.root = Some(root.to_path_buf());
+        self.target_id = None;
+        self.target_id();
+        Ok(())
+    }
+
+    /// The target is not walked: what it holds would
+    /// be moved into itself.
+    fn enter_dir(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<bool> {
+        let stat = dir.metadata(name)?;
+        Ok(self.target_id() != Some((stat.dev(), stat.ino())))
+    }
+}
+
+/// Move name of dir to target_name in target_dir, replacing
+/// it if it is not a directory. Across filesystems the entry
+/// is copied under a temporary name, renamed to target_name
+/// once complete and synced, and only then unlinked.
+pub(crate) fn move_across(
+    dir: &DirFd,
+    name: &OsStr,
+    target_dir: &DirFd,
+    target_name: &OsStr,
+) -> Result<()> {
+    match dir.rename_to(name, target_dir, target_name) {
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            copy_across(dir, name, target_dir, target_name)?;
+            dir.remove_dir_all(name)
+        }
+        result => result,
+    }
+}
+
+/// The copy half of move_across, a partial
+/// copy is removed on error.
+fn copy_across(dir: &DirFd, name: &OsStr, target_dir: &DirFd, target_name: &OsStr) -> Result<()> {
+    let partial = free_name(target_dir, target_name, "rmd-partial")?;
+    let result = copy_tree(dir, name, target_dir, &partial)
+        .and_then(|_| target_dir.rename_in(&partial, target_name))
+        .and_then(|_| target_dir.sync());
+    if result.is_err() {
+        let _ = target_dir.remove_dir_all(&partial);
+    }
+    result
+}
+
+/// Copy name of dir to target_name in target_dir, directories
+/// recursively, symbolic links as links, keeping permissions
+/// and times. Each copied file is synced to disk.
+fn copy_tree(dir: &DirFd, name: &OsStr, target_dir: &DirFd, target_name: &OsStr) -> Result<()> {
+    let stat = dir.metadata(name)?;
+    if stat.is_dir() {
+        target_dir.create_dir(target_name, 0o700)?;
+        let source = dir.open_dir(name)?;
+        let target = target_dir.open_dir(target_name)?;
+        for entry in source.entries()? {
+            copy_tree(&source, &entry, &target, &entry)?;
+        }
+        target.sync()?;
+    } else if stat.is_symlink() {
+        target_dir.symlink(&dir.read_link(name)?, target_name)?;
+    } else if stat.is_file() {
+        let mut source = dir.open_file(name)?;
+        let mut target = target_dir.create_file(target_name, 0o600)?;
+        io::copy(&mut source, &mut target)?;
+        target.sync_all()?;
+    } else {
+        let msg = format!(
+            "cannot copy {}: not a file, directory or link",
+            quote(Path::new(name))
+        );
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    target_dir.copy_status(target_name, &stat)
+}
+
+/// A name like `.name.tag.1` that is free in dir.
+fn free_name(dir: &DirFd, name: &OsStr, tag: &str) -> Result<OsString> {
+    for count in 1.. {
+        let mut candidate = OsString::from(".");
+        candidate.push(name);
+        candidate.push(format!(".{}.{}", tag, count));
+        match dir.metadata(&candidate) {
+            Ok(_) => continue,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(candidate),
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::ffi::CString;
+    use std::fs::{create_dir_all, read_to_string, File, FileTimes};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::symlink;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_move_to() {
+        let root = TempDir::new().unwrap();
+        let scan = root.path().join("scan");
+        let target = root.path().join("target");
+        create_dir_all(scan.join("a").join("b")).unwrap();
+        let file = scan.join("a").join("b").join("file");
+        fs::write(&file, "first").unwrap();
+
+        let mut action = MoveTo::new(&target);
+        action.enter_root(&scan).unwrap();
+        let dir = DirFd::open(scan.join("a").join("b")).unwrap();
+        action.remove_file(&dir, OsStr::new("file"), &file).unwrap();
+        let moved = target.join("a").join("b").join("file");
+        assert_eq!(read_to_string(&moved).unwrap(), "first");
+
+        // the empty directory left behind is merged
+        let dir = DirFd::open(scan.join("a")).unwrap();
+        let path = scan.join("a").join("b");
+        action.remove_dir(&dir, OsStr::new("b"), &path).unwrap();
+        assert!(!path.exists());
+        assert!(moved.exists());
+    }
+
+    #[test]
+    fn test_collision() {
+        let root = TempDir::new().unwrap();
+        let target = root.path().join("target");
+        create_dir_all(&target).unwrap();
+        fs::write(target.join("file"), "old").unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        let file = root.path().join("file");
+        let policies = [
+            (Collision::Suffix, "file.1"),
+            (Collision::Skip, "file"),
+            (Collision::Overwrite, "file"),
+        ];
+        for (collision, name) in policies.iter() {
+            fs::write(&file, format!("{:?}", collision)).unwrap();
+            let mut action = MoveTo::new(&target).with_collision(*collision);
+            action.remove_file(&dir, OsStr::new("file"), &file).unwrap();
+            let content = read_to_string(target.join(name)).unwrap();
+            match collision {
+                Collision::Skip => {
+                    assert_eq!(content, "old");
+                    assert!(file.exists());
+                }
+                _ => assert_eq!(content, format!("{:?}", collision)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_overwrite_directory() {
+        let root = TempDir::new().unwrap();
+        let target = root.path().join("target");
+        create_dir_all(target.join("file").join("sub")).unwrap();
+        fs::write(target.join("file").join("sub").join("old"), "old").unwrap();
+        let file = root.path().join("file");
+        fs::write(&file, "new").unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        let mut action = MoveTo::new(&target).with_collision(Collision::Overwrite);
+        action.remove_file(&dir, OsStr::new("file"), &file).unwrap();
+        assert_eq!(read_to_string(target.join("file")).unwrap(), "new");
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 1);
+
+        // on error the old target is put back
+        let missing = root.path().join("missing");
+        create_dir_all(target.join("missing")).unwrap();
+        assert!(action
+            .remove_file(&dir, OsStr::new("missing"), &missing)
+            .is_err());
+        assert!(target.join("missing").is_dir());
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_copy_tree() {
+        let root = TempDir::new().unwrap();
+        let source = root.path().join("source");
+        create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("sub").join("file"), "data").unwrap();
+        symlink("sub/file", source.join("link")).unwrap();
+        let old = SystemTime::now() - Duration::new(3600, 0);
+        let times = FileTimes::new().set_modified(old);
+        File::options()
+            .write(true)
+            .open(source.join("sub").join("file"))
+            .unwrap()
+            .set_times(times)
+            .unwrap();
+        File::open(source.join("sub"))
+            .unwrap()
+            .set_times(times)
+            .unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        copy_across(&dir, OsStr::new("source"), &dir, OsStr::new("target")).unwrap();
+        let target = root.path().join("target");
+        assert_eq!(
+            read_to_string(target.join("sub").join("file")).unwrap(),
+            "data"
+        );
+        assert_eq!(
+            fs::read_link(target.join("link")).unwrap(),
+            PathBuf::from("sub/file")
+        );
+        for name in ["sub", "sub/file"].iter() {
+            let modified = fs::metadata(target.join(name)).unwrap().modified().unwrap();
+            assert_eq!(modified, old, "{}", name);
+        }
+
+        // a partial copy is removed
+        let fifo = CString::new(source.join("fifo").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+        assert!(copy_across(&dir, OsStr::new("source"), &dir, OsStr::new("copy")).is_err());
+        let mut names: Vec<OsString> = fs::read_dir(root.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![OsString::from("source"), OsString::from("target")]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use super::failures::quote;
+
+/// `$XDG_DATA_HOME`, or `$HOME/.local/share`.
+pub fn data_home() -> Result<PathBuf> {
+    match (env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+        (Some(data_home), _) if !data_home.is_empty() => Ok(PathBuf::from(data_home)),
+        (_, Some(home)) => Ok(Path::new(&home).join(".local").join("share")),
+        _ => {
+            let msg = "cannot find the data directory: neither XDG_DATA_HOME nor HOME is set";
+            Err(Error::new(ErrorKind::NotFound, msg))
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME`, or `$HOME/.config`.
+pub fn config_home() -> Result<PathBuf> {
+    match (env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) {
+        (Some(config_home), _) if !config_home.is_empty() => Ok(PathBuf::from(config_home)),
+        (_, Some(home)) => Ok(Path::new(&home).join(".config")),
+        _ => {
+            let msg = "cannot find the config directory: neither XDG_CONFIG_HOME nor HOME is set";
+            Err(Error::new(ErrorKind::NotFound, msg))
+        }
+    }
+}
+
+/// The absolute path of path, whose last
+/// component is never resolved.
+pub fn absolute(path: &Path) -> Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} has no file name", quote(path)),
+        )
+    })?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(fs::canonicalize(parent)?.join(name))
+}
+
+/// Escape path as an URL path, as required by trash info files.
+pub fn url_escape(path: &OsStr) -> String {
+    let mut output = String::new();
+    for byte in path.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                output.push(*byte as char)
+            }
+            _ => output.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    output
+}
+
+/// Reverse url_escape, fails on a malformed escape.
+pub fn url_unescape(path: &str) -> Result<OsString> {
+    let bytes = path.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let byte = path
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| {
+                    let msg = format!("malformed escape in `{}`", path);
+                    Error::new(ErrorKind::InvalidData, msg)
+                })?;
+            output.push(byte);
+            index += 3;
+        } else {
+            output.push(bytes[index]);
+            index += 1;
+        }
+    }
+    Ok(OsString::from_vec(output))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_url_escape() {
+        assert_eq!(url_escape(OsStr::new("/home/a b/%c")), "/home/a%20b/%25c");
+        assert_eq!(url_escape(OsStr::new("/tmp/\u{e8}")), "/tmp/%C3%A8");
+
+        let path = OsStr::new("/tmp/a b/\u{e8}%");
+        assert_eq!(url_unescape(&url_escape(path)).unwrap(), path);
+        assert!(url_unescape("/tmp/%2").is_err());
+        assert!(url_unescape("/tmp/%zz").is_err());
+    }
+}
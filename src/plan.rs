@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+
+use super::dir_fd::{DirFd, Stat};
+use super::failures::{keep_going, quote, Failures};
+use super::logger::{self, StatusLogger};
+use super::remove_action::RemoveAction;
+
+/// How many directories the summary lists.
+const TOP_DIRECTORIES: usize = 3;
+
+/// One call to make on the action, in walk order.
+enum Step {
+    Root(PathBuf),
+    File(Target),
+    Dir(Target),
+    Tree(Target),
+}
+
+/// An entry to remove, with the device and inode of its
+/// parent directory and of itself when it was planned:
+/// path is resolved again when the plan is carried out,
+/// and must still lead to the same entry.
+struct Target {
+    path: PathBuf,
+    parent: (u64, u64),
+    entry: (u64, u64),
+}
+
+impl Target {
+    fn new(dir: &DirFd, name: &OsStr, path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            parent: id(&dir.status()?),
+            entry: id(&dir.metadata(name)?),
+        })
+    }
+
+    /// Open the parent directory of the entry again.
+    fn open_parent(&self) -> Result<(DirFd, OsString)> {
+        let (dir, name) = DirFd::open_parent(&self.path)?;
+        if id(&dir.status()?) != self.parent || id(&dir.metadata(&name)?) != self.entry {
+            let msg = format!(
+                "{} changed since the removal was confirmed",
+                quote(&self.path)
+            );
+            return Err(Error::other(msg));
+        }
+        Ok((dir, name))
+    }
+}
+
+/// Record what a run would remove instead of removing it, so
+/// that it can be confirmed at once, then carried out by action.
+/// Roots and sub directories are still given to action, so
+/// that it can refuse them before anything is asked.
+pub struct Plan<'a> {
+    action: &'a mut dyn RemoveAction,
+    steps: Vec<Step>,
+    files: u64,
+    dirs: u64,
+    size: u64,
+    /// entries to remove in each directory
+    directories: HashMap<PathBuf, u64>,
+    roots: usize,
+    root_used: bool,
+}
+
+impl<'a> Plan<'a> {
+    pub fn new(action: &'a mut dyn RemoveAction) -> Self {
+        Self {
+            action,
+            steps: Vec::new(),
+            files: 0,
+            dirs: 0,
+            size: 0,
+            directories: HashMap::new(),
+            roots: 0,
+            root_used: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files == 0 && self.dirs == 0
+    }
+
+    /// Give every step to action. Entries are
+    /// logged as they are removed, like in a
+    /// run without plan.
+    pub fn carry_out(
+        self,
+        log: &mut Option<StatusLogger>,
+        failures: &mut Option<Failures>,
+    ) -> Result<()> {
+        let action = self.action;
+        for step in self.steps {
+            let target = match &step {
+                Step::Root(root) => {
+                    keep_going(action.enter_root(root), root, failures)?;
+                    continue;
+                }
+                Step::File(target) | Step::Dir(target) | Step::Tree(target) => target,
+            };
+            let path = &target.path;
+            let removed = logger::add_file_remove_log(log, path).and_then(|_| {
+                let (dir, name) = target.open_parent()?;
+                match step {
+                    Step::File(_) => action.remove_file(&dir, &name, path),
+                    Step::Dir(_) => action.remove_dir(&dir, &name, path),
+                    _ => action.remove_dir_all(&dir, &name, path),
+                }
+            });
+            if keep_going(removed, path, failures)?.is_some() {
+                logger::output_file_remove_log(log);
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, step: Step, directory: &Path, entries: u64) {
+        *self.directories.entry(directory.to_path_buf()).or_default() += entries;
+        if !self.root_used {
+            self.root_used = true;
+            self.roots += 1;
+        }
+        self.steps.push(step);
+    }
+
+    /// Count the files, directories and bytes of a tree.
+    fn measure(&mut self, dir: &DirFd, name: &OsStr) -> Result<u64> {
+        let stat = dir.metadata(name)?;
+        if !stat.is_dir() {
+            self.files += 1;
+            self.size += stat.len();
+            return Ok(1);
+        }
+        self.dirs += 1;
+        let sub_dir = dir.open_dir(name)?;
+        let mut entries = 1;
+        for entry in sub_dir.entries()? {
+            entries += self.measure(&sub_dir, &entry)?;
+        }
+        Ok(entries)
+    }
+}
+
+impl RemoveAction for Plan<'_> {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let target = Target::new(dir, name, path)?;
+        self.size += dir.metadata(name)?.len();
+        self.files += 1;
+        self.add(Step::File(target), &parent(path), 1);
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let target = Target::new(dir, name, path)?;
+        self.dirs += 1;
+        self.add(Step::Dir(target), &parent(path), 1);
+        Ok(())
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let target = Target::new(dir, name, path)?;
+        let entries = self.measure(dir, name)?;
+        self.add(Step::Tree(target), path, entries);
+        Ok(())
+    }
+
+    fn keeps_trees(&self) -> bool {
+        self.action.keeps_trees()
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<()> {
+        self.action.enter_root(root)?;
+        self.root_used = false;
+        self.steps.push(Step::Root(root.to_path_buf()));
+        Ok(())
+    }
+
+    fn enter_tree(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.action.enter_tree(dir, name, path)
+    }
+
+    fn enter_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool> {
+        self.action.enter_dir(dir, name, path)
+    }
+}
+
+/// The directories with the most entries to
+/// remove, then the question to confirm them all:
+/// `remove 12,403 files (48.00 Gb) from 3 roots?`
+impl fmt::Display for Plan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut directories: Vec<(&PathBuf, &u64)> = self.directories.iter().collect();
+        directories.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (directory, entries) in directories.iter().take(TOP_DIRECTORIES) {
+            writeln!(f, "  {} in {}", group_digits(**entries), quote(directory))?;
+        }
+
+        write!(f, "remove {}", plural(self.files, "file", "files"))?;
+        if self.dirs > 0 {
+            write!(f, " and {}", plural(self.dirs, "directory", "directories"))?;
+        }
+        write!(f, " ({})", logger::format_size(self.size))?;
+        if self.roots > 0 {
+            write!(f, " from {}", plural(self.roots as u64, "root", "roots"))?;
+        }
+        write!(f, "?")
+    }
+}
+
+fn id(stat: &Stat) -> (u64, u64) {
+    (stat.dev(), stat.ino())
+}
+
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn plural(count: u64, one: &str, many: &str) -> String {
+    format!(
+        "{} {}",
+        group_digits(count),
+        if count == 1 { one } else { many }
+    )
+}
+
+/// `12403` as `12,403`.
+fn group_digits(number: u64) -> String {
+    let digits = number.to_string();
+    let mut output = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            output.push(',');
+        }
+        output.push(digit);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::remove_action::Unlink;
+    use std::fs::{self, create_dir_all};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(12403), "12,403");
+        assert_eq!(group_digits(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_plan() {
+        let root = TempDir::new().unwrap();
+        let tree = root.path().join("tree");
+        create_dir_all(tree.join("sub")).unwrap();
+        fs::write(tree.join("sub").join("a"), "1234").unwrap();
+        fs::write(root.path().join("b"), "12").unwrap();
+        fs::write(root.path().join("c"), "1").unwrap();
+
+        let mut action = Unlink;
+        let mut plan = Plan::new(&mut action);
+        let dir = DirFd::open(root.path()).unwrap();
+        plan.enter_root(root.path()).unwrap();
+        plan.remove_dir_all(&dir, OsStr::new("tree"), &tree)
+            .unwrap();
+        plan.remove_file(&dir, OsStr::new("b"), &root.path().join("b"))
+            .unwrap();
+        assert!(tree.exists());
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "  3 in '{}'\n  1 in '{}'\nremove 2 files and 2 directories (6.00 b) from 1 root?",
+                tree.display(),
+                root.path().display()
+            )
+        );
+
+        plan.carry_out(&mut None, &mut None).unwrap();
+        assert!(!tree.exists());
+        assert!(!root.path().join("b").exists());
+        assert!(root.path().join("c").exists());
+    }
+
+    #[test]
+    fn test_replaced_after_planning() {
+        let root = TempDir::new().unwrap();
+        let tree = root.path().join("tree");
+        let other = root.path().join("other");
+        create_dir_all(&tree).unwrap();
+        create_dir_all(&other).unwrap();
+        fs::write(tree.join("a"), "planned").unwrap();
+        fs::write(tree.join("b"), "planned").unwrap();
+
+        let mut action = Unlink;
+        let mut plan = Plan::new(&mut action);
+        let dir = DirFd::open(&tree).unwrap();
+        plan.remove_file(&dir, OsStr::new("a"), &tree.join("a"))
+            .unwrap();
+        plan.remove_file(&dir, OsStr::new("b"), &tree.join("b"))
+            .unwrap();
+
+        // a is replaced, then the whole tree is swapped
+        fs::write(tree.join("new"), "new").unwrap();
+        fs::rename(tree.join("new"), tree.join("a")).unwrap();
+        fs::write(other.join("b"), "other").unwrap();
+        let mut failures = Some(Failures::new());
+        plan.carry_out(&mut None, &mut failures).unwrap();
+        assert_eq!(failures.unwrap().len(), 1);
+        assert_eq!(fs::read(tree.join("a")).unwrap(), b"new");
+        assert!(!tree.join("b").exists());
+
+        fs::write(tree.join("b"), "planned").unwrap();
+        let mut plan = Plan::new(&mut action);
+        plan.remove_file(&dir, OsStr::new("b"), &tree.join("b"))
+            .unwrap();
+        fs::rename(&tree, root.path().join("moved")).unwrap();
+        fs::rename(&other, &tree).unwrap();
+        let error = plan.carry_out(&mut None, &mut None).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("changed since the removal was confirmed"));
+        assert_eq!(fs::read(tree.join("b")).unwrap(), b"other");
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use super::dir_fd::DirFd;
+use super::failures::quote;
+use super::logger::{self, StatusLogger};
+use super::paths;
+use super::remove_action::RemoveAction;
+
+/// Directories protected by default, besides `$HOME`.
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib32", "/lib64", "/opt", "/proc", "/root",
+    "/run", "/sbin", "/srv", "/sys", "/usr", "/var",
+];
+
+/// Read before the configuration of the user.
+const SYSTEM_CONFIG: &str = "/etc/rmd/protected";
+
+/// What `--preserve-root` refuses, like rm.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PreserveRoot {
+    /// `--no-preserve-root`
+    No,
+    /// never work recursively on `/`
+    #[default]
+    Root,
+    /// also refuse operands on another device than their parent
+    All,
+}
+
+/// A file is known by its device and inode numbers,
+/// so that no link or bind mount can get around it.
+type Id = (u64, u64);
+
+/// The paths rmd refuses to remove or to walk into.
+#[derive(Default)]
+pub struct Protection {
+    preserve_root: PreserveRoot,
+    root: Option<Id>,
+    paths: HashMap<Id, PathBuf>,
+    /// checked by path, as a recursive removal
+    /// is refused when one lies inside the tree
+    mounts: Vec<PathBuf>,
+    /// configuration lines that were ignored
+    warnings: Vec<String>,
+}
+
+impl Protection {
+    /// Protect `/` only.
+    pub fn new() -> Self {
+        Self {
+            root: id_of(Path::new("/")).ok(),
+            ..Self::default()
+        }
+    }
+
+    /// Protect the system directories, `$HOME`, every mount
+    /// point and the paths listed in `/etc/rmd/protected`
+    /// and `$XDG_CONFIG_HOME/rmd/protected`.
+    pub fn load() -> Result<Self> {
+        let mut protection = Self::new();
+        for dir in SYSTEM_DIRS {
+            protection.protect(Path::new(dir));
+        }
+        if let Some(home) = env::var_os("HOME") {
+            protection.protect(Path::new(&home));
+        }
+        protection.mounts = mount_points();
+        protection.read_config(Path::new(SYSTEM_CONFIG))?;
+        if let Ok(config_home) = paths::config_home() {
+            protection.read_config(&config_home.join("rmd").join("protected"))?;
+        }
+        Ok(protection)
+    }
+
+    pub fn with_preserve_root(mut self, preserve_root: PreserveRoot) -> Self {
+        self.preserve_root = preserve_root;
+        self
+    }
+
+    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.protect(path.as_ref());
+        self
+    }
+
+    /// Paths that do not exist have nothing to protect.
+    fn protect(&mut self, path: &Path) {
+        if let (Ok(id), Ok(path)) = (id_of(path), fs::canonicalize(path)) {
+            self.paths.insert(id, path);
+        }
+    }
+
+    fn unprotect(&mut self, path: &Path) {
+        if let Ok(id) = id_of(path) {
+            self.paths.remove(&id);
+        }
+    }
+
+    /// One absolute path per line, a leading `!` takes
+    /// a path out of the list. Blank lines and lines
+    /// starting with `#` are ignored, other lines that
+    /// are not absolute paths are ignored with a warning.
+    /// A missing file is an empty list.
+    fn read_config(&mut self, file: &Path) -> Result<()> {
+        let content = match fs::read_to_string(file) {
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            content => content?,
+        };
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (remove, path) = match line.strip_prefix('!') {
+                Some(path) => (true, Path::new(path.trim_start())),
+                None => (false, Path::new(line)),
+            };
+            if !path.is_absolute() {
+                self.warnings.push(format!(
+                    "{}, line {}: {} is not an absolute path, it is ignored",
+                    quote(file),
+                    index + 1,
+                    quote(path)
+                ));
+                continue;
+            }
+            if remove {
+                self.unprotect(path);
+            } else {
+                self.protect(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse to walk `/` or, with `--preserve-root=all`,
+    /// a directory on another device than its parent. A
+    /// protected directory can be walked when given as
+    /// root, it is still never removed.
+    fn check_root(&self, root: &Path) -> Result<()> {
+        let meta = fs::metadata(root)?;
+        let id = (meta.dev(), meta.ino());
+        if self.preserve_root != PreserveRoot::No && Some(id) == self.root {
+            return Err(dangerous(root));
+        }
+        if self.preserve_root == PreserveRoot::All
+            && fs::metadata(root.join(".."))?.dev() != meta.dev()
+        {
+            return Err(different_device(root));
+        }
+        Ok(())
+    }
+
+    /// Refuse to remove a protected path or a mount point, and
+    /// when recursive `/` or a tree holding a protected path.
+    fn check_remove(&self, dir: &DirFd, name: &OsStr, path: &Path, recursive: bool) -> Result<()> {
+        let stat = dir.metadata(name)?;
+        let id = (stat.dev(), stat.ino());
+        if recursive && self.preserve_root != PreserveRoot::No && Some(id) == self.root {
+            return Err(dangerous(path));
+        }
+        if self.paths.contains_key(&id) {
+            return Err(refuse(format!(
+                "refusing to remove protected path {}",
+                quote(path)
+            )));
+        }
+        let all = self.preserve_root == PreserveRoot::All;
+        if (stat.is_dir() || all) && dir.metadata(OsStr::new("."))?.dev() != stat.dev() {
+            return Err(if all {
+                different_device(path)
+            } else {
+                refuse(format!("refusing to remove mount point {}", quote(path)))
+            });
+        }
+        if recursive && stat.is_dir() {
+            let tree = paths::absolute(path)?;
+            let inside = self
+                .paths
+                .values()
+                .chain(self.mounts.iter())
+                .find(|protected| protected.starts_with(&tree) && **protected != tree);
+            if let Some(protected) = inside {
+                return Err(refuse(format!(
+                    "refusing to remove {}: it contains protected path {}",
+                    quote(path),
+                    quote(protected)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse to walk into `/`. A protected directory or
+    /// a mount point is skipped like `find -xdev` does:
+    /// return what it is.
+    fn check_enter(&self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<Option<&'static str>> {
+        let stat = dir.metadata(name)?;
+        let id = (stat.dev(), stat.ino());
+        if self.preserve_root != PreserveRoot::No && Some(id) == self.root {
+            return Err(dangerous(path));
+        }
+        if self.paths.contains_key(&id) {
+            return Ok(Some("protected path"));
+        }
+        if dir.metadata(OsStr::new("."))?.dev() != stat.dev() {
+            return Ok(Some("mount point"));
+        }
+        Ok(None)
+    }
+}
+
+/// Check every entry against protection
+/// before giving it to action.
+pub struct Protected {
+    action: Box<dyn RemoveAction>,
+    protection: Protection,
+    /// directories not walked, reported by finish
+    skipped: Vec<String>,
+}
+
+impl Protected {
+    pub fn new(action: Box<dyn RemoveAction>, protection: Protection) -> Self {
+        Self {
+            action,
+            protection,
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl RemoveAction for Protected {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.protection.check_remove(dir, name, path, false)?;
+        self.action.remove_file(dir, name, path)
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.protection.check_remove(dir, name, path, false)?;
+        self.action.remove_dir(dir, name, path)
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.protection.check_remove(dir, name, path, true)?;
+        self.action.remove_dir_all(dir, name, path)
+    }
+
+    fn keeps_trees(&self) -> bool {
+        self.action.keeps_trees()
+    }
+
+    fn enter_root(&mut self, root: &Path) -> Result<()> {
+        self.protection.check_root(root)?;
+        self.action.enter_root(root)
+    }
+
+    fn enter_tree(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.protection.check_remove(dir, name, path, true)?;
+        self.action.enter_tree(dir, name, path)
+    }
+
+    fn enter_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<bool> {
+        if let Some(kind) = self.protection.check_enter(dir, name, path)? {
+            self.skipped
+                .push(format!("skipping {} {}", kind, quote(path)));
+            return Ok(false);
+        }
+        self.action.enter_dir(dir, name, path)
+    }
+
+    fn finish(&mut self, log: &mut Option<StatusLogger>) -> Result<()> {
+        let warnings = self.protection.warnings.drain(..);
+        for warning in warnings.chain(self.skipped.drain(..)) {
+            logger::add_warning_log(log, &warning);
+        }
+        self.action.finish(log)
+    }
+}
+
+fn id_of(path: &Path) -> Result<Id> {
+    let meta = fs::metadata(path)?;
+    Ok((meta.dev(), meta.ino()))
+}
+
+fn refuse(msg: String) -> Error {
+    Error::new(ErrorKind::PermissionDenied, msg)
+}
+
+fn dangerous(path: &Path) -> Error {
+    refuse(format!(
+        "it is dangerous to operate recursively on {}\nrmd: use --no-preserve-root to override this failsafe",
+        quote(path)
+    ))
+}
+
+fn different_device(path: &Path) -> Error {
+    refuse(format!(
+        "skipping {}, since it's on a different device\nrmd: and --preserve-root=all is in effect",
+        quote(path)
+    ))
+}
+
+/// The mount points listed by the kernel, none
+/// when they cannot be read.
+fn mount_points() -> Vec<PathBuf> {
+    let mounts = fs::read("/proc/self/mounts").unwrap_or_default();
+    mounts
+        .split(|c| *c == b'\n')
+        .filter_map(|line| line.split(|c| *c == b' ').nth(1))
+        .map(|field| PathBuf::from(unescape(field)))
+        .collect()
+}
+
+/// Decode the `\040` like escapes of a mount point.
+fn unescape(field: &[u8]) -> OsString {
+    let mut output = Vec::with_capacity(field.len());
+    let mut index = 0;
+    while index < field.len() {
+        let code = field.get(index + 1..index + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match code {
+            Some(code) if field[index] == b'\\' => {
+                output.push(code);
+                index += 4;
+            }
+            _ => {
+                output.push(field[index]);
+                index += 1;
+            }
+        }
+    }
+    OsString::from_vec(output)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::remove_action::{DryRun, Unlink};
+    use std::fs::{create_dir_all, File};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_protected() {
+        let root = TempDir::new().unwrap();
+        let tree = root.path().join("tree");
+        let keep = tree.join("keep");
+        create_dir_all(&keep).unwrap();
+        File::create(tree.join("file")).unwrap();
+
+        let protection = Protection::new().with_path(&keep);
+        let mut action = Protected::new(Box::new(Unlink), protection);
+        let dir = DirFd::open(&tree).unwrap();
+        let error = action
+            .remove_dir_all(&dir, OsStr::new("keep"), &keep)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert!(!action.enter_dir(&dir, OsStr::new("keep"), &keep).unwrap());
+        assert_eq!(
+            action.skipped,
+            vec![format!("skipping protected path {}", quote(&keep))]
+        );
+        action
+            .remove_file(&dir, OsStr::new("file"), &tree.join("file"))
+            .unwrap();
+        assert!(!tree.join("file").exists());
+
+        let parent = DirFd::open(root.path()).unwrap();
+        let error = action
+            .remove_dir_all(&parent, OsStr::new("tree"), &tree)
+            .unwrap_err();
+        assert!(error.to_string().contains("it contains protected path"));
+        assert!(keep.exists());
+
+        // a protected root is walked, but never removed
+        action.enter_root(&keep).unwrap();
+        let error = action
+            .remove_dir(&dir, OsStr::new("keep"), &keep)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        action.finish(&mut None).unwrap();
+        assert!(action.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_preserve_root() {
+        let mut action = Protected::new(Box::new(DryRun), Protection::new());
+        let error = action.enter_root(Path::new("/")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "it is dangerous to operate recursively on '/'\nrmd: use --no-preserve-root to override this failsafe"
+        );
+        let root = TempDir::new().unwrap();
+        action.enter_root(root.path()).unwrap();
+
+        let protection = Protection::new().with_preserve_root(PreserveRoot::No);
+        let mut action = Protected::new(Box::new(DryRun), protection);
+        action.enter_root(Path::new("/")).unwrap();
+    }
+
+    #[test]
+    fn test_read_config() {
+        let root = TempDir::new().unwrap();
+        let first = root.path().join("first");
+        let second = root.path().join("second");
+        create_dir_all(&first).unwrap();
+        create_dir_all(&second).unwrap();
+        let config = root.path().join("protected");
+        let content = format!(
+            "# protected\n\n{}\n{}\n! {}\n{}\n",
+            first.display(),
+            second.display(),
+            second.display(),
+            root.path().join("missing").display()
+        );
+        fs::write(&config, content).unwrap();
+
+        let mut protection = Protection::new();
+        protection.read_config(&config).unwrap();
+        assert_eq!(protection.paths.len(), 1);
+        assert!(protection.paths.contains_key(&id_of(&first).unwrap()));
+
+        protection.read_config(&root.path().join("none")).unwrap();
+        assert!(protection.warnings.is_empty());
+        fs::write(&config, format!("relative\n{}\n", second.display())).unwrap();
+        protection.read_config(&config).unwrap();
+        assert_eq!(protection.paths.len(), 2);
+        assert_eq!(protection.warnings.len(), 1);
+        assert!(protection.warnings[0].contains("line 1"));
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(br"/mnt/a\040b"), OsString::from("/mnt/a b"));
+        assert_eq!(unescape(br"/mnt/\134x\04"), OsString::from(r"/mnt/\x\04"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::ffi::OsStr;
+use std::io::{Error, Result};
+use std::path::Path;
+
+use super::dir_fd::DirFd;
+use super::logger::StatusLogger;
+
+/// What is done to a file or a directory once it has been
+/// chosen for removal. Each entry is given as name, relative
+/// to dir, and as path, the full path used for reporting.
+pub trait RemoveAction {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()>;
+
+    /// Remove an empty directory.
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()>;
+
+    /// Remove a directory and all its content.
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()>;
+
+    /// Whether remove_dir_all keeps a tree whole, so that it can
+    /// be put back at once: a recursive engine::remove then gives
+    /// it the tree instead of removing it entry by entry.
+    fn keeps_trees(&self) -> bool {
+        false
+    }
+
+    /// Called by file_remover before walking root.
+    fn enter_root(&mut self, _root: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called by engine::remove before removing the
+    /// tree name of dir entry by entry.
+    fn enter_tree(&mut self, _dir: &DirFd, _name: &OsStr, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before walking the sub directory name of
+    /// dir, the walk skips it when false is returned.
+    fn enter_dir(&mut self, _dir: &DirFd, _name: &OsStr, _path: &Path) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Called once every entry has been given, for
+    /// actions that defer part of their work.
+    fn finish(&mut self, _log: &mut Option<StatusLogger>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Unlink each entry, the default action.
+pub struct Unlink;
+
+impl RemoveAction for Unlink {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        dir.remove_file(name)
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        dir.remove_dir(name)
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        dir.remove_dir_all(name)
+    }
+}
+
+/// Leave every entry in place. Entries are still checked
+/// to exist, and a directory is never removed as a file,
+/// so the errors of a real run are reported.
+pub struct DryRun;
+
+impl RemoveAction for DryRun {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        if dir.metadata(name)?.is_dir() {
+            Err(Error::from_raw_os_error(libc::EISDIR))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        dir.metadata(name).map(|_| ())
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        dir.metadata(name).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{create_dir, File};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dry_run() {
+        let root = TempDir::new().unwrap();
+        create_dir(root.path().join("dir")).unwrap();
+        File::create(root.path().join("file")).unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        let mut action = DryRun;
+        let path = root.path();
+        assert!(action.remove_file(&dir, OsStr::new("file"), path).is_ok());
+        assert!(action.remove_file(&dir, OsStr::new("dir"), path).is_err());
+        assert!(action.remove_file(&dir, OsStr::new("none"), path).is_err());
+        assert!(action.remove_dir(&dir, OsStr::new("dir"), path).is_ok());
+        assert!(action.remove_dir_all(&dir, OsStr::new("dir"), path).is_ok());
+
+        assert!(root.path().join("file").exists());
+        assert!(root.path().join("dir").exists());
+    }
+}
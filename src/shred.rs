@@ -0,0 +1,378 @@
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::dir_fd::{DirFd, Stat};
+use super::failures::quote;
+use super::logger::{self, StatusLogger};
+use super::remove_action::RemoveAction;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// What Shred writes over a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fill {
+    #[default]
+    Random,
+    Zeros,
+}
+
+impl Fill {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "random" => Some(Fill::Random),
+            "zeros" => Some(Fill::Zeros),
+            _ => None,
+        }
+    }
+}
+
+/// Overwrite regular files before unlinking them: each pass
+/// is synced to disk, then the file is truncated and renamed
+/// to shorter and shorter names to scrub its name from the
+/// directory. This only helps when the filesystem writes
+/// in place: copy-on-write and log-structured filesystems,
+/// snapshots and SSD wear leveling keep the old blocks.
+/// A file with other hard links is only unlinked, its data
+/// is still in use.
+pub struct Shred {
+    passes: usize,
+    fill: Fill,
+    random: SystemRandom,
+    /// devices already reported as copy-on-write
+    warned: HashSet<u64>,
+    /// reported by finish
+    warnings: Vec<String>,
+}
+
+impl Shred {
+    pub fn new(passes: usize) -> Self {
+        Self {
+            passes,
+            fill: Fill::default(),
+            random: SystemRandom::new(),
+            warned: HashSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn with_fill(mut self, fill: Fill) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    fn shred_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let stat = dir.metadata(name)?;
+        if stat.is_dir() {
+            return Err(Error::from_raw_os_error(libc::EISDIR));
+        }
+        if !stat.is_file() {
+            // links and special files have no content to overwrite
+            return dir.remove_file(name);
+        }
+
+        let file = if stat.links() > 1 {
+            None
+        } else {
+            open_for_writing(dir, name, &stat)?
+        };
+        // the link count is checked again on the file actually opened
+        let links = match &file {
+            Some((file, _)) => file.metadata()?.nlink(),
+            None => stat.links(),
+        };
+        if links > 1 {
+            self.warnings.push(format!(
+                "{} has other hard links: it is unlinked, not overwritten",
+                quote(path)
+            ));
+            return dir.remove_file(name);
+        }
+        let (mut file, widened) = match file {
+            Some(opened) => opened,
+            None => {
+                self.warnings.push(format!(
+                    "{} cannot be written: it is unlinked, not overwritten",
+                    quote(path)
+                ));
+                return dir.remove_file(name);
+            }
+        };
+        self.warn_copy_on_write(&file, stat.dev(), path);
+        let overwritten = self.overwrite_all(&mut file, stat.len());
+        drop(file);
+        if let Err(err) = overwritten {
+            if widened {
+                // the file keeps the permissions it was found with
+                let _ = dir.set_permissions(name, stat.permissions());
+            }
+            return Err(err);
+        }
+
+        let name = scrub_name(dir, name)?;
+        dir.remove_file(&name)
+    }
+
+    fn shred_tree(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        if dir.metadata(name)?.is_dir() {
+            let sub_dir = dir.open_dir(name)?;
+            for entry in sub_dir.entries()? {
+                self.shred_tree(&sub_dir, &entry, &path.join(&entry))?;
+            }
+            drop(sub_dir);
+            dir.remove_dir(name)
+        } else {
+            self.shred_file(dir, name, path)
+        }
+    }
+
+    /// Write every pass over file, then truncate it.
+    fn overwrite_all(&self, file: &mut File, size: u64) -> Result<()> {
+        for _ in 0..self.passes {
+            self.overwrite(file, size)?;
+        }
+        file.set_len(0)?;
+        file.sync_all()
+    }
+
+    /// Write one pass over the first size bytes of file.
+    fn overwrite(&self, file: &mut File, size: u64) -> Result<()> {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        let mut left = size;
+        while left > 0 {
+            let length = left.min(CHUNK_SIZE as u64) as usize;
+            if self.fill == Fill::Random {
+                self.random
+                    .fill(&mut buffer[..length])
+                    .map_err(|_| Error::other("cannot generate random data"))?;
+            }
+            file.write_all(&buffer[..length])?;
+            left -= length as u64;
+        }
+        file.sync_all()
+    }
+
+    /// Tell, once per filesystem, that overwriting is not
+    /// reliable when the filesystem does not write in place.
+    fn warn_copy_on_write(&mut self, file: &File, device: u64, path: &Path) {
+        if let Some(kind) = copy_on_write(file) {
+            if self.warned.insert(device) {
+                self.warnings.push(format!(
+                    "{} is on {}, that does not overwrite in place: shredding may leave its data on disk",
+                    quote(path), kind
+                ));
+            }
+        }
+    }
+}
+
+impl RemoveAction for Shred {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.shred_file(dir, name, path)
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, _path: &Path) -> Result<()> {
+        dir.remove_dir(name)
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.shred_tree(dir, name, path)
+    }
+
+    fn finish(&mut self, log: &mut Option<StatusLogger>) -> Result<()> {
+        for warning in self.warnings.drain(..) {
+            logger::add_warning_log(log, &warning);
+        }
+        Ok(())
+    }
+}
+
+/// Open name for writing, and tell whether its permissions
+/// changed. Like shred -f, the owner is given write permission
+/// when it is missing; None if the file still cannot be
+/// written, rm would just unlink it.
+fn open_for_writing(dir: &DirFd, name: &OsStr, stat: &Stat) -> Result<Option<(File, bool)>> {
+    match dir.open_file_write(name) {
+        Ok(file) => Ok(Some((file, false))),
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            if dir
+                .set_permissions(name, stat.permissions() | libc::S_IWUSR)
+                .is_err()
+            {
+                return Ok(None);
+            }
+            match dir.open_file_write(name) {
+                Ok(file) => Ok(Some((file, true))),
+                Err(err) => {
+                    let _ = dir.set_permissions(name, stat.permissions());
+                    Err(err)
+                }
+            }
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Rename name to `000...`, then to one `0` less at each
+/// step, syncing the directory every time. Names already
+/// taken are skipped, the rename never replaces them.
+/// Return the final name.
+fn scrub_name(dir: &DirFd, name: &OsStr) -> Result<OsString> {
+    let mut current = name.to_os_string();
+    for length in (1..=name.len()).rev() {
+        let candidate = OsString::from("0".repeat(length));
+        if candidate == current {
+            continue;
+        }
+        match dir.rename_in_no_replace(&current, &candidate) {
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            result => result?,
+        }
+        dir.sync()?;
+        current = candidate;
+    }
+    Ok(current)
+}
+
+/// The name of the filesystem holding file,
+/// if it is known not to write in place.
+#[cfg(target_os = "linux")]
+#[allow(clippy::unnecessary_cast)]
+fn copy_on_write(file: &File) -> Option<&'static str> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } == -1 {
+        return None;
+    }
+    match stat.f_type as u32 {
+        0x9123_683E => Some("btrfs"),
+        0x2FC1_2FC1 => Some("zfs"),
+        0xCA45_1A4E => Some("bcachefs"),
+        0xF2F5_2010 => Some("f2fs"),
+        0x3434 => Some("nilfs2"),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_on_write(_file: &File) -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{self, create_dir_all, hard_link, OpenOptions};
+    use std::io::Read;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_overwrite() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("file");
+        let content = vec![b'x'; CHUNK_SIZE + 100];
+        fs::write(&path, &content).unwrap();
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        Shred::new(1)
+            .with_fill(Fill::Zeros)
+            .overwrite(&mut file, content.len() as u64)
+            .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![0; content.len()]);
+
+        Shred::new(1)
+            .overwrite(&mut file, content.len() as u64)
+            .unwrap();
+        let random = fs::read(&path).unwrap();
+        assert_eq!(random.len(), content.len());
+        assert!(random.iter().any(|b| *b != 0));
+    }
+
+    #[test]
+    fn test_shred() {
+        let root = TempDir::new().unwrap();
+        let dir_path = root.path().join("dir");
+        create_dir_all(&dir_path).unwrap();
+        let path = dir_path.join("secret");
+        fs::write(&path, "password").unwrap();
+        // an open descriptor shows what happened to the data
+        let mut witness = File::open(&path).unwrap();
+        fs::write(dir_path.join("0"), "taken").unwrap();
+
+        let dir = DirFd::open(&dir_path).unwrap();
+        let mut action = Shred::new(2);
+        action
+            .remove_file(&dir, OsStr::new("secret"), &path)
+            .unwrap();
+        assert!(!path.exists());
+        let mut content = Vec::new();
+        witness.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"");
+        let names: Vec<OsString> = dir.entries().unwrap();
+        assert_eq!(names, vec![OsString::from("0")]);
+        assert_eq!(fs::read(dir_path.join("0")).unwrap(), b"taken");
+
+        let mut action = Shred::new(1);
+        assert!(action
+            .remove_file(
+                &DirFd::open(root.path()).unwrap(),
+                OsStr::new("dir"),
+                &dir_path
+            )
+            .is_err());
+        let root_dir = DirFd::open(root.path()).unwrap();
+        action
+            .remove_dir_all(&root_dir, OsStr::new("dir"), &dir_path)
+            .unwrap();
+        assert!(!dir_path.exists());
+    }
+
+    #[test]
+    fn test_hard_links() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("secret");
+        fs::write(&path, "password").unwrap();
+        let other = root.path().join("other");
+        hard_link(&path, &other).unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        let mut action = Shred::new(1);
+        action
+            .remove_file(&dir, OsStr::new("secret"), &path)
+            .unwrap();
+        assert!(!path.exists());
+        // the data is still reachable, it is not overwritten
+        assert_eq!(fs::read(&other).unwrap(), b"password");
+        assert_eq!(action.warnings.len(), 1);
+        assert!(action.warnings[0].contains("other hard links"));
+        action.finish(&mut None).unwrap();
+        assert!(action.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let root = TempDir::new().unwrap();
+        let path = root.path().join("secret");
+        fs::write(&path, "password").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o400)).unwrap();
+        let mut witness = File::open(&path).unwrap();
+
+        let dir = DirFd::open(root.path()).unwrap();
+        Shred::new(1)
+            .remove_file(&dir, OsStr::new("secret"), &path)
+            .unwrap();
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+        let mut content = Vec::new();
+        witness.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"");
+    }
+}
@@ -0,0 +1,377 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, DirBuilder};
+use std::io::{Error, ErrorKind, Result, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use super::dir_fd::DirFd;
+use super::failures::quote;
+use super::logger::{self, StatusLogger};
+use super::paths::{absolute, data_home, url_escape};
+use super::remove_action::RemoveAction;
+
+const INFO_EXTENSION: &str = ".trashinfo";
+
+/// Move entries to the freedesktop.org trash instead of
+/// unlinking them, so that file managers can restore them.
+/// An entry goes to the home trash when it is on the same
+/// filesystem, otherwise to `$topdir/.Trash/$uid` when the
+/// administrator created `$topdir/.Trash` with the sticky
+/// bit, or to `$topdir/.Trash-$uid`, where topdir is the
+/// mount point holding the entry. Trash directories are
+/// only created when an entry is moved there.
+pub struct Trash {
+    home: PathBuf,
+    // trash directories already in use, by device
+    trashes: Vec<(u64, TrashDir)>,
+    /// reported by finish
+    warnings: Vec<String>,
+}
+
+struct TrashDir {
+    files: DirFd,
+    info: DirFd,
+    // the mount point, None for the home trash
+    topdir: Option<PathBuf>,
+}
+
+impl Trash {
+    /// Use `$XDG_DATA_HOME/Trash` as home trash,
+    /// or `$HOME/.local/share/Trash`.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_home(data_home()?.join("Trash")))
+    }
+
+    /// Use home as home trash.
+    pub fn with_home<P: AsRef<Path>>(home: P) -> Self {
+        Self {
+            home: home.as_ref().to_path_buf(),
+            trashes: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn trash(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        let dev = dir.metadata(name)?.dev();
+        let original = absolute(path)?;
+        let trash = self.trash_dir(dev, &original)?;
+        let info_path = match &trash.topdir {
+            Some(topdir) => original.strip_prefix(topdir).unwrap_or(&original),
+            None => &original,
+        };
+
+        let (trash_name, info_name, mut info_file) = trash.reserve(name)?;
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            url_escape(info_path.as_os_str()),
+            Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+        let result = info_file
+            .write_all(info.as_bytes())
+            .and_then(|_| dir.rename_to(name, &trash.files, &trash_name));
+        if result.is_err() {
+            let _ = trash.info.remove_file(&info_name);
+        }
+        result
+    }
+
+    /// The trash for entries on device dev,
+    /// created if needed.
+    fn trash_dir(&mut self, dev: u64, original: &Path) -> Result<&TrashDir> {
+        let index = match self.trashes.iter().position(|(d, _)| *d == dev) {
+            Some(index) => index,
+            None => {
+                let trash = self.find_trash_dir(dev, original)?;
+                self.trashes.push((dev, trash));
+                self.trashes.len() - 1
+            }
+        };
+        Ok(&self.trashes[index].1)
+    }
+
+    fn find_trash_dir(&mut self, dev: u64, original: &Path) -> Result<TrashDir> {
+        if existing_ancestor(&self.home)?.dev() == dev {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&self.home)?;
+            let root = DirFd::open(&self.home)?;
+            return TrashDir::open(&root, None);
+        }
+
+        let topdir = mount_point(original, dev)?;
+        topdir_trash(&DirFd::open(&topdir)?, topdir, &mut self.warnings)
+    }
+}
+
+/// The trash of the filesystem whose mount point is
+/// topdir, opened as top: `.Trash/$uid` if `.Trash` is
+/// a real directory with the sticky bit, `.Trash-$uid`
+/// otherwise. Both must be real directories owned by the
+/// user, and are checked before anything is created.
+/// Like the specification asks, a `.Trash` that fails
+/// the checks is reported in warnings and not used.
+fn topdir_trash(top: &DirFd, topdir: PathBuf, warnings: &mut Vec<String>) -> Result<TrashDir> {
+    let uid = unsafe { libc::getuid() };
+    let shared = OsStr::new(".Trash");
+    match top.metadata(shared) {
+        Ok(meta) if meta.is_dir() && meta.is_sticky() => {
+            let trash = top
+                .open_dir(shared)
+                .and_then(|shared| trash_root(&shared, OsStr::new(&uid.to_string()), uid))
+                .and_then(|root| TrashDir::open(&root, Some(topdir.clone())));
+            match trash {
+                Ok(trash) => return Ok(trash),
+                Err(err) => warnings.push(format!(
+                    "{} is not used: {}",
+                    quote(&topdir.join(shared).join(uid.to_string())),
+                    err
+                )),
+            }
+        }
+        Ok(_) => warnings.push(format!(
+            "{} is not used: it is not a directory with the sticky bit",
+            quote(&topdir.join(shared))
+        )),
+        Err(err) if err.kind() == ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+
+    let root = trash_root(top, OsStr::new(&format!(".Trash-{}", uid)), uid)?;
+    TrashDir::open(&root, Some(topdir))
+}
+
+/// Open the trash directory name in parent, created
+/// if missing. It must be a directory, not a symbolic
+/// link, owned by uid and on the same filesystem.
+fn trash_root(parent: &DirFd, name: &OsStr, uid: u32) -> Result<DirFd> {
+    let meta = match parent.metadata(name) {
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            parent.create_dir(name, 0o700)?;
+            parent.metadata(name)?
+        }
+        meta => meta?,
+    };
+    let parent_dev = parent.status()?.dev();
+    if !meta.is_dir() || meta.uid() != uid || meta.dev() != parent_dev {
+        let msg = format!("cannot use {} as trash", quote(Path::new(name)));
+        return Err(Error::new(ErrorKind::PermissionDenied, msg));
+    }
+    parent.open_dir(name)
+}
+
+impl TrashDir {
+    /// Open, or create, the files and info
+    /// directories of the trash root.
+    fn open(root: &DirFd, topdir: Option<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            files: open_or_create_dir(root, OsStr::new("files"))?,
+            info: open_or_create_dir(root, OsStr::new("info"))?,
+            topdir,
+        })
+    }
+
+    /// Find a free name for name, like `name.2`, and create
+    /// its info file, so that no other process can take it.
+    fn reserve(&self, name: &OsStr) -> Result<(OsString, OsString, fs::File)> {
+        for count in 1.. {
+            let mut trash_name = name.to_os_string();
+            if count > 1 {
+                trash_name.push(format!(".{}", count));
+            }
+            match self.files.metadata(&trash_name) {
+                Ok(_) => continue,
+                Err(err) if err.kind() == ErrorKind::NotFound => (),
+                Err(err) => return Err(err),
+            }
+            let mut info_name = trash_name.clone();
+            info_name.push(INFO_EXTENSION);
+            match self.info.create_file(&info_name, 0o600) {
+                Ok(file) => return Ok((trash_name, info_name, file)),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl RemoveAction for Trash {
+    fn remove_file(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.trash(dir, name, path)
+    }
+
+    fn remove_dir(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.trash(dir, name, path)
+    }
+
+    fn remove_dir_all(&mut self, dir: &DirFd, name: &OsStr, path: &Path) -> Result<()> {
+        self.trash(dir, name, path)
+    }
+
+    fn keeps_trees(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self, log: &mut Option<StatusLogger>) -> Result<()> {
+        for warning in self.warnings.drain(..) {
+            logger::add_warning_log(log, &warning);
+        }
+        Ok(())
+    }
+}
+
+/// Open the sub directory name, created if missing,
+/// never following a symbolic link.
+fn open_or_create_dir(parent: &DirFd, name: &OsStr) -> Result<DirFd> {
+    match parent.create_dir(name, 0o700) {
+        Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(err),
+        _ => (),
+    }
+    parent.open_dir(name)
+}
+
+/// The status of path, or of its closest
+/// ancestor when it does not exist yet.
+fn existing_ancestor(path: &Path) -> Result<fs::Metadata> {
+    let mut current = path;
+    loop {
+        match fs::metadata(current) {
+            Err(err) if err.kind() == ErrorKind::NotFound => match current.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => current = parent,
+                _ => return Err(err),
+            },
+            meta => return meta,
+        }
+    }
+}
+
+/// The top directory of the filesystem holding
+/// path, that is on device dev.
+fn mount_point(path: &Path, dev: u64) -> Result<PathBuf> {
+    let mut topdir = path;
+    while let Some(parent) = topdir.parent() {
+        if fs::metadata(parent)?.dev() != dev {
+            break;
+        }
+        topdir = parent;
+    }
+    Ok(topdir.to_path_buf())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::fs::{create_dir, read_to_string, set_permissions, File, Permissions};
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_trash() {
+        let root = TempDir::new().unwrap();
+        let home = root.path().join("Trash");
+        let mut trash = Trash::with_home(&home);
+        // the trash is only created when used
+        assert!(!home.exists());
+
+        let dir = DirFd::open(root.path()).unwrap();
+        let path = root.path().join("file name");
+        for _ in 0..2 {
+            File::create(&path).unwrap();
+            trash
+                .remove_file(&dir, OsStr::new("file name"), &path)
+                .unwrap();
+            assert!(!path.exists());
+        }
+        create_dir(root.path().join("dir")).unwrap();
+        let dir_path = root.path().join("dir");
+        trash
+            .remove_dir_all(&dir, OsStr::new("dir"), &dir_path)
+            .unwrap();
+
+        assert!(home.join("files").join("file name").exists());
+        assert!(home.join("files").join("file name.2").exists());
+        assert!(home.join("files").join("dir").is_dir());
+
+        let info = read_to_string(home.join("info").join("file name.2.trashinfo")).unwrap();
+        let original = fs::canonicalize(root.path()).unwrap().join("file name");
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next(),
+            Some(format!("Path={}", url_escape(original.as_os_str())).as_str())
+        );
+        assert!(lines.next().unwrap().starts_with("DeletionDate="));
+
+        // a missing file leaves no info file behind
+        let missing = root.path().join("missing");
+        assert!(trash
+            .remove_file(&dir, OsStr::new("missing"), &missing)
+            .is_err());
+        assert!(!home.join("info").join("missing.trashinfo").exists());
+    }
+
+    #[test]
+    fn test_topdir_trash() {
+        let uid = unsafe { libc::getuid() };
+        let root = TempDir::new().unwrap();
+        let top = DirFd::open(root.path()).unwrap();
+        let private = root.path().join(format!(".Trash-{}", uid));
+
+        // .Trash without the sticky bit is not used
+        let shared = root.path().join(".Trash");
+        create_dir(&shared).unwrap();
+        set_permissions(&shared, Permissions::from_mode(0o777)).unwrap();
+        let mut warnings = Vec::new();
+        topdir_trash(&top, root.path().to_path_buf(), &mut warnings).unwrap();
+        assert!(private.join("files").is_dir());
+        assert!(private.join("info").is_dir());
+        assert!(!shared.join(uid.to_string()).exists());
+        assert_eq!(
+            warnings,
+            vec![format!(
+                "{} is not used: it is not a directory with the sticky bit",
+                quote(&shared)
+            )]
+        );
+
+        set_permissions(&shared, Permissions::from_mode(0o1777)).unwrap();
+        let mut warnings = Vec::new();
+        topdir_trash(&top, root.path().to_path_buf(), &mut warnings).unwrap();
+        assert!(shared.join(uid.to_string()).join("files").is_dir());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_topdir_trash_refuses_links() {
+        let uid = unsafe { libc::getuid() };
+        let root = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        symlink(outside.path(), root.path().join(format!(".Trash-{}", uid))).unwrap();
+
+        let top = DirFd::open(root.path()).unwrap();
+        let mut warnings = Vec::new();
+        assert!(topdir_trash(&top, root.path().to_path_buf(), &mut warnings).is_err());
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+
+        // a link in place of the per user directory
+        // is not used either, .Trash-$uid is instead
+        fs::remove_file(root.path().join(format!(".Trash-{}", uid))).unwrap();
+        let shared = root.path().join(".Trash");
+        create_dir(&shared).unwrap();
+        set_permissions(&shared, Permissions::from_mode(0o1777)).unwrap();
+        symlink(outside.path(), shared.join(uid.to_string())).unwrap();
+        topdir_trash(&top, root.path().to_path_buf(), &mut warnings).unwrap();
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+        let private = root.path().join(format!(".Trash-{}", uid));
+        assert!(private.join("files").is_dir());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&format!(
+            "{} is not used: ",
+            quote(&shared.join(uid.to_string()))
+        )));
+    }
+}
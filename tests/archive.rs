@@ -0,0 +1,44 @@
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read_dir, write, File};
+use tempfile::TempDir;
+
+mod common;
+use common::{run_rmd, stderr};
+
+/*
+    Files selected by an automatic remover are stored
+    in the --archive-to archive, with their path relative
+    to the scanned directory, and removed afterwards.
+*/
+#[test]
+fn test_archive_to() {
+    let temp_root = TempDir::new().unwrap();
+    let scan = temp_root.path().join("scan");
+    create_dir_all(scan.join("logs")).unwrap();
+    let small = scan.join("logs").join("small.log");
+    write(&small, "expired").unwrap();
+    let large = scan.join("logs").join("large.log");
+    write(&large, vec![b'x'; 2000]).unwrap();
+    let archive = temp_root.path().join("expired.zip");
+
+    let output = run_rmd(
+        &[
+            OsStr::new("--smaller"),
+            OsStr::new("1kb"),
+            OsStr::new("--archive-to"),
+            archive.as_os_str(),
+            scan.as_os_str(),
+        ],
+        "",
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!small.exists());
+    assert!(large.exists());
+    assert!(archive.exists());
+    assert_eq!(read_dir(temp_root.path()).unwrap().count(), 2);
+
+    let mut archive = zip::ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+    assert_eq!(archive.len(), 1);
+    assert!(archive.by_name("logs/small.log").is_ok());
+}
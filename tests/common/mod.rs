@@ -0,0 +1,41 @@
+//! Helpers shared by the integration tests,
+//! each test uses only some of them.
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// The rmd binary built for the tests, called with args.
+pub fn rmd<S: AsRef<OsStr>>(args: &[S]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rmd"));
+    command.args(args);
+    command
+}
+
+/// Run rmd with args, writing input to its standard input.
+pub fn run_rmd<S: AsRef<OsStr>>(args: &[S], input: &str) -> Output {
+    run(&mut rmd(args), input)
+}
+
+/// Run command, writing input to its standard input.
+pub fn run(command: &mut Command, input: &str) -> Output {
+    let mut process = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    process
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    process.wait_with_output().unwrap()
+}
+
+/// What the run wrote to its standard error.
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
@@ -0,0 +1,52 @@
+use std::ffi::OsStr;
+use std::fs::{create_dir, File};
+use std::io::Write;
+use tempfile::TempDir;
+
+mod common;
+use common::run_rmd;
+
+/*
+    A dry run must report every file that would be
+    removed, and the directory left empty by --clean,
+    with the space it would free, even without -v,
+    while leaving all of them in place.
+*/
+#[test]
+fn test_dry_run() {
+    let temp_root = TempDir::new().unwrap();
+    let sub_dir = temp_root.path().join("sub");
+    create_dir(&sub_dir).unwrap();
+    let small = sub_dir.join("small");
+    File::create(&small).unwrap().write_all(&[0; 10]).unwrap();
+    let large = temp_root.path().join("large");
+    File::create(&large).unwrap().write_all(&[0; 1000]).unwrap();
+
+    let output = run_rmd(
+        &[
+            OsStr::new("--smaller"),
+            OsStr::new("100b"),
+            OsStr::new("-nc"),
+            temp_root.path().as_os_str(),
+        ],
+        "",
+    );
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!("{:?}", small),
+            format!("{:?}", sub_dir),
+            "Final job statistics:".to_owned(),
+            "1 directory would be removed".to_owned(),
+            "1 file would be removed".to_owned(),
+            "10.00 b would be freed".to_owned(),
+        ]
+    );
+
+    assert!(small.exists());
+    assert!(large.exists());
+}
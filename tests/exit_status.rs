@@ -0,0 +1,66 @@
+use std::fs::{create_dir, File};
+use tempfile::TempDir;
+
+mod common;
+use common::{run_rmd, stderr};
+
+/*
+    Exit statuses and diagnostics follow rm: 0 on
+    success, 1 on failure, 2 on a bad command line,
+    and -f ignores operands that do not exist.
+*/
+#[test]
+fn test_exit_status() {
+    let temp_root = TempDir::new().unwrap();
+    let missing = temp_root.path().join("missing");
+    let missing = missing.to_str().unwrap();
+    let dir = temp_root.path().join("dir");
+    create_dir(&dir).unwrap();
+    let dir = dir.to_str().unwrap();
+    let file = temp_root.path().join("file");
+    File::create(&file).unwrap();
+
+    let output = run_rmd(&[missing], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "rmd: cannot remove '{}': No such file or directory\n",
+            missing
+        )
+    );
+
+    // every operand is tried, like rm
+    let other = temp_root.path().join("other");
+    File::create(&other).unwrap();
+    let output = run_rmd(&[missing, dir, other.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "rmd: cannot remove '{}': No such file or directory\nrmd: cannot remove '{}': Is a directory\n",
+            missing, dir
+        )
+    );
+    assert!(!other.exists());
+
+    let output = run_rmd(&["-f", missing, file.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "");
+    assert!(!file.exists());
+
+    let output = run_rmd(&["-f", missing, dir], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!("rmd: cannot remove '{}': Is a directory\n", dir)
+    );
+
+    let output = run_rmd::<&str>(&[], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("rmd: missing operand\n"));
+    assert_eq!(run_rmd(&["-f"], "").status.code(), Some(0));
+
+    let output = run_rmd(&["--no-such-option"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+use common::run_rmd;
+
+/*
+    -I asks a single question, after the whole
+    removal is planned, and only when it is
+    recursive or removes more than three files.
+*/
+#[test]
+fn test_interactive_once() {
+    let temp_root = TempDir::new().unwrap();
+    let root = temp_root.path();
+    for name in &["a", "b", "c"] {
+        fs::write(root.join(name), "same").unwrap();
+    }
+    let root_arg = root.to_str().unwrap();
+
+    let output = run_rmd(&["-I", "--duplicates", root_arg], "n\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "  2 in '{}'\nremove 2 files (8.00 b) from 1 root? [y/N]\n",
+            root_arg
+        )
+    );
+    assert_eq!(count(root), 3);
+
+    let output = run_rmd(&["--interactive=once", "--duplicates", root_arg], "y\n");
+    assert!(output.status.success());
+    assert_eq!(count(root), 1);
+
+    fs::write(root.join("d"), "").unwrap();
+    let files: Vec<String> = fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_str().unwrap().to_owned())
+        .collect();
+    let mut args = vec!["-I"];
+    args.extend(files.iter().map(String::as_str));
+    let output = run_rmd(&args, "");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(count(root), 0);
+}
+
+fn count(root: &Path) -> usize {
+    fs::read_dir(root).unwrap().count()
+}
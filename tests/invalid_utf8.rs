@@ -0,0 +1,78 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use tempfile::TempDir;
+
+mod common;
+use common::run_rmd;
+
+/*
+    File names are bytes, not UTF-8: names from
+    old Latin-1 shares are removed like any other
+    and messages show them escaped like rm does.
+*/
+#[test]
+fn test_remove_operands() {
+    let temp_root = TempDir::new().unwrap();
+    let root = temp_root.path();
+    let cafe = root.join(name(b"caf\xe9"));
+    let missing = root.join(name(b"missing\xff"));
+    fs::write(&cafe, "content").unwrap();
+
+    let output = run_rmd(&[cafe.as_os_str(), missing.as_os_str()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!cafe.exists());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr,
+        format!(
+            "rmd: cannot remove '{}/missing'$'\\377': No such file or directory\n",
+            root.display()
+        )
+    );
+}
+
+#[test]
+fn test_walk() {
+    let temp_root = TempDir::new().unwrap();
+    let root = temp_root.path();
+    let tree = root.join(name(b"d\xfcr"));
+    fs::create_dir(&tree).unwrap();
+    for file in &[&b"caf\xe9"[..], b"na\xefve", b"kept.t\xf8t"] {
+        fs::write(tree.join(name(file)), "same").unwrap();
+    }
+
+    let output = run_rmd(
+        &[
+            tree.as_os_str(),
+            OsStr::new("--duplicates"),
+            OsStr::new("--ignore-extensions"),
+            name(b"t\xf8t"),
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_dir(&tree).unwrap().count(), 2);
+    assert!(tree.join(name(b"kept.t\xf8t")).exists());
+
+    let output = run_rmd(
+        &[OsStr::new("-i"), OsStr::new("-r"), tree.as_os_str()],
+        "y\nn\nn\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let tree_arg = format!("'{}/d'$'\\374''r", root.display());
+    assert!(stdout.starts_with(&format!("descend into directory {}'? [y/N]\n", tree_arg)));
+    assert!(stdout.contains(&format!(
+        "remove regular file {}/kept.t'$'\\370''t'? [y/N]\n",
+        tree_arg
+    )));
+
+    let output = run_rmd(&[OsStr::new("-r"), tree.as_os_str()], "");
+    assert!(output.status.success());
+    assert!(!tree.exists());
+}
+
+fn name(bytes: &[u8]) -> &OsStr {
+    OsStr::from_bytes(bytes)
+}
@@ -0,0 +1,101 @@
+use std::fs::{create_dir, create_dir_all, read_dir, File};
+use std::io::Write;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+use common::{rmd, run};
+
+/*
+    Files removed with --journal are kept in a staging
+    area, restore puts them back and purge deletes
+    the staging areas.
+*/
+#[test]
+fn test_journal_restore_and_purge() {
+    let temp_root = TempDir::new().unwrap();
+    let data_home = temp_root.path().join("data");
+    let work = temp_root.path().join("work");
+    create_dir(&work).unwrap();
+    let small = work.join("small");
+    File::create(&small).unwrap().write_all(&[0; 10]).unwrap();
+    let other = work.join("other");
+    File::create(&other).unwrap().write_all(&[1; 10]).unwrap();
+
+    let output = run(
+        rmd(&["--journal", "--smaller", "1kb", to_str(&work)]).env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+    assert!(output.status.success());
+    assert!(!small.exists());
+    assert!(!other.exists());
+
+    let base = data_home.join("rmd").join("journal");
+    let areas: Vec<_> = read_dir(&base)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(areas.len(), 1);
+
+    let output = run(
+        rmd(&["restore", to_str(&areas[0].join("journal"))]).env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+    assert!(output.status.success());
+    assert!(small.exists());
+    assert!(other.exists());
+    assert!(!areas[0].exists());
+
+    let output = run(
+        rmd(&["--journal", to_str(&small)]).env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+    assert!(output.status.success());
+    assert!(!small.exists());
+    let output = run(
+        rmd(&["purge", "--older", "1d"]).env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(read_dir(&base).unwrap().count(), 1);
+    let output = run(rmd(&["purge"]).env("XDG_DATA_HOME", &data_home), "");
+    assert!(output.status.success());
+    assert_eq!(read_dir(&base).unwrap().count(), 0);
+}
+
+/*
+    Directories emptied by --clean are restored
+    together with their content.
+*/
+#[test]
+fn test_restore_after_clean() {
+    let temp_root = TempDir::new().unwrap();
+    let data_home = temp_root.path().join("data");
+    let work = temp_root.path().join("work");
+    let deep = work.join("a").join("b");
+    create_dir_all(&deep).unwrap();
+    let small = deep.join("small");
+    File::create(&small).unwrap().write_all(&[0; 10]).unwrap();
+
+    let output = run(
+        rmd(&["--journal", "--clean", "--smaller", "1kb", to_str(&work)])
+            .env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+    assert!(output.status.success());
+    assert!(!work.join("a").exists());
+
+    let base = data_home.join("rmd").join("journal");
+    let area = read_dir(&base).unwrap().next().unwrap().unwrap().path();
+    let output = run(
+        rmd(&["restore", to_str(&area)]).env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(small.exists());
+    assert!(!area.exists());
+}
+
+fn to_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
@@ -0,0 +1,36 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use tempfile::TempDir;
+
+mod common;
+use common::{run_rmd, stderr};
+
+/*
+    With --keep-going a missing file does not stop
+    the removal of the following ones: every error is
+    reported at the end and the exit status is non-zero.
+*/
+#[test]
+fn test_keep_going() {
+    let temp_root = TempDir::new().unwrap();
+    let missing = temp_root.path().join("missing");
+    let file = temp_root.path().join("file");
+    File::create(&file).unwrap();
+
+    let output = run_rmd(
+        &[
+            OsStr::new("--keep-going"),
+            missing.as_os_str(),
+            file.as_os_str(),
+        ],
+        "",
+    );
+
+    assert!(!output.status.success());
+    assert!(!file.exists());
+    let diagnostic = format!(
+        "rmd: cannot remove '{}': No such file or directory\n",
+        missing.display()
+    );
+    assert!(stderr(&output).ends_with(&(diagnostic + "rmd: 1 error: NotFound (1)\n")));
+}
@@ -0,0 +1,71 @@
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read_to_string, write};
+use tempfile::TempDir;
+
+mod common;
+use common::{run_rmd, stderr};
+
+/*
+    Files selected by an automatic remover are moved
+    into the --move-to directory, keeping their path
+    relative to the scanned directory.
+*/
+#[test]
+fn test_move_to() {
+    let temp_root = TempDir::new().unwrap();
+    let scan = temp_root.path().join("scan");
+    let target = temp_root.path().join("quarantine");
+    create_dir_all(scan.join("a").join("b")).unwrap();
+    let small = scan.join("a").join("b").join("small");
+    write(&small, "junk").unwrap();
+    let large = scan.join("a").join("large");
+    write(&large, vec![b'x'; 2000]).unwrap();
+
+    let output = run_rmd(
+        &[
+            OsStr::new("--smaller"),
+            OsStr::new("1kb"),
+            OsStr::new("--move-to"),
+            target.as_os_str(),
+            OsStr::new("-c"),
+            scan.as_os_str(),
+        ],
+        "",
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!small.exists());
+    assert!(!scan.join("a").join("b").exists());
+    assert!(large.exists());
+    let moved = target.join("a").join("b").join("small");
+    assert_eq!(read_to_string(moved).unwrap(), "junk");
+}
+
+/*
+    A --move-to directory inside the scanned directory
+    is not walked, so a second run does not move the
+    quarantine into itself.
+*/
+#[test]
+fn test_move_to_inside_root() {
+    let temp_root = TempDir::new().unwrap();
+    let scan = temp_root.path().join("scan");
+    let target = scan.join("quarantine");
+    create_dir_all(scan.join("a")).unwrap();
+    write(scan.join("a").join("file"), "junk").unwrap();
+    let args = [
+        OsStr::new("--larger"),
+        OsStr::new("0b"),
+        OsStr::new("--move-to"),
+        target.as_os_str(),
+        scan.as_os_str(),
+    ];
+
+    for _ in 0..2 {
+        let output = run_rmd(&args, "");
+        assert!(output.status.success(), "{}", stderr(&output));
+        let moved = target.join("a").join("file");
+        assert_eq!(read_to_string(moved).unwrap(), "junk");
+        assert!(!target.join("quarantine").exists());
+    }
+}
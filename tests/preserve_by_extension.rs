@@ -1,3 +1,9 @@
+#![allow(
+    clippy::needless_borrow,
+    clippy::needless_borrows_for_generic_args,
+    clippy::unused_io_amount
+)]
+
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::io::Write;
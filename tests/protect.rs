@@ -0,0 +1,72 @@
+use std::fs::{self, create_dir_all};
+use tempfile::TempDir;
+
+mod common;
+use common::{rmd, run, stderr};
+
+/*
+    Paths listed in $XDG_CONFIG_HOME/rmd/protected are
+    neither removed, nor removed with a tree holding them,
+    nor walked into by an automatic removal, that skips
+    them. A protected directory can still be walked when
+    given as operand, like the home directory.
+*/
+#[test]
+fn test_protect() {
+    let temp_root = TempDir::new().unwrap();
+    let config = temp_root.path().join("config");
+    let tree = temp_root.path().join("tree");
+    let keep = tree.join("keep");
+    create_dir_all(config.join("rmd")).unwrap();
+    create_dir_all(&keep).unwrap();
+    fs::write(
+        config.join("rmd").join("protected"),
+        format!("{}\n", keep.display()),
+    )
+    .unwrap();
+    fs::write(keep.join("a"), "same").unwrap();
+    fs::write(keep.join("b"), "same").unwrap();
+    fs::write(tree.join("c"), "other").unwrap();
+    fs::write(tree.join("d"), "other").unwrap();
+    let tree_arg = tree.to_str().unwrap();
+
+    let output = run(rmd(&["-r", tree_arg]).env("XDG_CONFIG_HOME", &config), "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "rmd: refusing to remove '{}': it contains protected path '{}'\n",
+            tree_arg,
+            keep.display()
+        )
+    );
+
+    let output = run(
+        rmd(&["-r", keep.to_str().unwrap()]).env("XDG_CONFIG_HOME", &config),
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(keep.join("a").exists());
+
+    let output = run(
+        rmd(&["-v", "-d", tree_arg]).env("XDG_CONFIG_HOME", &config),
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        stderr(&output),
+        format!("warning: skipping protected path '{}'\n", keep.display())
+    );
+    assert!(keep.join("a").exists() && keep.join("b").exists());
+    assert!(tree.join("c").exists() != tree.join("d").exists());
+
+    let output = run(
+        rmd(&["-d", keep.to_str().unwrap()])
+            .env("XDG_CONFIG_HOME", &config)
+            .env("HOME", &keep),
+        "",
+    );
+    assert!(output.status.success());
+    assert!(keep.exists());
+    assert!(keep.join("a").exists() != keep.join("b").exists());
+}
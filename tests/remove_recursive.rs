@@ -0,0 +1,80 @@
+use std::fs::{self, create_dir_all};
+use tempfile::TempDir;
+
+mod common;
+use common::run_rmd;
+
+/*
+    -r removes a tree one entry at a time: every
+    entry is logged and counted, and -i asks
+    for each of them with the questions of rm -ri.
+*/
+#[test]
+fn test_remove_recursive() {
+    let temp_root = TempDir::new().unwrap();
+    let tree = temp_root.path().join("tree");
+    let sub = tree.join("sub");
+    create_dir_all(&sub).unwrap();
+    fs::write(sub.join("a"), "aaa").unwrap();
+    let tree_arg = tree.to_str().unwrap();
+
+    let output = run_rmd(&["-i", "-r", tree_arg], "y\ny\nn\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let questions: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        questions,
+        vec![
+            format!("descend into directory '{}'? [y/N]", tree_arg),
+            format!("descend into directory '{}'? [y/N]", sub.display()),
+            format!("remove regular file '{}'? [y/N]", sub.join("a").display()),
+        ]
+    );
+    assert!(sub.join("a").exists());
+
+    let output = run_rmd(
+        &["-i", "-r", sub.to_str().unwrap()],
+        "y
+y
+y
+",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let questions: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        questions,
+        vec![
+            format!("descend into directory '{}'? [y/N]", sub.display()),
+            format!("remove regular file '{}'? [y/N]", sub.join("a").display()),
+            format!("remove directory '{}'? [y/N]", sub.display()),
+        ]
+    );
+    assert!(!sub.exists());
+
+    // an empty directory is not descended into
+    create_dir_all(&sub).unwrap();
+    let output = run_rmd(
+        &["-i", "-r", sub.to_str().unwrap()],
+        "y
+",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!("remove directory '{}'? [y/N]\n", sub.display())
+    );
+    assert!(!sub.exists());
+
+    create_dir_all(&sub).unwrap();
+    fs::write(sub.join("a"), "aaa").unwrap();
+
+    fs::write(tree.join("b"), "bb").unwrap();
+    let output = run_rmd(&["-vv", "-r", tree_arg], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("{:?}", sub.join("a"))));
+    assert!(stdout.contains("2 directories removed\n"));
+    assert!(!tree.exists());
+}
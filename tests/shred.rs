@@ -0,0 +1,48 @@
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, hard_link, read, read_dir, write};
+use tempfile::TempDir;
+
+mod common;
+use common::{run_rmd, stderr};
+
+/*
+    With --shred removed files are overwritten
+    and truncated before being unlinked. A file with
+    another hard link is only unlinked: the other
+    link keeps its content.
+*/
+#[test]
+fn test_shred() {
+    let temp_root = TempDir::new().unwrap();
+    let scan = temp_root.path().join("scan");
+    create_dir_all(&scan).unwrap();
+    let secret = scan.join("secret");
+    write(&secret, "password").unwrap();
+    let witness = temp_root.path().join("witness");
+    hard_link(&secret, &witness).unwrap();
+    let other = scan.join("other");
+    write(&other, "other secret").unwrap();
+    let large = scan.join("large");
+    write(&large, vec![b'x'; 2000]).unwrap();
+
+    let output = run_rmd(
+        &[
+            OsStr::new("-v"),
+            OsStr::new("--shred"),
+            OsStr::new("--shred-passes"),
+            OsStr::new("1"),
+            OsStr::new("--smaller"),
+            OsStr::new("1kb"),
+            scan.as_os_str(),
+        ],
+        "",
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!secret.exists());
+    assert!(large.exists());
+    assert!(!other.exists());
+    assert_eq!(read(&witness).unwrap(), b"password");
+    assert!(stderr(&output).contains("other hard links: it is unlinked, not overwritten"));
+    assert_eq!(read_dir(&scan).unwrap().count(), 1);
+}
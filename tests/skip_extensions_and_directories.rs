@@ -1,3 +1,5 @@
+#![allow(clippy::unused_io_amount)]
+
 use std::fs::{create_dir, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -1,3 +1,5 @@
+#![allow(clippy::map_flatten, clippy::unused_io_amount)]
+
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
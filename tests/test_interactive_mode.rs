@@ -1,9 +1,13 @@
-use std::fs::File;
+#![allow(clippy::erasing_op, clippy::map_flatten, clippy::unused_io_amount)]
+
+use std::fs::{create_dir, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use tempfile::TempDir;
 
+mod common;
+
 #[test]
 fn test_interactive_mode() {
     let temp_root = TempDir::new().unwrap();
@@ -48,6 +52,42 @@ fn test_interactive_mode() {
     assert_eq!(lines.len(), small.len());
 }
 
+/*
+    A directory left empty is asked about
+    before --clean removes it.
+*/
+#[test]
+fn test_interactive_clean() {
+    let temp_root = TempDir::new().unwrap();
+    let sub_dir = temp_root.path().join("sub");
+    create_dir(&sub_dir).unwrap();
+    let small = create_file(sub_dir.join("small"), 10);
+    let args = [
+        "--smaller".as_ref(),
+        "100b".as_ref(),
+        "-ic".as_ref(),
+        temp_root.path().as_os_str(),
+    ];
+
+    let output = common::run_rmd(&args, "y\nn\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!("remove regular file '{}'? [y/N]", small.display()),
+            format!("remove directory '{}'? [y/N]", sub_dir.display()),
+        ]
+    );
+    assert!(!small.exists());
+    assert!(sub_dir.exists());
+
+    let output = common::run_rmd(&args, "y\n");
+    assert!(output.status.success());
+    assert!(!sub_dir.exists());
+}
+
 fn remove_answer(count: usize, ans: &str) -> Vec<u8> {
     (0..count)
         .map(|_| ans)
@@ -0,0 +1,44 @@
+use std::ffi::OsStr;
+use std::fs::{create_dir, read_dir, File};
+use tempfile::TempDir;
+
+mod common;
+use common::{rmd, run, stderr};
+
+/*
+    Files and directories removed with --trash
+    are moved in the XDG trash, each with its
+    own info file.
+*/
+#[test]
+fn test_trash() {
+    let temp_root = TempDir::new().unwrap();
+    let data_home = temp_root.path().join("data");
+    let work = temp_root.path().join("work");
+    create_dir(&work).unwrap();
+    let file = work.join("file");
+    File::create(&file).unwrap();
+    let dir = work.join("dir");
+    create_dir(&dir).unwrap();
+    File::create(dir.join("inner")).unwrap();
+
+    let output = run(
+        rmd(&[
+            OsStr::new("--trash"),
+            OsStr::new("-r"),
+            file.as_os_str(),
+            dir.as_os_str(),
+        ])
+        .env("XDG_DATA_HOME", &data_home),
+        "",
+    );
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!file.exists());
+    assert!(!dir.exists());
+
+    let trash = data_home.join("Trash");
+    assert!(trash.join("files").join("file").exists());
+    assert!(trash.join("files").join("dir").join("inner").exists());
+    assert_eq!(read_dir(trash.join("info")).unwrap().count(), 2);
+}